@@ -0,0 +1,156 @@
+//! Android ABIs, the Rust and NDK targets that build for them, and the ELF
+//! check that keeps a binary off a device it can't run on.
+
+use std::fmt;
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::Path;
+
+use crate::device::Device;
+use crate::error::{MonoError, Result};
+
+/// The ABIs the daemon can be built for, as named by `ro.product.cpu.abilist`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum AndroidAbi {
+    Arm64V8a,
+    ArmeabiV7a,
+    X86_64,
+    X86,
+}
+
+impl AndroidAbi {
+    pub const ALL: [AndroidAbi; 4] = [
+        AndroidAbi::Arm64V8a,
+        AndroidAbi::ArmeabiV7a,
+        AndroidAbi::X86_64,
+        AndroidAbi::X86,
+    ];
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|abi| abi.name() == name.trim())
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            AndroidAbi::Arm64V8a => "arm64-v8a",
+            AndroidAbi::ArmeabiV7a => "armeabi-v7a",
+            AndroidAbi::X86_64 => "x86_64",
+            AndroidAbi::X86 => "x86",
+        }
+    }
+
+    /// The `cargo build --target` triple.
+    pub fn rust_target(self) -> &'static str {
+        match self {
+            AndroidAbi::Arm64V8a => "aarch64-linux-android",
+            AndroidAbi::ArmeabiV7a => "armv7-linux-androideabi",
+            AndroidAbi::X86_64 => "x86_64-linux-android",
+            AndroidAbi::X86 => "i686-linux-android",
+        }
+    }
+
+    /// Prefix of the NDK's clang wrappers, which differs from the Rust
+    /// triple for 32-bit ARM.
+    pub fn clang_target(self) -> &'static str {
+        match self {
+            AndroidAbi::ArmeabiV7a => "armv7a-linux-androideabi",
+            abi => abi.rust_target(),
+        }
+    }
+
+    /// `e_machine` of binaries built for this ABI.
+    fn elf_machine(self) -> u16 {
+        match self {
+            AndroidAbi::Arm64V8a => EM_AARCH64,
+            AndroidAbi::ArmeabiV7a => EM_ARM,
+            AndroidAbi::X86_64 => EM_X86_64,
+            AndroidAbi::X86 => EM_386,
+        }
+    }
+
+    fn is_64_bit(self) -> bool {
+        matches!(self, AndroidAbi::Arm64V8a | AndroidAbi::X86_64)
+    }
+}
+
+impl fmt::Display for AndroidAbi {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+const EM_386: u16 = 3;
+const EM_ARM: u16 = 40;
+const EM_X86_64: u16 = 62;
+const EM_AARCH64: u16 = 183;
+
+/// The ABIs in a comma separated `abilist` that we can build for, in the
+/// device's order of preference.
+pub fn parse_abilist(abilist: &str) -> Vec<AndroidAbi> {
+    let mut abis = Vec::new();
+    for abi in abilist.split(',').filter_map(AndroidAbi::from_name) {
+        if !abis.contains(&abi) {
+            abis.push(abi);
+        }
+    }
+    abis
+}
+
+/// The device's preferred ABI that the daemon can be built for, from
+/// `ro.product.cpu.abilist`, or `ro.product.cpu.abi` on devices too old to
+/// have the list.
+pub fn device_abi(device: &Device) -> Result<AndroidAbi> {
+    let mut abilist = device.getprop("ro.product.cpu.abilist")?;
+    if abilist.is_empty() {
+        abilist = device.getprop("ro.product.cpu.abi")?;
+    }
+    parse_abilist(&abilist).first().copied().ok_or_else(|| {
+        MonoError::Other(format!(
+            "Device {} has no supported ABI (reports '{}', supported: {})",
+            device.serial,
+            abilist,
+            AndroidAbi::ALL.map(AndroidAbi::name).join(", ")
+        ))
+    })
+}
+
+/// Reads the ABI an ELF binary was built for from its header; `None` for an
+/// ELF file of some other architecture.
+pub fn elf_abi(path: &Path) -> io::Result<Option<AndroidAbi>> {
+    let mut header = [0u8; 20];
+    File::open(path)?.read_exact(&mut header).map_err(|e| match e.kind() {
+        io::ErrorKind::UnexpectedEof => not_elf(path),
+        _ => e,
+    })?;
+    if &header[..4] != b"\x7fELF" {
+        return Err(not_elf(path));
+    }
+
+    let is_64_bit = header[4] == 2;
+    let machine = match header[5] {
+        2 => u16::from_be_bytes([header[18], header[19]]),
+        _ => u16::from_le_bytes([header[18], header[19]]),
+    };
+    Ok(AndroidAbi::ALL
+        .into_iter()
+        .find(|abi| abi.elf_machine() == machine && abi.is_64_bit() == is_64_bit))
+}
+
+/// Fails unless `path` is an ELF binary for `abi`, so a stale or
+/// cross-built artifact is never pushed to a device that can't run it.
+pub fn check_binary(path: &Path, abi: AndroidAbi) -> Result<()> {
+    let found = elf_abi(path).map_err(|e| MonoError::BuildFailed(format!("Cannot check {}: {}", path.display(), e)))?;
+    match found {
+        Some(found) if found == abi => Ok(()),
+        found => Err(MonoError::BuildFailed(format!(
+            "{} is built for {}, not the device's {}; refusing to push it",
+            path.display(),
+            found.map_or("another architecture", AndroidAbi::name),
+            abi
+        ))),
+    }
+}
+
+fn not_elf(path: &Path) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("{} is not an ELF binary", path.display()))
+}
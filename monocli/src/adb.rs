@@ -0,0 +1,507 @@
+//! Minimal client for the adb server's smart-socket protocol.
+//!
+//! Every request is a 4 digit hex length followed by the payload, answered by
+//! `OKAY` or `FAIL` + hex length + message. Device services are reached by
+//! first switching the connection with `host:transport:<serial>`.
+//! See `system/core/adb/protocol.txt` and `SERVICES.TXT` in AOSP.
+
+use std::env;
+use std::fs::{self, File};
+use std::io::{self, Read, Write};
+use std::net::TcpStream;
+use std::path::Path;
+use std::process::Command;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use crate::cancel;
+
+pub const DEFAULT_SERVER_PORT: u16 = 5037;
+
+/// How often a blocked shell read wakes up to check for a deadline or Ctrl-C.
+const POLL_INTERVAL: Duration = Duration::from_millis(200);
+
+/// Largest payload a single sync `DATA` packet may carry.
+const SYNC_DATA_MAX: usize = 64 * 1024;
+
+// Packet ids of the shell v2 protocol.
+const SHELL_STDOUT: u8 = 1;
+const SHELL_STDERR: u8 = 2;
+const SHELL_EXIT: u8 = 3;
+const SHELL_CLOSE_STDIN: u8 = 4;
+
+/// A device as listed by `host:devices`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeviceEntry {
+    pub serial: String,
+    pub state: String,
+}
+
+/// Result of a command run through the shell service.
+#[derive(Debug, Clone, Default)]
+pub struct ShellOutput {
+    pub stdout: Vec<u8>,
+    pub stderr: Vec<u8>,
+    pub exit_code: i32,
+}
+
+impl ShellOutput {
+    pub fn stdout_lossy(&self) -> String {
+        String::from_utf8_lossy(&self.stdout).to_string()
+    }
+
+    pub fn stderr_lossy(&self) -> String {
+        String::from_utf8_lossy(&self.stderr).to_string()
+    }
+
+    pub fn success(&self) -> bool {
+        self.exit_code == 0
+    }
+}
+
+/// Handle on an adb server. Cheap to clone; every request opens its own socket.
+#[derive(Debug, Clone)]
+pub struct AdbClient {
+    address: String,
+    /// adb binary used to start the server if it isn't running.
+    adb_path: String,
+}
+
+impl Default for AdbClient {
+    fn default() -> Self {
+        Self::from_env()
+    }
+}
+
+impl AdbClient {
+    pub fn new(address: &str) -> Self {
+        AdbClient {
+            address: address.to_string(),
+            adb_path: "adb".to_string(),
+        }
+    }
+
+    /// Honors the same `ANDROID_ADB_SERVER_ADDRESS` / `ANDROID_ADB_SERVER_PORT`
+    /// variables as the adb binary itself.
+    pub fn from_env() -> Self {
+        let host = env::var("ANDROID_ADB_SERVER_ADDRESS").unwrap_or_else(|_| "127.0.0.1".to_string());
+        let port = env::var("ANDROID_ADB_SERVER_PORT")
+            .ok()
+            .and_then(|p| p.parse().ok())
+            .unwrap_or(DEFAULT_SERVER_PORT);
+        Self::new(&format!("{}:{}", host, port))
+    }
+
+    pub fn with_adb_path(mut self, adb_path: &str) -> Self {
+        self.adb_path = adb_path.to_string();
+        self
+    }
+
+    pub fn address(&self) -> &str {
+        &self.address
+    }
+
+    fn connect(&self) -> io::Result<TcpStream> {
+        match TcpStream::connect(&self.address) {
+            Ok(stream) => Ok(stream),
+            Err(e) if e.kind() == io::ErrorKind::ConnectionRefused => {
+                // Same courtesy as the adb binary: start the server if it isn't up yet.
+                // This is the only place we shell out, and it's optional.
+                let started = Command::new(&self.adb_path)
+                    .arg("start-server")
+                    .output()
+                    .map(|o| o.status.success())
+                    .unwrap_or(false);
+                if !started {
+                    return Err(io::Error::new(
+                        io::ErrorKind::ConnectionRefused,
+                        format!("adb server is not running on {}", self.address),
+                    ));
+                }
+                TcpStream::connect(&self.address)
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Sends a host request that answers with a length-prefixed payload.
+    fn host_query(&self, request: &str) -> io::Result<String> {
+        let mut stream = self.connect()?;
+        send_request(&mut stream, request)?;
+        read_status(&mut stream)?;
+        read_length_prefixed(&mut stream)
+    }
+
+    pub fn version(&self) -> io::Result<u32> {
+        let payload = self.host_query("host:version")?;
+        u32::from_str_radix(payload.trim(), 16).map_err(|_| protocol_error("malformed version reply"))
+    }
+
+    pub fn devices(&self) -> io::Result<Vec<DeviceEntry>> {
+        let payload = self.host_query("host:devices")?;
+        Ok(payload
+            .lines()
+            .filter_map(|line| {
+                let (serial, state) = line.split_once('\t')?;
+                Some(DeviceEntry {
+                    serial: serial.to_string(),
+                    state: state.trim().to_string(),
+                })
+            })
+            .collect())
+    }
+
+    /// Forwards a local TCP port, picked by the adb server, to `remote` on the
+    /// device, e.g. `localabstract:monodeamon`, and returns the port. Like
+    /// `adb forward`, the port only listens on the adb server's host.
+    pub fn forward(&self, serial: &str, remote: &str) -> io::Result<u16> {
+        let mut stream = self.connect()?;
+        send_request(&mut stream, &format!("host-serial:{}:forward:tcp:0;{}", serial, remote))?;
+        // One OKAY for the host service, one for the forward itself.
+        read_status(&mut stream)?;
+        read_status(&mut stream)?;
+        let port = read_length_prefixed(&mut stream)?;
+        port.trim().parse().map_err(|_| protocol_error("malformed forward port"))
+    }
+
+    pub fn remove_forward(&self, serial: &str, port: u16) -> io::Result<()> {
+        let mut stream = self.connect()?;
+        send_request(&mut stream, &format!("host-serial:{}:killforward:tcp:{}", serial, port))?;
+        read_status(&mut stream)?;
+        read_status(&mut stream)
+    }
+
+    /// Opens a connection already switched to `serial` and asks for `service`.
+    pub fn open_service(&self, serial: &str, service: &str) -> io::Result<TcpStream> {
+        let mut stream = self.connect()?;
+        send_request(&mut stream, &format!("host:transport:{}", serial))?;
+        read_status(&mut stream)?;
+        send_request(&mut stream, service)?;
+        read_status(&mut stream)?;
+        Ok(stream)
+    }
+
+    /// Runs `cmd` through the shell v2 protocol, handing stdout and stderr
+    /// chunks to the callbacks as they arrive, and returns the exit status.
+    ///
+    /// Falls back to the legacy shell service on devices too old for v2.
+    pub fn shell_stream<O, E>(&self, serial: &str, cmd: &str, on_stdout: O, on_stderr: E) -> io::Result<i32>
+    where
+        O: FnMut(&[u8]),
+        E: FnMut(&[u8]),
+    {
+        self.shell_stream_until(serial, cmd, None, true, on_stdout, on_stderr)
+    }
+
+    /// Like [`shell_stream`](Self::shell_stream), but gives up with a
+    /// `TimedOut` error once `deadline` passes. Either way, Ctrl-C aborts the
+    /// read if `cancellable`; dropping the connection makes adbd kill the
+    /// remote command.
+    fn shell_stream_until<O, E>(
+        &self,
+        serial: &str,
+        cmd: &str,
+        deadline: Option<Instant>,
+        cancellable: bool,
+        mut on_stdout: O,
+        mut on_stderr: E,
+    ) -> io::Result<i32>
+    where
+        O: FnMut(&[u8]),
+        E: FnMut(&[u8]),
+    {
+        let mut stream = match self.open_service(serial, &format!("shell,v2,raw:{}", cmd)) {
+            Ok(stream) => stream,
+            Err(e) if is_server_failure(&e) && !is_device_failure(&e) => {
+                return self.legacy_shell_stream(serial, cmd, deadline, cancellable, on_stdout);
+            }
+            Err(e) => return Err(e),
+        };
+        // Nothing is ever written to stdin, so close it: a command that reads
+        // it, like `cat` or some dumpsys services, would otherwise wait for it.
+        stream.write_all(&[SHELL_CLOSE_STDIN, 0, 0, 0, 0])?;
+        let mut stream = LimitedReader::new(&mut stream, deadline, cancellable);
+
+        let mut header = [0u8; 5];
+        loop {
+            match stream.read_exact(&mut header) {
+                Ok(()) => {}
+                Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => {
+                    return Err(protocol_error("shell closed without reporting an exit status"));
+                }
+                Err(e) => return Err(e),
+            }
+            let id = header[0];
+            let len = u32::from_le_bytes([header[1], header[2], header[3], header[4]]) as usize;
+            let mut data = vec![0u8; len];
+            stream.read_exact(&mut data)?;
+
+            match id {
+                SHELL_STDOUT => on_stdout(&data),
+                SHELL_STDERR => on_stderr(&data),
+                SHELL_EXIT => return Ok(data.first().copied().unwrap_or(0) as i32),
+                _ => {}
+            }
+        }
+    }
+
+    /// Legacy `shell:` merges stderr into stdout and never reports an exit
+    /// code, so we echo one behind a marker and strip it back off.
+    fn legacy_shell_stream<O>(
+        &self,
+        serial: &str,
+        cmd: &str,
+        deadline: Option<Instant>,
+        cancellable: bool,
+        mut on_stdout: O,
+    ) -> io::Result<i32>
+    where
+        O: FnMut(&[u8]),
+    {
+        const MARKER: &str = "\x1fMONO_EXIT:";
+        let mut stream = self.open_service(serial, &format!("shell:{}; echo \"{}$?\"", cmd, MARKER))?;
+        let mut output = Vec::new();
+        LimitedReader::new(&mut stream, deadline, cancellable).read_to_end(&mut output)?;
+
+        let text = String::from_utf8_lossy(&output);
+        match text.rfind(MARKER) {
+            Some(pos) => {
+                on_stdout(&output[..pos]);
+                let code = text[pos + MARKER.len()..].trim().parse().unwrap_or(-1);
+                Ok(code)
+            }
+            None => {
+                on_stdout(&output);
+                Ok(-1)
+            }
+        }
+    }
+
+    pub fn shell(&self, serial: &str, cmd: &str) -> io::Result<ShellOutput> {
+        self.shell_with_timeout(serial, cmd, None)
+    }
+
+    /// Runs `cmd` and collects its output, failing with `TimedOut` if it
+    /// hasn't finished within `timeout`.
+    pub fn shell_with_timeout(&self, serial: &str, cmd: &str, timeout: Option<Duration>) -> io::Result<ShellOutput> {
+        self.collect_shell(serial, cmd, timeout.map(|timeout| Instant::now() + timeout), true)
+    }
+
+    /// Like [`shell_with_timeout`](Self::shell_with_timeout), but Ctrl-C
+    /// doesn't abort it: for the cleanup that has to run once work is
+    /// cancelled, such as rolling back an install.
+    pub fn shell_uncancellable(&self, serial: &str, cmd: &str, timeout: Duration) -> io::Result<ShellOutput> {
+        self.collect_shell(serial, cmd, Some(Instant::now() + timeout), false)
+    }
+
+    fn collect_shell(&self, serial: &str, cmd: &str, deadline: Option<Instant>, cancellable: bool) -> io::Result<ShellOutput> {
+        let mut stdout = Vec::new();
+        let mut stderr = Vec::new();
+        let exit_code = self.shell_stream_until(
+            serial,
+            cmd,
+            deadline,
+            cancellable,
+            |chunk| stdout.extend_from_slice(chunk),
+            |chunk| stderr.extend_from_slice(chunk),
+        )?;
+
+        Ok(ShellOutput {
+            stdout,
+            stderr,
+            exit_code,
+        })
+    }
+
+    /// Copies a local file to `remote` on the device with the given permission bits.
+    pub fn push(&self, serial: &str, local: &Path, remote: &str, mode: u32) -> io::Result<()> {
+        let mut file = File::open(local)?;
+        let mut stream = self.open_service(serial, "sync:")?;
+
+        let spec = format!("{},{}", remote, mode);
+        write_sync_packet(&mut stream, b"SEND", spec.as_bytes())?;
+
+        let mut buffer = vec![0u8; SYNC_DATA_MAX];
+        loop {
+            let read = file.read(&mut buffer)?;
+            if read == 0 {
+                break;
+            }
+            write_sync_packet(&mut stream, b"DATA", &buffer[..read])?;
+        }
+
+        let mtime = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or(Duration::ZERO)
+            .as_secs() as u32;
+        stream.write_all(b"DONE")?;
+        stream.write_all(&mtime.to_le_bytes())?;
+
+        let (id, len) = read_sync_header(&mut stream)?;
+        match &id {
+            b"OKAY" => {
+                stream.write_all(b"QUIT\0\0\0\0")?;
+                Ok(())
+            }
+            b"FAIL" => Err(sync_failure(&mut stream, len)?),
+            _ => Err(protocol_error("unexpected sync reply to SEND")),
+        }
+    }
+
+    /// Copies `remote` from the device into the local file `local`. `local`
+    /// is only created once the device starts sending, and is removed again
+    /// if the transfer fails part way.
+    pub fn pull(&self, serial: &str, remote: &str, local: &Path) -> io::Result<u64> {
+        let mut stream = self.open_service(serial, "sync:")?;
+        write_sync_packet(&mut stream, b"RECV", remote.as_bytes())?;
+
+        let mut file = None;
+        let received = receive_file(&mut stream, local, &mut file);
+        if received.is_err() && file.take().is_some() {
+            let _ = fs::remove_file(local);
+        }
+        let total = received?;
+
+        stream.write_all(b"QUIT\0\0\0\0")?;
+        Ok(total)
+    }
+}
+
+/// Writes the DATA packets of a RECV into `local`, which is created into
+/// `file` on the first DATA or DONE.
+fn receive_file(stream: &mut TcpStream, local: &Path, file: &mut Option<File>) -> io::Result<u64> {
+    let mut total = 0u64;
+    loop {
+        let (id, len) = read_sync_header(stream)?;
+        match &id {
+            b"DATA" => {
+                let mut data = vec![0u8; len as usize];
+                stream.read_exact(&mut data)?;
+                created(local, file)?.write_all(&data)?;
+                total += len as u64;
+            }
+            b"DONE" => {
+                created(local, file)?;
+                return Ok(total);
+            }
+            b"FAIL" => return Err(sync_failure(stream, len)?),
+            _ => return Err(protocol_error("unexpected sync reply to RECV")),
+        }
+    }
+}
+
+fn created<'a>(local: &Path, file: &'a mut Option<File>) -> io::Result<&'a mut File> {
+    match file {
+        Some(file) => Ok(file),
+        None => Ok(file.insert(File::create(local)?)),
+    }
+}
+
+/// Reads from a socket in short slices so a silent device can't block past
+/// `deadline` or, if `cancellable`, ignore Ctrl-C.
+struct LimitedReader<'a> {
+    stream: &'a mut TcpStream,
+    deadline: Option<Instant>,
+    cancellable: bool,
+}
+
+impl<'a> LimitedReader<'a> {
+    fn new(stream: &'a mut TcpStream, deadline: Option<Instant>, cancellable: bool) -> Self {
+        LimitedReader {
+            stream,
+            deadline,
+            cancellable,
+        }
+    }
+}
+
+impl Read for LimitedReader<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            if self.cancellable && cancel::requested() {
+                return Err(cancel::error());
+            }
+            let slice = match self.deadline {
+                Some(deadline) => {
+                    let remaining = deadline.saturating_duration_since(Instant::now());
+                    if remaining.is_zero() {
+                        return Err(io::Error::new(io::ErrorKind::TimedOut, "command timed out"));
+                    }
+                    remaining.min(POLL_INTERVAL)
+                }
+                None => POLL_INTERVAL,
+            };
+            self.stream.set_read_timeout(Some(slice))?;
+            match self.stream.read(buf) {
+                Err(e) if matches!(e.kind(), io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut) => continue,
+                result => return result,
+            }
+        }
+    }
+}
+
+fn send_request(stream: &mut TcpStream, request: &str) -> io::Result<()> {
+    stream.write_all(format!("{:04x}{}", request.len(), request).as_bytes())
+}
+
+/// Reads the 4 byte status; a `FAIL` is turned into an error carrying the server's message.
+fn read_status(stream: &mut TcpStream) -> io::Result<()> {
+    let mut status = [0u8; 4];
+    stream.read_exact(&mut status)?;
+    match &status {
+        b"OKAY" => Ok(()),
+        b"FAIL" => {
+            let message = read_length_prefixed(stream)?;
+            Err(io::Error::other(format!("adb server: {}", message)))
+        }
+        _ => Err(protocol_error("expected OKAY or FAIL")),
+    }
+}
+
+fn read_length_prefixed(stream: &mut TcpStream) -> io::Result<String> {
+    let mut length = [0u8; 4];
+    stream.read_exact(&mut length)?;
+    let length = std::str::from_utf8(&length)
+        .ok()
+        .and_then(|hex| usize::from_str_radix(hex, 16).ok())
+        .ok_or_else(|| protocol_error("malformed length prefix"))?;
+
+    let mut payload = vec![0u8; length];
+    stream.read_exact(&mut payload)?;
+    Ok(String::from_utf8_lossy(&payload).to_string())
+}
+
+fn write_sync_packet(stream: &mut TcpStream, id: &[u8; 4], data: &[u8]) -> io::Result<()> {
+    stream.write_all(id)?;
+    stream.write_all(&(data.len() as u32).to_le_bytes())?;
+    stream.write_all(data)
+}
+
+fn read_sync_header(stream: &mut TcpStream) -> io::Result<([u8; 4], u32)> {
+    let mut header = [0u8; 8];
+    stream.read_exact(&mut header)?;
+    let id = [header[0], header[1], header[2], header[3]];
+    let len = u32::from_le_bytes([header[4], header[5], header[6], header[7]]);
+    Ok((id, len))
+}
+
+fn sync_failure(stream: &mut TcpStream, len: u32) -> io::Result<io::Error> {
+    let mut message = vec![0u8; len as usize];
+    stream.read_exact(&mut message)?;
+    Ok(io::Error::other(format!("adb sync: {}", String::from_utf8_lossy(&message))))
+}
+
+fn protocol_error(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("adb protocol error: {}", message))
+}
+
+fn is_server_failure(error: &io::Error) -> bool {
+    error.to_string().starts_with("adb server:")
+}
+
+/// Failures about the device itself, as opposed to the service being unknown.
+fn is_device_failure(error: &io::Error) -> bool {
+    let message = error.to_string();
+    ["not found", "offline", "unauthorized", "no devices"]
+        .iter()
+        .any(|needle| message.contains(needle))
+}
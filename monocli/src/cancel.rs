@@ -0,0 +1,63 @@
+//! Process-wide cancellation, requested by Ctrl-C.
+//!
+//! Long-running work (shell reads, the capture scheduler) polls
+//! [`requested`] and winds down cleanly instead of being killed mid-write.
+
+use std::error::Error;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::{fmt, io, process};
+
+use crate::log_error;
+
+static CANCELLED: AtomicBool = AtomicBool::new(false);
+
+pub fn request() {
+    CANCELLED.store(true, Ordering::SeqCst);
+}
+
+pub fn requested() -> bool {
+    CANCELLED.load(Ordering::SeqCst)
+}
+
+/// Withdraws a request. monocli exits once cancelled work has wound down,
+/// so only tests, which share the flag across a process, need this.
+pub fn reset() {
+    CANCELLED.store(false, Ordering::SeqCst);
+}
+
+/// What a read gives up with once cancellation is requested. It is not
+/// `Interrupted`, which `read_exact` and friends would retry forever.
+pub fn error() -> io::Error {
+    io::Error::other(CancelledRead)
+}
+
+/// Whether `error` came from [`error`].
+pub fn is_cancelled(error: &io::Error) -> bool {
+    error.get_ref().is_some_and(|inner| inner.is::<CancelledRead>())
+}
+
+#[derive(Debug)]
+struct CancelledRead;
+
+impl fmt::Display for CancelledRead {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("cancelled")
+    }
+}
+
+impl Error for CancelledRead {}
+
+/// The first Ctrl-C asks running work to stop and keep what it has; a second
+/// one exits immediately.
+pub fn install_handler() {
+    let result = ctrlc::set_handler(|| {
+        if requested() {
+            process::exit(130);
+        }
+        log_error("Interrupted, finishing up. Press Ctrl-C again to quit immediately.");
+        request();
+    });
+    if let Err(e) = result {
+        log_error(&format!("Failed to install Ctrl-C handler: {}", e));
+    }
+}
@@ -0,0 +1,129 @@
+//! Server-side chart rendering for the HTML reports.
+//!
+//! Charts are drawn with plotters into SVG and inlined into the page, so a
+//! dashboard needs no JavaScript libraries or network access to render, and
+//! looks the same whenever and wherever it is opened.
+
+use std::error::Error;
+
+use plotters::prelude::*;
+use plotters::style::text_anchor::{HPos, Pos, VPos};
+
+pub const WIDTH: u32 = 360;
+pub const HEIGHT: u32 = 300;
+
+/// One segment of a doughnut chart.
+pub struct Slice<'a> {
+    pub label: &'a str,
+    pub value: f64,
+    pub color: RGBColor,
+}
+
+/// One line of a line chart. `x` is whatever unit the caller's label
+/// formatter understands, e.g. seconds since the epoch.
+pub struct Series {
+    pub label: String,
+    pub color: RGBColor,
+    pub points: Vec<(f64, f64)>,
+}
+
+pub fn doughnut_svg(slices: &[Slice]) -> String {
+    if slices.iter().all(|slice| slice.value <= 0.0) {
+        return no_data_svg();
+    }
+    render(|root| {
+        let center = (WIDTH as i32 / 2, HEIGHT as i32 / 2);
+        let radius = HEIGHT as f64 * 0.32;
+        let sizes: Vec<f64> = slices.iter().map(|slice| slice.value.max(0.0)).collect();
+        let colors: Vec<RGBColor> = slices.iter().map(|slice| slice.color).collect();
+        let labels: Vec<&str> = slices.iter().map(|slice| slice.label).collect();
+
+        let mut pie = Pie::new(&center, &radius, &sizes, &colors, &labels);
+        pie.start_angle(-90.0);
+        pie.donut_hole(radius * 0.5);
+        pie.label_style(("sans-serif", 14).into_font().color(&BLACK));
+        pie.percentages(("sans-serif", 12).into_font().color(&WHITE));
+        root.draw(&pie)?;
+        Ok(())
+    })
+}
+
+pub fn line_svg(series: &[Series], y_desc: &str, x_format: &dyn Fn(&f64) -> String) -> String {
+    let points = series.iter().flat_map(|s| s.points.iter());
+    let (mut x_min, mut x_max, mut y_max) = (f64::MAX, f64::MIN, 0f64);
+    for &(x, y) in points {
+        x_min = x_min.min(x);
+        x_max = x_max.max(x);
+        y_max = y_max.max(y);
+    }
+    if x_min > x_max {
+        return no_data_svg();
+    }
+    if x_min == x_max {
+        x_max = x_min + 1.0;
+    }
+    if y_max <= 0.0 {
+        y_max = 1.0;
+    }
+
+    render(|root| {
+        let mut chart = ChartBuilder::on(root)
+            .margin(10)
+            .x_label_area_size(30)
+            .y_label_area_size(60)
+            .build_cartesian_2d(x_min..x_max, 0f64..y_max * 1.1)?;
+
+        chart
+            .configure_mesh()
+            .x_labels(4)
+            .y_labels(5)
+            .x_label_formatter(x_format)
+            .y_desc(y_desc)
+            .label_style(("sans-serif", 11))
+            .draw()?;
+
+        for line in series {
+            let color = line.color;
+            chart
+                .draw_series(LineSeries::new(line.points.iter().copied(), color.stroke_width(2)))?
+                .label(line.label.as_str())
+                .legend(move |(x, y)| PathElement::new(vec![(x, y), (x + 16, y)], color.stroke_width(2)));
+        }
+
+        chart
+            .configure_series_labels()
+            .position(SeriesLabelPosition::UpperLeft)
+            .background_style(WHITE.mix(0.8))
+            .border_style(BLACK.mix(0.3))
+            .label_font(("sans-serif", 11))
+            .draw()?;
+        Ok(())
+    })
+}
+
+fn no_data_svg() -> String {
+    render(|root| {
+        let style = ("sans-serif", 16).into_font().color(&BLACK.mix(0.5));
+        let style = style.pos(Pos::new(HPos::Center, VPos::Center));
+        root.draw_text("No data", &style, (WIDTH as i32 / 2, HEIGHT as i32 / 2))?;
+        Ok(())
+    })
+}
+
+type Root<'a> = DrawingArea<SVGBackend<'a>, plotters::coord::Shift>;
+
+fn render<F>(draw: F) -> String
+where
+    F: FnOnce(&Root) -> Result<(), Box<dyn Error>>,
+{
+    let mut svg = String::new();
+    let result = {
+        let root = SVGBackend::with_string(&mut svg, (WIDTH, HEIGHT)).into_drawing_area();
+        draw(&root).and_then(|_| root.present().map_err(|e| e.into()))
+    };
+
+    match result {
+        Ok(()) => svg,
+        Err(e) => format!("<p>Chart unavailable: {}</p>", e),
+    }
+}
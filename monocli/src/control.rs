@@ -0,0 +1,87 @@
+//! monodeamon's control socket (see `monocore::control`), reached through
+//! `adb forward tcp:0 localabstract:monodeamon`. Each request forwards a
+//! port of its own and removes it afterwards, so concurrent requests to
+//! several devices don't get in each other's way.
+
+use std::io::{Read, Write};
+use std::net::{Ipv4Addr, SocketAddr, TcpStream};
+use std::time::Duration;
+
+use monocore::control::{parse_reply, Request, SOCKET_NAME};
+use monocore::status::DaemonStatus;
+
+use crate::device::Device;
+use crate::error::{MonoError, Result};
+use crate::install::{self, CONFIG_PATH};
+
+/// Lines `monocli service recent` prints without a count.
+pub const DEFAULT_RECENT_LINES: usize = 100;
+
+/// How long a request gets to connect and be answered. `flush` and `stop`
+/// wait for the spool to be sent on the device first.
+fn timeout(request: Request) -> Duration {
+    match request {
+        Request::Flush => Duration::from_secs(40),
+        Request::Stop => Duration::from_secs(15),
+        _ => Duration::from_secs(5),
+    }
+}
+
+/// Sends `request` to the daemon on `device` and returns the body of its
+/// reply. An error reply is a [`MonoError::CommandFailed`].
+pub fn request(device: &Device, request: Request) -> Result<String> {
+    let port = device.forward(&format!("localabstract:{}", SOCKET_NAME))?;
+    let reply = exchange(port, request);
+    let _ = device.remove_forward(port);
+    let failed = |message: String| MonoError::CommandFailed {
+        serial: device.serial.clone(),
+        command: format!("monodeamon control: {}", request),
+        exit_code: None,
+        message,
+    };
+    parse_reply(&reply.map_err(|e| failed(e.to_string()))?).map_err(failed)
+}
+
+fn exchange(port: u16, request: Request) -> std::io::Result<String> {
+    let address = SocketAddr::from((Ipv4Addr::LOCALHOST, port));
+    let mut stream = TcpStream::connect_timeout(&address, timeout(request))?;
+    stream.set_read_timeout(Some(timeout(request)))?;
+    stream.write_all(format!("{}\n", request).as_bytes())?;
+    // The daemon closes the connection once it has replied.
+    let mut reply = String::new();
+    stream.read_to_string(&mut reply)?;
+    Ok(reply)
+}
+
+/// The running daemon's status, straight from it.
+pub fn status(device: &Device) -> Result<DaemonStatus> {
+    let body = request(device, Request::Status)?;
+    DaemonStatus::parse(&body).ok_or_else(|| MonoError::CommandFailed {
+        serial: device.serial.clone(),
+        command: format!("monodeamon control: {}", Request::Status),
+        exit_code: None,
+        message: format!("unexpected status: {}", body.lines().next().unwrap_or_default()),
+    })
+}
+
+/// `monocli service reload`: pushes `config` as the daemon's config and has
+/// the running daemon pick it up without restarting.
+pub fn reload(device: &Device, config: &str) -> Result<String> {
+    install::push_contents(device, config, CONFIG_PATH, 0o644)?;
+    request(device, Request::Reload)
+}
+
+/// `monocli service flush`: waits for the daemon to send its spool.
+pub fn flush(device: &Device) -> Result<String> {
+    request(device, Request::Flush)
+}
+
+/// `monocli service recent`: prints the last `count` lines the daemon read
+/// from logcat.
+pub fn recent(device: &Device, count: usize) -> Result<String> {
+    let body = request(device, Request::Recent(count))?;
+    for line in body.lines() {
+        println!("[{}] {}", device.serial, line);
+    }
+    Ok(format!("{} lines", body.lines().count()))
+}
@@ -0,0 +1,372 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use crate::adb::{AdbClient, ShellOutput};
+use crate::error::{MonoError, Result};
+use crate::transport::{AdbTransport, DeviceTransport, RecordingTransport, ReplayTransport};
+
+/// A single Android device, reached through whichever transport backs it.
+#[derive(Clone)]
+pub struct Device {
+    transport: Arc<dyn DeviceTransport>,
+    pub serial: String,
+}
+
+impl Device {
+    /// A device attached to the adb server.
+    pub fn new(client: &AdbClient, serial: &str) -> Self {
+        Self::from_transport(AdbTransport::new(client, serial))
+    }
+
+    pub fn from_transport<T: DeviceTransport + 'static>(transport: T) -> Self {
+        Device {
+            serial: transport.serial().to_string(),
+            transport: Arc::new(transport),
+        }
+    }
+
+    pub fn transport(&self) -> &dyn DeviceTransport {
+        self.transport.as_ref()
+    }
+
+    /// Runs `cmd` in the device shell and returns stdout, stderr and exit status.
+    pub fn shell_output(&self, cmd: &str) -> io::Result<ShellOutput> {
+        self.transport.shell(cmd)
+    }
+
+    /// Like [`shell_output`](Self::shell_output), giving up after `timeout`.
+    pub fn shell_output_timeout(&self, cmd: &str, timeout: Duration) -> io::Result<ShellOutput> {
+        self.transport.shell_timeout(cmd, timeout)
+    }
+
+    /// Runs `cmd` in the device shell, handing its stdout to `on_stdout` as it
+    /// arrives, and returns the exit status.
+    pub fn shell_stream(&self, cmd: &str, mut on_stdout: impl FnMut(&[u8])) -> Result<i32> {
+        self.transport
+            .shell_stream(cmd, &mut on_stdout)
+            .map_err(|e| MonoError::from_io(&self.serial, cmd, e))
+    }
+
+    /// Runs `cmd` in the device shell and returns its stdout, whatever the exit status.
+    pub fn shell(&self, cmd: &str) -> Result<String> {
+        self.shell_output(cmd)
+            .map(|output| output.stdout_lossy())
+            .map_err(|e| MonoError::from_io(&self.serial, cmd, e))
+    }
+
+    /// Runs `cmd` in the device shell, failing unless it exits with status 0.
+    pub fn run(&self, cmd: &str) -> Result<String> {
+        self.check(cmd, self.shell_output(cmd))
+    }
+
+    /// Like [`run`](Self::run), but Ctrl-C doesn't abort it, so cleanup can
+    /// still run once work is cancelled. It gives up after `timeout`.
+    pub fn run_uncancellable(&self, cmd: &str, timeout: Duration) -> Result<String> {
+        self.check(cmd, self.transport.shell_uncancellable(cmd, timeout))
+    }
+
+    fn check(&self, cmd: &str, output: io::Result<ShellOutput>) -> Result<String> {
+        let output = output.map_err(|e| MonoError::from_io(&self.serial, cmd, e))?;
+        if !output.success() {
+            let stderr = output.stderr_lossy();
+            let message = if stderr.trim().is_empty() { output.stdout_lossy() } else { stderr };
+            return Err(MonoError::CommandFailed {
+                serial: self.serial.clone(),
+                command: cmd.to_string(),
+                exit_code: Some(output.exit_code),
+                message: message.trim().to_string(),
+            });
+        }
+        Ok(output.stdout_lossy())
+    }
+
+    pub fn push(&self, local: &Path, remote: &str, mode: u32) -> Result<()> {
+        self.transport
+            .push(local, remote, mode)
+            .map_err(|e| MonoError::from_io(&self.serial, &format!("push {}", remote), e))
+    }
+
+    pub fn pull(&self, remote: &str, local: &Path) -> Result<u64> {
+        self.transport
+            .pull(remote, local)
+            .map_err(|e| MonoError::from_io(&self.serial, &format!("pull {}", remote), e))
+    }
+
+    pub fn getprop(&self, name: &str) -> Result<String> {
+        self.transport
+            .getprop(name)
+            .map_err(|e| MonoError::from_io(&self.serial, &format!("getprop {}", name), e))
+    }
+
+    /// Forwards a local TCP port to `remote` on the device; see
+    /// [`DeviceTransport::forward`].
+    pub fn forward(&self, remote: &str) -> Result<u16> {
+        self.transport
+            .forward(remote)
+            .map_err(|e| MonoError::from_io(&self.serial, &format!("forward {}", remote), e))
+    }
+
+    pub fn remove_forward(&self, port: u16) -> Result<()> {
+        self.transport
+            .remove_forward(port)
+            .map_err(|e| MonoError::from_io(&self.serial, &format!("forward --remove tcp:{}", port), e))
+    }
+
+    /// Whether `su` gives a root shell on this device.
+    pub fn is_rooted(&self) -> Result<bool> {
+        Ok(self.shell("su -c id")?.contains("uid=0(root)"))
+    }
+
+    pub fn log(&self, message: &str) {
+        println!("[INFO] [{}] {}", self.serial, message);
+    }
+}
+
+/// How the user asked us to pick devices on the command line.
+#[derive(Debug, Default)]
+pub struct DeviceSelector {
+    pub serials: Vec<String>,
+    pub all: bool,
+    pub matches: Vec<(String, String)>,
+    /// Fixture files to replay instead of talking to adb.
+    pub replays: Vec<PathBuf>,
+    /// Directory to record one fixture per selected device into.
+    pub record: Option<PathBuf>,
+}
+
+impl DeviceSelector {
+    /// Consumes `--serial <id>`, `--all`, `--match <prop=value>`, `--replay <fixture>`
+    /// and `--record <dir>` from `args`, leaving everything else in place for
+    /// the command itself.
+    pub fn from_args(args: &mut Vec<String>) -> Result<Self> {
+        let mut selector = DeviceSelector::default();
+        let mut rest = Vec::new();
+        let mut iter = args.drain(..);
+
+        while let Some(arg) = iter.next() {
+            match arg.as_str() {
+                "--serial" | "-s" => {
+                    let serial = iter.next().ok_or_else(|| usage("--serial requires a device serial"))?;
+                    selector.serials.push(serial);
+                }
+                "--all" => selector.all = true,
+                "--match" => {
+                    let filter = iter.next().ok_or_else(|| usage("--match requires a <prop=value> filter"))?;
+                    let (prop, value) = filter
+                        .split_once('=')
+                        .ok_or_else(|| usage(&format!("Invalid --match filter '{}', expected <prop=value>", filter)))?;
+                    selector.matches.push((prop.trim().to_string(), value.trim().to_string()));
+                }
+                "--replay" => {
+                    let fixture = iter.next().ok_or_else(|| usage("--replay requires a fixture file"))?;
+                    selector.replays.push(PathBuf::from(fixture));
+                }
+                "--record" => {
+                    let dir = iter.next().ok_or_else(|| usage("--record requires a directory"))?;
+                    selector.record = Some(PathBuf::from(dir));
+                }
+                _ => rest.push(arg),
+            }
+        }
+
+        drop(iter);
+        *args = rest;
+        Ok(selector)
+    }
+
+    fn is_empty(&self) -> bool {
+        self.serials.is_empty() && !self.all && self.matches.is_empty()
+    }
+}
+
+/// Lists `(serial, state)` pairs as reported by the adb server.
+pub fn list_devices(client: &AdbClient) -> Result<Vec<(String, String)>> {
+    let devices = client.devices().map_err(|e| {
+        MonoError::AdbUnavailable(format!("Failed to list devices from adb server at {}: {}", client.address(), e))
+    })?;
+
+    Ok(devices.into_iter().map(|d| (d.serial, d.state)).collect())
+}
+
+/// Resolves the selector into devices: replayed fixtures if any were given,
+/// otherwise devices attached to adb, optionally wrapped for recording.
+pub fn select_devices(client: &AdbClient, selector: &DeviceSelector) -> Result<Vec<Device>> {
+    if !selector.replays.is_empty() {
+        return selector
+            .replays
+            .iter()
+            .map(|path| {
+                ReplayTransport::load(path)
+                    .map(Device::from_transport)
+                    .map_err(|e| MonoError::Usage(format!("Failed to load fixture {}: {}", path.display(), e)))
+            })
+            .collect();
+    }
+
+    let serials = select_serials(client, selector)?;
+
+    let Some(record_dir) = &selector.record else {
+        return Ok(serials.iter().map(|serial| Device::new(client, serial)).collect());
+    };
+
+    fs::create_dir_all(record_dir)
+        .map_err(|e| MonoError::Other(format!("Failed to create {}: {}", record_dir.display(), e)))?;
+    Ok(serials
+        .iter()
+        .map(|serial| {
+            let inner = Box::new(AdbTransport::new(client, serial));
+            let fixture = record_dir.join(format!("{}.json", serial));
+            Device::from_transport(RecordingTransport::new(inner, &fixture))
+        })
+        .collect())
+}
+
+/// Resolves the selector against the devices currently attached to adb.
+///
+/// With no selector at all we keep the old behaviour of talking to the only
+/// attached device, but refuse to guess when several are plugged in.
+fn select_serials(client: &AdbClient, selector: &DeviceSelector) -> Result<Vec<String>> {
+    let attached = list_devices(client)?;
+    let online: Vec<&String> = attached
+        .iter()
+        .filter(|(_, state)| state == "device")
+        .map(|(serial, _)| serial)
+        .collect();
+
+    if selector.is_empty() {
+        return match online.as_slice() {
+            [] => Err(MonoError::DeviceUnavailable(
+                "No online devices found. Check 'adb devices'.".to_string(),
+            )),
+            [serial] => Ok(vec![serial.to_string()]),
+            _ => Err(usage(&format!(
+                "{} devices attached ({}). Pick one with --serial <id>, use --match <prop=value>, or pass --all.",
+                online.len(),
+                online.iter().map(|s| s.as_str()).collect::<Vec<_>>().join(", ")
+            ))),
+        };
+    }
+
+    let mut serials: Vec<String> = Vec::new();
+
+    for serial in &selector.serials {
+        match attached.iter().find(|(s, _)| s == serial) {
+            Some((_, state)) if state == "device" => serials.push(serial.clone()),
+            Some((_, state)) => {
+                return Err(MonoError::DeviceUnavailable(format!("Device {} is {}, not ready", serial, state)));
+            }
+            None => return Err(MonoError::DeviceUnavailable(format!("Device {} is not attached", serial))),
+        }
+    }
+
+    if selector.all || !selector.matches.is_empty() {
+        for serial in &online {
+            if serials.contains(serial) {
+                continue;
+            }
+            let transport = AdbTransport::new(client, serial);
+            let matches = selector
+                .matches
+                .iter()
+                .all(|(prop, value)| transport.getprop(prop).is_ok_and(|actual| actual == *value));
+            if matches {
+                serials.push(serial.to_string());
+            }
+        }
+    }
+
+    if serials.is_empty() {
+        return Err(MonoError::DeviceUnavailable(
+            "No attached device matches the given selectors.".to_string(),
+        ));
+    }
+
+    Ok(serials)
+}
+
+/// Outcome of running one command against one device.
+pub struct DeviceReport {
+    pub serial: String,
+    pub model: String,
+    pub result: Result<String>,
+}
+
+/// Runs `task` on every device, concurrently when there is more than one, and
+/// collects one report per device in selection order.
+pub fn run_on_devices<F>(devices: &[Device], task: F) -> Vec<DeviceReport>
+where
+    F: Fn(&Device) -> Result<String> + Sync,
+{
+    map_devices(
+        devices,
+        |device| DeviceReport {
+            serial: device.serial.clone(),
+            model: device.getprop("ro.product.model").unwrap_or_default(),
+            result: task(device),
+        },
+        |device| DeviceReport {
+            serial: device.serial.clone(),
+            model: String::new(),
+            result: Err(MonoError::Other("worker panicked".to_string())),
+        },
+    )
+}
+
+/// Runs `task` on every device like [`run_on_devices`], for tasks with
+/// their own kind of result; `panicked` stands in for a task that panicked.
+pub fn map_devices<T, F, P>(devices: &[Device], task: F, panicked: P) -> Vec<T>
+where
+    T: Send,
+    F: Fn(&Device) -> T + Sync,
+    P: Fn(&Device) -> T,
+{
+    if devices.len() == 1 {
+        return vec![task(&devices[0])];
+    }
+
+    thread::scope(|scope| {
+        let handles: Vec<_> = devices
+            .iter()
+            .map(|device| scope.spawn(|| task(device)))
+            .collect();
+
+        handles
+            .into_iter()
+            .zip(devices)
+            .map(|(handle, device)| handle.join().unwrap_or_else(|_| panicked(device)))
+            .collect()
+    })
+}
+
+/// Prints the per-device summary table and returns the process exit code:
+/// 0 if every device succeeded, otherwise that of the first failure.
+pub fn print_summary(command: &str, reports: &[DeviceReport]) -> i32 {
+    let serial_width = reports.iter().map(|r| r.serial.len()).chain([6]).max().unwrap_or(6);
+    let model_width = reports.iter().map(|r| r.model.len()).chain([5]).max().unwrap_or(5);
+
+    println!();
+    println!("Summary: {}", command);
+    println!("{:<serial_width$}  {:<model_width$}  {:<6}  Details", "Serial", "Model", "Status");
+    println!("{}", "-".repeat(serial_width + model_width + 24));
+
+    for report in reports {
+        let (status, details) = match &report.result {
+            Ok(details) => ("OK", details.clone()),
+            Err(error) => ("FAILED", error.to_string()),
+        };
+        println!("{:<serial_width$}  {:<model_width$}  {:<6}  {}", report.serial, report.model, status, details);
+    }
+
+    reports
+        .iter()
+        .find_map(|r| r.result.as_ref().err())
+        .map_or(0, MonoError::exit_code)
+}
+
+fn usage(message: &str) -> MonoError {
+    MonoError::Usage(message.to_string())
+}
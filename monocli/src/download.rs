@@ -0,0 +1,196 @@
+//! Resumable, checksum-verified HTTP downloads.
+//!
+//! A download goes to `<file>.part` first and is only renamed into place
+//! once its size and checksum match, so whatever sits at the final path has
+//! been verified. An interrupted download keeps its `.part` file and picks
+//! up from there next time with an HTTP `Range` request.
+
+use std::fmt;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use reqwest::blocking::Client;
+use reqwest::header::RANGE;
+use reqwest::StatusCode;
+use sha1::Sha1;
+use sha2::{Digest, Sha256};
+
+use crate::cancel;
+use crate::error::{MonoError, Result};
+
+/// An expected digest, as lowercase hex.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Checksum {
+    Sha1(String),
+    Sha256(String),
+}
+
+impl Checksum {
+    /// Hashes `path` with this checksum's algorithm.
+    pub fn digest_file(&self, path: &Path) -> io::Result<String> {
+        match self {
+            Checksum::Sha1(_) => digest_file::<Sha1>(path),
+            Checksum::Sha256(_) => digest_file::<Sha256>(path),
+        }
+    }
+
+    pub fn hex(&self) -> &str {
+        match self {
+            Checksum::Sha1(hex) | Checksum::Sha256(hex) => hex,
+        }
+    }
+}
+
+impl fmt::Display for Checksum {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Checksum::Sha1(hex) => write!(f, "sha1:{}", hex),
+            Checksum::Sha256(hex) => write!(f, "sha256:{}", hex),
+        }
+    }
+}
+
+/// SHA-256 of a file, as lowercase hex.
+pub fn sha256_file(path: &Path) -> io::Result<String> {
+    digest_file::<Sha256>(path)
+}
+
+fn digest_file<D: Digest>(path: &Path) -> io::Result<String> {
+    let mut file = File::open(path)?;
+    let mut hasher = D::new();
+    let mut buffer = vec![0u8; 64 * 1024];
+    loop {
+        let read = file.read(&mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buffer[..read]);
+    }
+    Ok(hasher.finalize().iter().map(|byte| format!("{:02x}", byte)).collect())
+}
+
+/// What [`fetch`] had to do.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Fetched {
+    /// A verified copy was already there.
+    Cached,
+    /// Downloaded, continuing from `resumed_from` bytes of an earlier attempt.
+    Downloaded { resumed_from: u64 },
+}
+
+/// Something to download and how to recognize it.
+#[derive(Debug, Clone)]
+pub struct Download<'a> {
+    pub url: &'a str,
+    pub checksum: &'a Checksum,
+    /// Expected size in bytes, when known up front.
+    pub size: Option<u64>,
+}
+
+/// A client without reqwest's default 30 second limit on the whole
+/// request, which a large archive would never fit in.
+pub fn client() -> Result<Client> {
+    Client::builder()
+        .connect_timeout(Duration::from_secs(30))
+        .timeout(None)
+        .build()
+        .map_err(|e| MonoError::Other(format!("Failed to set up HTTP client: {}", e)))
+}
+
+/// Downloads `download` to `dest` unless a copy with the right checksum is
+/// already there, resuming an earlier partial download when possible.
+/// `progress` is called with the bytes so far and the total, if known.
+pub fn fetch(
+    client: &Client,
+    download: &Download,
+    dest: &Path,
+    mut progress: impl FnMut(u64, Option<u64>),
+) -> Result<Fetched> {
+    let io_error = |path: &Path, e: io::Error| MonoError::Other(format!("{}: {}", path.display(), e));
+
+    if dest.is_file() {
+        if download.checksum.digest_file(dest).map_err(|e| io_error(dest, e))? == download.checksum.hex() {
+            return Ok(Fetched::Cached);
+        }
+        fs::remove_file(dest).map_err(|e| io_error(dest, e))?;
+    }
+    if let Some(parent) = dest.parent() {
+        fs::create_dir_all(parent).map_err(|e| io_error(parent, e))?;
+    }
+
+    let part = part_path(dest);
+    let mut offset = fs::metadata(&part).map_or(0, |metadata| metadata.len());
+    if download.size.is_some_and(|size| offset > size) {
+        offset = 0;
+    }
+    let mut resumed_from = offset;
+
+    if download.size != Some(offset) || offset == 0 {
+        let mut request = client.get(download.url);
+        if offset > 0 {
+            request = request.header(RANGE, format!("bytes={}-", offset));
+        }
+        let failed = |e: &dyn fmt::Display| MonoError::Other(format!("Failed to download {}: {}", download.url, e));
+        let mut response = request.send().map_err(|e| failed(&e))?;
+
+        let file = match response.status() {
+            StatusCode::PARTIAL_CONTENT => Some(OpenOptions::new().append(true).open(&part)),
+            StatusCode::OK => {
+                // The server ignored the range, so start over.
+                offset = 0;
+                resumed_from = 0;
+                Some(File::create(&part))
+            }
+            // Nothing left past what we have; the checks below decide if it's right.
+            StatusCode::RANGE_NOT_SATISFIABLE if offset > 0 => None,
+            status => return Err(failed(&status)),
+        };
+        if let Some(file) = file {
+            let mut file = file.map_err(|e| io_error(&part, e))?;
+            let total = download.size.or_else(|| response.content_length().map(|length| length + offset));
+            let mut buffer = vec![0u8; 64 * 1024];
+            loop {
+                if cancel::requested() {
+                    return Err(MonoError::Cancelled(format!(
+                        "Download cancelled, {} bytes kept in {} to resume from",
+                        offset,
+                        part.display()
+                    )));
+                }
+                let read = response.read(&mut buffer).map_err(|e| failed(&e))?;
+                if read == 0 {
+                    break;
+                }
+                file.write_all(&buffer[..read]).map_err(|e| io_error(&part, e))?;
+                offset += read as u64;
+                progress(offset, total);
+            }
+            file.flush().map_err(|e| io_error(&part, e))?;
+        }
+    }
+
+    // A wrong size or checksum won't get better by resuming: start over next time.
+    let mismatch = |message: String| {
+        let _ = fs::remove_file(&part);
+        MonoError::Other(format!("Downloaded {} is corrupt: {}", download.url, message))
+    };
+    if let Some(size) = download.size.filter(|&size| size != offset) {
+        return Err(mismatch(format!("expected {} bytes, got {}", size, offset)));
+    }
+    let digest = download.checksum.digest_file(&part).map_err(|e| io_error(&part, e))?;
+    if digest != download.checksum.hex() {
+        return Err(mismatch(format!("expected {}, got {}", download.checksum, digest)));
+    }
+
+    fs::rename(&part, dest).map_err(|e| io_error(dest, e))?;
+    Ok(Fetched::Downloaded { resumed_from })
+}
+
+/// Where the download of `dest` is kept until it has been verified.
+pub fn part_path(dest: &Path) -> PathBuf {
+    let mut name = dest.file_name().unwrap_or_default().to_os_string();
+    name.push(".part");
+    dest.with_file_name(name)
+}
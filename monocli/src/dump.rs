@@ -0,0 +1,407 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use chrono::Local;
+use monocore::config::{parse_value, Config};
+
+use crate::cancel;
+use crate::device::Device;
+use crate::error::{MonoError, Result};
+use crate::manifest::{output_file_name, safe_name, Manifest, ManifestEntry, Outcome};
+use crate::netstats::{self, RateRow};
+use crate::profile::{CaptureCommand, Profile, Profiles};
+use crate::report::{generate_html_report, ReportTheme};
+use crate::log_message;
+
+/// Raw text captured from a device, one section per report section in the
+/// order the capture profile first listed them.
+pub struct CapturedData {
+    pub sections: Vec<CapturedSection>,
+    /// Network rates sampled during the capture, empty if there were none.
+    pub network: Vec<RateRow>,
+}
+
+pub struct CapturedSection {
+    pub name: String,
+    pub text: String,
+}
+
+impl CapturedData {
+    /// Rebuilds the section text from the raw files saved in a dump folder,
+    /// exactly as it was assembled when the dump was captured.
+    pub fn from_dump(dump_dir: &Path, manifest: &Manifest) -> Result<Self> {
+        let mut sections: Vec<(String, Vec<String>)> = Vec::new();
+        for entry in &manifest.entries {
+            let path = dump_dir.join(&entry.file);
+            let stdout =
+                fs::read(&path).map_err(|e| MonoError::Other(format!("Failed to read {}: {}", path.display(), e)))?;
+            let output = format_output(&entry.command, &String::from_utf8_lossy(&stdout));
+            match sections.iter_mut().find(|(name, _)| *name == entry.section) {
+                Some((_, outputs)) => outputs.push(output),
+                None => sections.push((entry.section.clone(), vec![output])),
+            }
+        }
+
+        let network = match &manifest.network_samples {
+            Some(file) => {
+                let path = dump_dir.join(file);
+                netstats::read_csv(&path)
+                    .map_err(|e| MonoError::Other(format!("Failed to read {}: {}", path.display(), e)))?
+            }
+            None => Vec::new(),
+        };
+
+        Ok(CapturedData {
+            network,
+            sections: sections
+                .into_iter()
+                .map(|(name, outputs)| CapturedSection {
+                    name,
+                    text: outputs.join("\n\n"),
+                })
+                .collect(),
+        })
+    }
+
+    /// Text of one section, empty if the profile had no such section.
+    pub fn section(&self, name: &str) -> &str {
+        self.sections
+            .iter()
+            .find(|section| section.name == name)
+            .map_or("", |section| section.text.as_str())
+    }
+
+    /// One command's stdout, from whichever section captured it.
+    pub fn command_output(&self, cmd: &str) -> Option<&str> {
+        self.sections.iter().find_map(|section| command_output(&section.text, cmd))
+    }
+}
+
+/// Re-renders the reports of an existing dump folder from its saved raw
+/// outputs, with no device involved.
+pub fn regenerate_report(dump_dir: &Path, theme: &ReportTheme) -> Result<String> {
+    let manifest = Manifest::load(dump_dir)
+        .map_err(|e| MonoError::Other(format!("Not a dump folder ({}): {}", dump_dir.display(), e)))?;
+    let data = CapturedData::from_dump(dump_dir, &manifest)?;
+
+    generate_html_report(dump_dir, &manifest.device_name, &manifest.timestamp, &data, theme)?;
+
+    log_message(&format!("Dashboard regenerated in {}/", dump_dir.display()));
+    Ok(dump_dir.join("dashboard.html").display().to_string())
+}
+
+/// What a dump captures and how: the profile's commands, how many run at
+/// once and how long each one may take.
+#[derive(Debug, Clone)]
+pub struct CaptureOptions {
+    pub profile: Profile,
+    pub jobs: usize,
+    pub timeout: Duration,
+    /// Per-command overrides of `timeout`, keyed by the exact command line.
+    pub timeouts: BTreeMap<String, Duration>,
+    /// How often `/proc/net/dev` is sampled during the capture; `None` disables it.
+    pub net_interval: Option<Duration>,
+}
+
+impl Default for CaptureOptions {
+    fn default() -> Self {
+        let profiles = Profiles::builtin();
+        CaptureOptions {
+            profile: profiles.get("full").expect("built-in 'full' profile").clone(),
+            jobs: 4,
+            timeout: Duration::from_secs(60),
+            timeouts: BTreeMap::new(),
+            net_interval: Some(Duration::from_secs(1)),
+        }
+    }
+}
+
+impl CaptureOptions {
+    /// Reads `dump.profile`, `dump.jobs`, `dump.timeout`, the per-command
+    /// `[dump.timeouts]` and `dump.net_interval`.
+    pub fn from_config(config: &Config) -> Result<Self> {
+        let jobs: usize = config.parse("dump.jobs")?;
+        if jobs == 0 {
+            return Err(MonoError::Usage("dump.jobs must be at least 1".to_string()));
+        }
+
+        let mut timeouts = BTreeMap::new();
+        for (command, value) in config.table("dump.timeouts") {
+            let key = format!("dump.timeouts.{}", command);
+            timeouts.insert(command.to_string(), seconds(&key, parse_value(&key, value)?)?);
+        }
+
+        let profile = Profiles::load(config)?.get(config.get("dump.profile"))?.clone();
+        let net_interval: f64 = config.parse("dump.net_interval")?;
+        let net_interval = match net_interval {
+            0.0 => None,
+            seconds_between => Some(seconds("dump.net_interval", seconds_between)?),
+        };
+
+        Ok(CaptureOptions {
+            profile,
+            jobs,
+            timeout: seconds("dump.timeout", config.parse("dump.timeout")?)?,
+            timeouts,
+            net_interval,
+        })
+    }
+
+    /// The profile entry's own timeout, else `[dump.timeouts]`, else `dump.timeout`.
+    pub fn timeout_for(&self, command: &CaptureCommand) -> Duration {
+        command
+            .timeout()
+            .or_else(|| self.timeouts.get(&command.command).copied())
+            .unwrap_or(self.timeout)
+    }
+}
+
+/// Captures the selected profile from `device` into a new timestamped folder under `output_root`.
+///
+/// If the run is cancelled with Ctrl-C, commands still running are abandoned
+/// and the rest skipped, but the manifest and dashboard are still written so
+/// the partial dump stays usable.
+pub fn dump_device_data(
+    device: &Device,
+    output_root: &Path,
+    options: &CaptureOptions,
+    theme: &ReportTheme,
+) -> Result<String> {
+    let device_name = get_device_name(device)?;
+    let timestamp = Local::now().format("%Y%m%d_%H%M%S").to_string();
+    let dump_dir = output_root.join(run_dir_name(&device_name, &device.serial, &timestamp));
+    fs::create_dir_all(&dump_dir)
+        .map_err(|e| MonoError::Other(format!("Failed to create dump directory: {}", e)))?;
+
+    let dump_dir = dump_dir.as_path();
+
+    // Capture all information
+    device.log(&format!("Capturing device information (profile '{}')...", options.profile.name));
+    let capture = Capture {
+        device,
+        dump_dir,
+        options,
+    };
+    let mut manifest = Manifest::new(&device.serial, &device_name, &timestamp, &options.profile.name);
+    let done = &AtomicBool::new(false);
+    let (entries, samples) = thread::scope(|scope| {
+        // Network counters are sampled alongside the capture commands.
+        let sampler = options
+            .net_interval
+            .map(|interval| scope.spawn(move || netstats::sample_until(device, interval, options.timeout, done)));
+        let entries = capture.run();
+        done.store(true, Ordering::SeqCst);
+        let samples = sampler.map_or(Vec::new(), |sampler| sampler.join().unwrap_or_default());
+        (entries, samples)
+    });
+    manifest.entries = entries?;
+    manifest.cancelled = cancel::requested();
+
+    if samples.len() >= 2 {
+        let path = dump_dir.join(netstats::SAMPLES_FILE);
+        netstats::write_csv(&path, &netstats::rates(&samples))
+            .map_err(|e| MonoError::Other(format!("Failed to write {}: {}", path.display(), e)))?;
+        manifest.network_samples = Some(netstats::SAMPLES_FILE.to_string());
+    }
+    let data = CapturedData::from_dump(dump_dir, &manifest)?;
+
+    manifest
+        .save(dump_dir)
+        .map_err(|e| MonoError::Other(format!("Failed to write manifest: {}", e)))?;
+
+    // Generate HTML report
+    generate_html_report(dump_dir, &device_name, &timestamp, &data, theme)?;
+
+    if manifest.cancelled {
+        return Err(MonoError::Cancelled(format!("Cancelled, partial dump saved in {}/", dump_dir.display())));
+    }
+    device.log(&format!("Advanced device dashboard generated in {}/", dump_dir.display()));
+    Ok(dump_dir.display().to_string())
+}
+
+/// One scheduled capture command.
+struct Job<'a> {
+    command: &'a CaptureCommand,
+    /// Output path relative to the dump folder.
+    file: String,
+}
+
+/// Runs capture commands against a device with bounded concurrency, saving
+/// each one's raw stdout under the dump folder.
+struct Capture<'a> {
+    device: &'a Device,
+    dump_dir: &'a Path,
+    options: &'a CaptureOptions,
+}
+
+impl Capture<'_> {
+    /// Runs the whole profile and returns manifest entries in profile order,
+    /// however the commands were interleaved.
+    fn run(&self) -> Result<Vec<ManifestEntry>> {
+        let mut jobs: Vec<Job> = Vec::new();
+        for command in &self.options.profile.commands {
+            let file = unique_file(&jobs, command);
+            jobs.push(Job { command, file });
+        }
+
+        // Only ask for root when the profile needs it.
+        let needs_root = jobs.iter().any(|job| job.command.root);
+        let rooted = needs_root && self.device.is_rooted().unwrap_or(false);
+        if needs_root && !rooted {
+            self.device.log("Device is not rooted, skipping commands that need root");
+        }
+
+        let next = AtomicUsize::new(0);
+        let results: Mutex<Vec<Option<Result<ManifestEntry>>>> =
+            Mutex::new(jobs.iter().map(|_| None).collect());
+        let workers = self.options.jobs.clamp(1, jobs.len().max(1));
+
+        thread::scope(|scope| {
+            for _ in 0..workers {
+                scope.spawn(|| loop {
+                    if cancel::requested() {
+                        break;
+                    }
+                    let index = next.fetch_add(1, Ordering::SeqCst);
+                    let Some(job) = jobs.get(index) else { break };
+                    let result = if job.command.root && !rooted {
+                        self.skip_command(job, Outcome::Skipped, "requires root")
+                    } else {
+                        self.execute_command(job)
+                    };
+                    results.lock().unwrap()[index] = Some(result);
+                });
+            }
+        });
+
+        jobs
+            .iter()
+            .zip(results.into_inner().unwrap())
+            .map(|(job, result)| match result {
+                Some(result) => result,
+                None => self.skip_command(job, Outcome::Cancelled, ""),
+            })
+            .collect()
+    }
+
+    fn execute_command(&self, job: &Job) -> Result<ManifestEntry> {
+        let command = &job.command.command;
+        let timeout = self.options.timeout_for(job.command);
+        let shell_command = if job.command.root { as_root(command) } else { command.clone() };
+        let started = Instant::now();
+        let (stdout, stderr, outcome, exit_code) = match self.device.shell_output_timeout(&shell_command, timeout) {
+            Ok(output) => {
+                let stderr = output.stderr_lossy();
+                (output.stdout, stderr, Outcome::Completed, Some(output.exit_code))
+            }
+            Err(e) if e.kind() == io::ErrorKind::TimedOut => {
+                self.device
+                    .log(&format!("'{}' timed out after {}s", command, timeout.as_secs_f64()));
+                (Vec::new(), e.to_string(), Outcome::TimedOut, None)
+            }
+            Err(e) if cancel::requested() => (Vec::new(), e.to_string(), Outcome::Cancelled, None),
+            Err(e) => (Vec::new(), e.to_string(), Outcome::Failed, None),
+        };
+        let duration = started.elapsed();
+
+        self.write_output(job, &stdout)?;
+        Ok(ManifestEntry {
+            command: command.clone(),
+            section: job.command.section.clone(),
+            root: job.command.root,
+            file: job.file.clone(),
+            outcome,
+            exit_code,
+            stderr,
+            duration_ms: duration.as_millis() as u64,
+            timeout_ms: Some(timeout.as_millis() as u64),
+            bytes: stdout.len() as u64,
+        })
+    }
+
+    /// Records a command that never ran, because the dump was cancelled or it
+    /// needs root, with an empty output file so the dump stays complete on disk.
+    fn skip_command(&self, job: &Job, outcome: Outcome, reason: &str) -> Result<ManifestEntry> {
+        self.write_output(job, &[])?;
+        Ok(ManifestEntry {
+            command: job.command.command.clone(),
+            section: job.command.section.clone(),
+            root: job.command.root,
+            file: job.file.clone(),
+            outcome,
+            exit_code: None,
+            stderr: reason.to_string(),
+            duration_ms: 0,
+            timeout_ms: None,
+            bytes: 0,
+        })
+    }
+
+    fn write_output(&self, job: &Job, stdout: &[u8]) -> Result<()> {
+        let path = self.dump_dir.join(&job.file);
+        fs::create_dir_all(path.parent().unwrap())
+            .and_then(|_| fs::write(&path, stdout))
+            .map_err(|e| MonoError::Other(format!("Failed to write {}: {}", path.display(), e)))
+    }
+}
+
+/// `<section>/<command>.txt`, suffixed if the same command runs twice.
+fn unique_file(jobs: &[Job], command: &CaptureCommand) -> String {
+    let section = &command.section;
+    let name = output_file_name(&command.command);
+    let stem = name.trim_end_matches(".txt");
+    let mut file = format!("{}/{}", section, name);
+    let mut n = 2;
+    while jobs.iter().any(|job| job.file == file) {
+        file = format!("{}/{}_{}.txt", section, stem, n);
+        n += 1;
+    }
+    file
+}
+
+/// Labels one command's output inside the section text the report consumes.
+pub fn format_output(cmd: &str, stdout: &str) -> String {
+    format!("### Output of {} ###\n{}", cmd, stdout)
+}
+
+/// Finds one command's stdout inside section text built by [`format_output`].
+pub fn command_output<'a>(section: &'a str, cmd: &str) -> Option<&'a str> {
+    let marker = format!("### Output of {} ###\n", cmd);
+    let start = section.find(&marker)? + marker.len();
+    let rest = &section[start..];
+    let end = rest.find("\n\n### Output of ").unwrap_or(rest.len());
+    Some(&rest[..end])
+}
+
+/// Wraps `cmd` for `su -c`, quoting it as a single shell word.
+fn as_root(cmd: &str) -> String {
+    format!("su -c '{}'", cmd.replace('\'', "'\\''"))
+}
+
+/// Folder name for a dump or monitor run: model, serial and timestamp. The
+/// serial keeps identical models run in the same second apart; it and the
+/// model are made safe first, since network serials are `host:port` and
+/// models can hold `/`.
+pub fn run_dir_name(device_name: &str, serial: &str, timestamp: &str) -> String {
+    format!("{}_{}_{}", safe_name(device_name), safe_name(serial), timestamp)
+}
+
+/// The model name as used in dump folder names, spaces replaced.
+pub fn get_device_name(device: &Device) -> Result<String> {
+    Ok(device.getprop("ro.product.model")?.replace(' ', "_"))
+}
+
+fn seconds(key: &str, seconds: f64) -> Result<Duration> {
+    if seconds > 0.0 && seconds.is_finite() {
+        Ok(Duration::from_secs_f64(seconds))
+    } else {
+        Err(MonoError::Usage(format!("{} must be a positive number of seconds", key)))
+    }
+}
+
@@ -0,0 +1,147 @@
+//! The error type shared by monocli's commands, and the process exit codes
+//! each kind of failure maps to.
+//!
+//! | Code | Meaning                                                   |
+//! |------|-----------------------------------------------------------|
+//! | 0    | Success                                                   |
+//! | 1    | Any other failure (local I/O, bad dump folder, ...)       |
+//! | 2    | Invalid command line, config or report template           |
+//! | 3    | The adb server is not running or can't be reached         |
+//! | 4    | A device is missing, offline or unauthorized              |
+//! | 5    | A command on the device failed or couldn't be run         |
+//! | 6    | Building monodeamon failed                                |
+//! | 7    | `check` found a device or its daemon unhealthy            |
+//! | 130  | Cancelled with Ctrl-C                                     |
+
+use std::fmt;
+use std::io;
+
+use monocore::config::ConfigError;
+
+use crate::cancel;
+
+#[derive(Debug)]
+pub enum MonoError {
+    /// Bad arguments or user-supplied input; the message says what to fix.
+    Usage(String),
+    /// No adb server to talk to.
+    AdbUnavailable(String),
+    /// No usable device: not attached, offline or unauthorized.
+    DeviceUnavailable(String),
+    /// A shell command or file transfer on the device failed.
+    CommandFailed {
+        serial: String,
+        command: String,
+        /// `None` when the command never produced an exit status.
+        exit_code: Option<i32>,
+        message: String,
+    },
+    /// The monodeamon build (NDK lookup, cargo) failed.
+    BuildFailed(String),
+    /// `check` found problems; the message lists them.
+    Unhealthy(String),
+    /// A step of a multi-step operation failed; exits with its cause's code.
+    StepFailed {
+        step: String,
+        /// Whether the steps before it were undone cleanly.
+        rolled_back: bool,
+        cause: Box<MonoError>,
+    },
+    Cancelled(String),
+    Other(String),
+}
+
+pub type Result<T> = std::result::Result<T, MonoError>;
+
+impl MonoError {
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            MonoError::Other(_) => 1,
+            MonoError::Usage(_) => 2,
+            MonoError::AdbUnavailable(_) => 3,
+            MonoError::DeviceUnavailable(_) => 4,
+            MonoError::CommandFailed { .. } => 5,
+            MonoError::BuildFailed(_) => 6,
+            MonoError::Unhealthy(_) => 7,
+            MonoError::StepFailed { cause, .. } => cause.exit_code(),
+            MonoError::Cancelled(_) => 130,
+        }
+    }
+
+    /// Classifies a transport error for `command` on `serial`: Ctrl-C, adb
+    /// server and device availability problems get their own variants,
+    /// anything else is a failed command.
+    pub fn from_io(serial: &str, command: &str, error: io::Error) -> Self {
+        if cancel::is_cancelled(&error) {
+            return MonoError::Cancelled(format!("'{}' on {} cancelled", command, serial));
+        }
+        let message = error.to_string();
+        if matches!(error.kind(), io::ErrorKind::ConnectionRefused) {
+            return MonoError::AdbUnavailable(message);
+        }
+        if ["not found", "offline", "unauthorized", "no devices"]
+            .iter()
+            .any(|needle| message.contains(needle))
+        {
+            return MonoError::DeviceUnavailable(format!("Device {}: {}", serial, message));
+        }
+        MonoError::CommandFailed {
+            serial: serial.to_string(),
+            command: command.to_string(),
+            exit_code: None,
+            message,
+        }
+    }
+}
+
+impl fmt::Display for MonoError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MonoError::Usage(message)
+            | MonoError::AdbUnavailable(message)
+            | MonoError::DeviceUnavailable(message)
+            | MonoError::BuildFailed(message)
+            | MonoError::Unhealthy(message)
+            | MonoError::Cancelled(message)
+            | MonoError::Other(message) => f.write_str(message),
+            MonoError::CommandFailed {
+                command,
+                exit_code: Some(code),
+                message,
+                ..
+            } => {
+                write!(f, "'{}' exited with status {}", command, code)?;
+                if !message.is_empty() {
+                    write!(f, ": {}", message)?;
+                }
+                Ok(())
+            }
+            MonoError::CommandFailed {
+                command,
+                exit_code: None,
+                message,
+                ..
+            } => write!(f, "'{}' failed: {}", command, message),
+            MonoError::StepFailed {
+                step,
+                rolled_back,
+                cause,
+            } => write!(
+                f,
+                "{} failed ({}): {}",
+                step,
+                if *rolled_back { "rolled back" } else { "rollback incomplete" },
+                cause
+            ),
+        }
+    }
+}
+
+impl std::error::Error for MonoError {}
+
+/// Config mistakes are the user's to fix, like bad arguments.
+impl From<ConfigError> for MonoError {
+    fn from(error: ConfigError) -> Self {
+        MonoError::Usage(error.to_string())
+    }
+}
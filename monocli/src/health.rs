@@ -0,0 +1,289 @@
+//! `monocli check`: how each device and its daemon are doing, read from adb,
+//! the boot service and the daemon itself, through its control socket (see
+//! [`crate::control`]). Daemons that don't answer there are judged by the
+//! status file they keep (see `monocore::status`).
+//!
+//! A device is healthy when its daemon is running, reporting and connected to
+//! the configured server. Anything else is listed in the report's problems
+//! and makes `check` exit with [`MonoError::Unhealthy`]'s code.
+
+use std::fmt::Write as _;
+
+use monocore::status::{DaemonStatus, STATUS_INTERVAL};
+use serde::Serialize;
+
+use crate::control;
+use crate::device::{self, Device, DeviceReport};
+use crate::error::{MonoError, Result};
+use crate::install::STATUS_PATH;
+use crate::service;
+use crate::upgrade::{self, Installed};
+
+/// Prints the version and the folders of `/data/adb`, which tell root
+/// managers apart.
+pub const ROOT_METHOD_COMMAND: &str = "su -c 'su -v 2>/dev/null; ls /data/adb 2>/dev/null'";
+
+/// A status file this many intervals old belongs to a daemon that is stuck.
+const STALE_INTERVALS: u64 = 3;
+
+#[derive(Debug, Serialize)]
+pub struct Health {
+    pub serial: String,
+    pub model: String,
+    /// adb's state for the device: `device` when it is usable, otherwise
+    /// `offline`, `unauthorized` and the like.
+    pub state: String,
+    pub healthy: bool,
+    /// Root manager providing `su`; `None` when the device isn't rooted.
+    pub root: Option<String>,
+    /// The boot service, on rooted devices.
+    pub service: Option<String>,
+    pub pid: Option<u32>,
+    pub uptime_secs: Option<u64>,
+    /// Build of the running daemon, or of the installed one when it isn't
+    /// running.
+    pub version: Option<String>,
+    /// `daemon.server` on this machine.
+    pub server: String,
+    pub connected: Option<bool>,
+    pub last_send_secs_ago: Option<u64>,
+    /// Lines waiting on the device to be sent.
+    pub spool: Option<usize>,
+    /// Lines the daemon dropped since it started because its spool was full.
+    pub dropped: Option<u64>,
+    pub problems: Vec<String>,
+    /// Why the check itself failed, if it did.
+    #[serde(skip)]
+    pub error: Option<MonoError>,
+}
+
+impl Health {
+    fn new(serial: &str, state: &str, server: &str) -> Self {
+        Health {
+            serial: serial.to_string(),
+            model: String::new(),
+            state: state.to_string(),
+            healthy: false,
+            root: None,
+            service: None,
+            pid: None,
+            uptime_secs: None,
+            version: None,
+            server: server.to_string(),
+            connected: None,
+            last_send_secs_ago: None,
+            spool: None,
+            dropped: None,
+            problems: Vec::new(),
+            error: None,
+        }
+    }
+
+    /// A device adb lists but can't talk to.
+    pub fn unavailable(serial: &str, state: &str, server: &str) -> Self {
+        let mut health = Health::new(serial, state, server);
+        let error = MonoError::DeviceUnavailable(format!("Device {} is {}, not ready", serial, state));
+        health.problems.push(error.to_string());
+        health.error = Some(error);
+        health
+    }
+
+    /// 0 when healthy, otherwise the exit code of what is wrong.
+    pub fn exit_code(&self) -> i32 {
+        match &self.error {
+            Some(error) => error.exit_code(),
+            None if self.healthy => 0,
+            None => MonoError::Unhealthy(String::new()).exit_code(),
+        }
+    }
+
+    /// One line for the summary table.
+    pub fn summary(&self) -> String {
+        let mut summary = match (self.pid, &self.version) {
+            (Some(pid), Some(version)) => format!("monodeamon {} pid {}", version, pid),
+            (Some(pid), None) => format!("monodeamon pid {}", pid),
+            (None, _) => "monodeamon not running".to_string(),
+        };
+        if let Some(uptime) = self.uptime_secs {
+            let _ = write!(summary, ", up {}", duration(uptime));
+        }
+        if self.connected == Some(true) {
+            let _ = write!(summary, ", connected to {}", self.server);
+        }
+        if let Some(ago) = self.last_send_secs_ago {
+            let _ = write!(summary, ", last send {} ago", duration(ago));
+        }
+        if let Some(spool) = self.spool {
+            let _ = write!(summary, ", spool {}", spool);
+        }
+        summary
+    }
+
+    /// Everything known about the device, one `label: value` per line.
+    pub fn details(&self) -> Vec<String> {
+        let or_none = |value: Option<String>| value.unwrap_or_else(|| "-".to_string());
+        vec![
+            format!("adb state: {}", self.state),
+            format!("root: {}", self.root.clone().unwrap_or_else(|| "not rooted".to_string())),
+            format!("service: {}", or_none(self.service.clone())),
+            format!("daemon pid: {}", or_none(self.pid.map(|pid| pid.to_string()))),
+            format!("uptime: {}", or_none(self.uptime_secs.map(duration))),
+            format!("version: {}", or_none(self.version.clone())),
+            format!("server: {}", self.server),
+            format!(
+                "connection: {}",
+                or_none(self.connected.map(|connected| if connected { "connected" } else { "disconnected" }.to_string()))
+            ),
+            format!("last send: {}", or_none(self.last_send_secs_ago.map(|ago| format!("{} ago", duration(ago))))),
+            format!(
+                "spool backlog: {}",
+                or_none(self.spool.map(|spool| format!("{} lines, {} dropped", spool, self.dropped.unwrap_or(0))))
+            ),
+        ]
+    }
+
+    /// The summary table's view: the summary when healthy, the problems otherwise.
+    pub fn into_report(self) -> DeviceReport {
+        let summary = self.summary();
+        let result = match (self.healthy, self.error) {
+            (true, _) => Ok(summary),
+            (false, Some(error)) => Err(error),
+            (false, None) => Err(MonoError::Unhealthy(self.problems.join("; "))),
+        };
+        DeviceReport {
+            serial: self.serial,
+            model: self.model,
+            result,
+        }
+    }
+}
+
+/// Checks every device, concurrently, against `server`.
+pub fn check_devices(devices: &[Device], server: &str) -> Vec<Health> {
+    device::map_devices(devices, |device| check(device, server), |device| {
+        let mut health = Health::new(&device.serial, "device", server);
+        health.error = Some(MonoError::Other("worker panicked".to_string()));
+        health
+    })
+}
+
+/// Checks one device. Errors talking to it end up in the report too.
+pub fn check(device: &Device, server: &str) -> Health {
+    let mut health = Health::new(&device.serial, "device", server);
+    health.model = device.getprop("ro.product.model").unwrap_or_default();
+    if let Err(error) = inspect(device, &mut health) {
+        health.problems.push(error.to_string());
+        health.error = Some(error);
+    }
+    health.healthy = health.problems.is_empty();
+    health
+}
+
+fn inspect(device: &Device, health: &mut Health) -> Result<()> {
+    let rooted = device.is_rooted()?;
+    let pids = if rooted {
+        health.root = Some(root_method(&device.shell(ROOT_METHOD_COMMAND)?).to_string());
+        let status = service::status(device)?;
+        health.service = Some(status.to_string());
+        if !status.installed {
+            health.problems.push("the monodeamon service isn't installed; run monocli install".to_string());
+        }
+        status.daemons
+    } else {
+        // pidof exits 1 when nothing matches.
+        device.shell("pidof monodeamon")?.split_whitespace().filter_map(|pid| pid.parse().ok()).collect()
+    };
+
+    let Some(&first_pid) = pids.first() else {
+        health.problems.push("monodeamon isn't running".to_string());
+        return installed_version(device, health);
+    };
+    let (status, now) = match control::status(device) {
+        Ok(status) => {
+            let now = status.updated;
+            (Some(status), now)
+        }
+        Err(e) => {
+            health.problems.push(format!("monodeamon doesn't answer on its control socket: {}", e));
+            read_status(device, rooted)?
+        }
+    };
+    // A status file left by an earlier run has another pid.
+    let Some(status) = status.filter(|status| pids.contains(&status.pid)) else {
+        health.pid = Some(first_pid);
+        health.problems.push("monodeamon writes no status; upgrade it with monocli upgrade".to_string());
+        return installed_version(device, health);
+    };
+
+    health.pid = Some(status.pid);
+    health.uptime_secs = Some(now.saturating_sub(status.started));
+    health.version = Some(status.build.clone());
+    health.connected = Some(status.connected);
+    health.last_send_secs_ago = status.last_send.map(|sent| now.saturating_sub(sent));
+    health.spool = Some(status.spool);
+    health.dropped = Some(status.dropped);
+
+    let silent = now.saturating_sub(status.updated);
+    if silent > STALE_INTERVALS * STATUS_INTERVAL.as_secs() {
+        health.problems.push(format!("monodeamon last reported {} ago; it may be stuck", duration(silent)));
+    }
+    if !status.connected {
+        health.problems.push(format!("monodeamon isn't connected to {}; is monoserve running there?", status.server));
+    }
+    if status.server != health.server {
+        health.problems.push(format!(
+            "monodeamon sends to {} but daemon.server is {}; run monocli service reload to update its config",
+            status.server, health.server
+        ));
+    }
+    Ok(())
+}
+
+fn installed_version(device: &Device, health: &mut Health) -> Result<()> {
+    health.version = match upgrade::binary_build(device)? {
+        Installed::Nothing => None,
+        installed => Some(installed.to_string()),
+    };
+    Ok(())
+}
+
+/// The daemon's status file, if there is one, and the device's clock.
+fn read_status(device: &Device, rooted: bool) -> Result<(Option<DaemonStatus>, u64)> {
+    let command = format!("cat {} 2>/dev/null; echo now=$(date +%s)", STATUS_PATH);
+    // The daemon runs as root under the service, and so owns the file.
+    let output = device.shell(&if rooted { format!("su -c '{}'", command) } else { command })?;
+    let (text, now) = output.trim_end().rsplit_once("now=").unwrap_or(("", ""));
+    let now = now.trim().parse().map_err(|_| MonoError::CommandFailed {
+        serial: device.serial.clone(),
+        command: "date +%s".to_string(),
+        exit_code: None,
+        message: format!("unexpected output: {}", output.trim()),
+    })?;
+    Ok((DaemonStatus::parse(text), now))
+}
+
+/// The root manager named by the output of [`ROOT_METHOD_COMMAND`].
+pub fn root_method(output: &str) -> &'static str {
+    let lower = output.to_lowercase();
+    let has_dir = |name: &str| output.lines().any(|line| line.trim() == name);
+    if lower.contains("kernelsu") || has_dir("ksud") {
+        "KernelSU"
+    } else if lower.contains("apatch") || has_dir("apd") {
+        "APatch"
+    } else if lower.contains("magisk") {
+        "Magisk"
+    } else {
+        "su"
+    }
+}
+
+/// `3d 4h`, `2h 5m`, `4m 10s` or `12s`.
+fn duration(secs: u64) -> String {
+    let (days, hours, minutes, seconds) = (secs / 86_400, secs / 3600 % 24, secs / 60 % 60, secs % 60);
+    match (days, hours, minutes) {
+        (0, 0, 0) => format!("{}s", seconds),
+        (0, 0, _) => format!("{}m {}s", minutes, seconds),
+        (0, _, _) => format!("{}h {}m", hours, minutes),
+        _ => format!("{}d {}h", days, hours),
+    }
+}
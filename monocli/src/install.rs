@@ -0,0 +1,286 @@
+//! `monocli install` as a sequence of steps that each know how to undo
+//! themselves. When a step fails (or Ctrl-C is pressed), the steps before it
+//! are undone in reverse order, so a device keeps its previous install, or
+//! none, rather than half of a new one.
+//!
+//! The daemon runs from the service module described in [`crate::service`].
+
+use std::env;
+use std::fs;
+use std::path::Path;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::cancel;
+use crate::control;
+use crate::device::Device;
+use crate::download;
+use crate::error::{MonoError, Result};
+use crate::manifest::safe_name;
+use crate::service::{self, ServiceStatus, MODULE_STAGING_DIR};
+
+pub const DAEMON_PATH: &str = "/data/local/tmp/monodeamon";
+pub const CONFIG_PATH: &str = "/data/local/tmp/mono.toml";
+/// Written by the daemon next to its binary; see `monocore::status`.
+pub const STATUS_PATH: &str = "/data/local/tmp/monodeamon.status";
+
+/// Files an install replaces, kept as `<path>.bak` until it has succeeded.
+const INSTALLED_FILES: [&str; 2] = [DAEMON_PATH, CONFIG_PATH];
+
+/// How long a freshly started daemon gets to connect to its server.
+pub const START_TIMEOUT: Duration = Duration::from_secs(10);
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+/// How long each rollback command gets. Rollbacks ignore Ctrl-C, which is
+/// usually what started them, so this is what bounds them.
+const UNDO_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// What to install on a device.
+pub struct Install<'a> {
+    /// The daemon built for the device's ABI.
+    pub binary: &'a Path,
+    /// The daemon's `mono.toml`.
+    pub config: &'a str,
+    /// `daemon.server`, which the daemon has to connect to for the install to count.
+    pub server: &'a str,
+    pub timeout: Duration,
+}
+
+/// Installs and starts the daemon, or leaves the device as it was.
+pub fn install_daemon(device: &Device, install: &Install) -> Result<String> {
+    let expected = download::sha256_file(install.binary)
+        .map_err(|e| MonoError::Other(format!("Cannot read {}: {}", install.binary.display(), e)))?;
+    let before = service::status(device)?;
+
+    let mut transaction = Transaction::new(device);
+    // Stopped first so the binary isn't busy, and restarted last on rollback,
+    // once the old files are back.
+    let restart = match (before.supervisor, before.is_running()) {
+        (Some(_), _) => vec![service::start_command()],
+        (None, true) => vec![format!("nohup {} > /dev/null 2>&1 &", DAEMON_PATH)],
+        (None, false) => Vec::new(),
+    };
+    transaction.step("stop the running daemon", restart, || {
+        service::stop_daemon(device, before.is_running(), false)
+    })?;
+    transaction.step("back up the current install", vec![restore_command()], || {
+        device.run(&backup_command()).map(drop)
+    })?;
+    transaction.step("push the daemon", Vec::new(), || device.push(install.binary, DAEMON_PATH, 0o755))?;
+    transaction.step("verify the pushed daemon", Vec::new(), || verify_push(device, DAEMON_PATH, &expected))?;
+    transaction.step("push the daemon config", Vec::new(), || {
+        push_contents(device, install.config, CONFIG_PATH, 0o644)
+    })?;
+    let remove_module = if before.installed { Vec::new() } else { vec![service::remove_module_command()] };
+    transaction.step("install the service module", remove_module, || {
+        for (name, contents, mode) in service::module_files() {
+            push_contents(device, &contents, &format!("{}/{}", MODULE_STAGING_DIR, name), mode)?;
+        }
+        device.run(&service::install_module_command()).map(drop)
+    })?;
+    transaction.step("start the service", vec![service::stop_command(false)], || {
+        device.run(&service::start_command()).map(drop)
+    })?;
+    transaction.step("check the daemon is connected", Vec::new(), || {
+        wait_for_connection(device, install.server, install.timeout)
+    })?;
+    transaction.commit();
+
+    device.log("Log collector daemon installed, set for persistence, and started successfully!");
+    Ok(format!("installed and started, connected to {}", install.server))
+}
+
+/// Replaces the daemon and its config on a device that has the service.
+/// The new files are pushed and verified next to the old ones first, so
+/// logging only stops for the swap and restart. A service that was stopped
+/// with `monocli service stop` is left stopped.
+pub fn upgrade_daemon(device: &Device, install: &Install) -> Result<()> {
+    let expected = download::sha256_file(install.binary)
+        .map_err(|e| MonoError::Other(format!("Cannot read {}: {}", install.binary.display(), e)))?;
+    let before = service::status(device)?;
+    if !before.installed {
+        return Err(MonoError::Other(format!(
+            "The monodeamon service isn't installed on {}; run monocli install first",
+            device.serial
+        )));
+    }
+
+    let staged = |path: &str| format!("{}.new", path);
+    let mut transaction = Transaction::new(device);
+    let remove_staged = vec![format!("rm -f {}", INSTALLED_FILES.map(staged).join(" "))];
+    transaction.step("push the new daemon", remove_staged, || {
+        device.push(install.binary, &staged(DAEMON_PATH), 0o755)
+    })?;
+    transaction.step("verify the pushed daemon", Vec::new(), || {
+        verify_push(device, &staged(DAEMON_PATH), &expected)
+    })?;
+    transaction.step("push the daemon config", Vec::new(), || {
+        push_contents(device, install.config, &staged(CONFIG_PATH), 0o644)
+    })?;
+    let restart = if before.supervisor.is_some() { vec![service::start_command()] } else { Vec::new() };
+    transaction.step("stop the service", restart, || service::stop_daemon(device, before.is_running(), false))?;
+    transaction.step("swap in the new build", vec![restore_command()], || {
+        device.run(&format!("{} && {}", backup_command(), swap_command())).map(drop)
+    })?;
+    if !before.stopped {
+        transaction.step("start the service", vec![service::stop_command(false)], || {
+            device.run(&service::start_command()).map(drop)
+        })?;
+        transaction.step("check the daemon is connected", Vec::new(), || {
+            wait_for_connection(device, install.server, install.timeout)
+        })?;
+    }
+    transaction.commit();
+    Ok(())
+}
+
+/// Fails unless the daemon is running and connected to `server` right now.
+pub fn check_health(device: &Device, server: &str) -> Result<()> {
+    wait_for_connection(device, server, Duration::ZERO)
+}
+
+/// Steps done so far and how to undo them.
+struct Transaction<'a> {
+    device: &'a Device,
+    undo: Vec<(&'static str, Vec<String>)>,
+}
+
+impl<'a> Transaction<'a> {
+    fn new(device: &'a Device) -> Self {
+        Transaction {
+            device,
+            undo: Vec::new(),
+        }
+    }
+
+    /// Runs `action`, undoing every step so far if it fails. Its own `undo`
+    /// commands run too, since a step can fail halfway.
+    fn step(&mut self, name: &'static str, undo: Vec<String>, action: impl FnOnce() -> Result<()>) -> Result<()> {
+        self.undo.push((name, undo));
+        let result = if cancel::requested() {
+            Err(MonoError::Cancelled("Install cancelled".to_string()))
+        } else {
+            self.device.log(&format!("Install: {}", name));
+            action()
+        };
+        result.map_err(|cause| {
+            self.device.log(&format!("Install: {} failed: {}", name, cause));
+            MonoError::StepFailed {
+                step: name.to_string(),
+                rolled_back: self.rollback(),
+                cause: Box::new(cause),
+            }
+        })
+    }
+
+    /// Undoes the steps in reverse order, carrying on past failures so as
+    /// much as possible is cleaned up. Returns whether everything was undone.
+    fn rollback(&mut self) -> bool {
+        let mut clean = true;
+        for (name, commands) in self.undo.drain(..).rev() {
+            if commands.is_empty() {
+                continue;
+            }
+            self.device.log(&format!("Rolling back: {}", name));
+            for command in commands {
+                if let Err(e) = self.device.run_uncancellable(&command, UNDO_TIMEOUT) {
+                    self.device.log(&format!("Rollback of {} failed: {}", name, e));
+                    clean = false;
+                }
+            }
+        }
+        clean
+    }
+
+    /// Keeps the new install; the backups are no longer needed.
+    fn commit(self) {
+        let backups: Vec<String> = INSTALLED_FILES.iter().map(|path| format!("{}.bak", path)).collect();
+        let _ = self
+            .device
+            .run_uncancellable(&format!("rm -rf {} {}", backups.join(" "), MODULE_STAGING_DIR), UNDO_TIMEOUT);
+    }
+}
+
+/// Copies each installed file to `<path>.bak`, and clears stale backups of
+/// files that don't exist, so restoring also removes files that are new.
+fn backup_command() -> String {
+    format!(
+        "for f in {}; do if [ -e $f ]; then cp -p $f $f.bak; else rm -f $f.bak; fi; done",
+        INSTALLED_FILES.join(" ")
+    )
+}
+
+/// Moves the files staged as `<path>.new` into place.
+fn swap_command() -> String {
+    format!("for f in {}; do mv $f.new $f; done", INSTALLED_FILES.join(" "))
+}
+
+fn restore_command() -> String {
+    format!(
+        "for f in {}; do if [ -e $f.bak ]; then mv $f.bak $f; else rm -f $f; fi; done",
+        INSTALLED_FILES.join(" ")
+    )
+}
+
+/// Compares the SHA-256 of the daemon pushed to `remote` with the local build's.
+fn verify_push(device: &Device, remote: &str, expected: &str) -> Result<()> {
+    let command = format!("sha256sum {}", remote);
+    let output = device.run(&command)?;
+    let found = output.split_whitespace().next().unwrap_or("");
+    if found != expected {
+        return Err(MonoError::CommandFailed {
+            serial: device.serial.clone(),
+            command,
+            exit_code: None,
+            message: format!("pushed file has SHA-256 {}, the local build {}", found, expected),
+        });
+    }
+    Ok(())
+}
+
+/// Pushes `contents` through a temp file of its own per device, so
+/// concurrent installs don't overwrite each other's.
+pub fn push_contents(device: &Device, contents: &str, remote: &str, mode: u32) -> Result<()> {
+    let name = remote.rsplit('/').next().unwrap_or(remote);
+    let tmp = env::temp_dir().join(format!("mono_{}_{}", safe_name(&device.serial), name));
+    fs::write(&tmp, contents).map_err(|e| MonoError::Other(format!("Failed to write {}: {}", tmp.display(), e)))?;
+    let pushed = device.push(&tmp, remote, mode);
+    let _ = fs::remove_file(&tmp);
+    pushed
+}
+
+/// Waits for the daemon to be running and to report, through its control
+/// socket, that it has connected to `daemon.server`.
+fn wait_for_connection(device: &Device, server: &str, timeout: Duration) -> Result<()> {
+    let deadline = Instant::now() + timeout;
+    let within = if timeout.is_zero() { String::new() } else { format!(" within {:?}", timeout) };
+    let mut seen = false;
+    let message = loop {
+        let status = ServiceStatus::parse(&device.run(&service::status_command())?);
+        let pid = status.daemons.first().copied();
+        if pid.is_some() {
+            seen = true;
+            // A daemon that has only just started may not be listening yet.
+            if control::status(device).is_ok_and(|daemon| status.daemons.contains(&daemon.pid) && daemon.connected) {
+                return Ok(());
+            }
+        }
+        if Instant::now() >= deadline || cancel::requested() {
+            break match (pid, seen) {
+                (Some(_), _) => format!(
+                    "monodeamon is running but not connected to {}{}; is monoserve running there?",
+                    server, within
+                ),
+                (None, true) => format!("monodeamon started but exited; run {} by hand to see why", DAEMON_PATH),
+                (None, false) => format!("monodeamon isn't running{}; run {} by hand to see why", within, DAEMON_PATH),
+            };
+        }
+        thread::sleep(POLL_INTERVAL);
+    };
+
+    Err(MonoError::CommandFailed {
+        serial: device.serial.clone(),
+        command: service::start_command(),
+        exit_code: None,
+        message,
+    })
+}
@@ -0,0 +1,31 @@
+pub mod abi;
+pub mod adb;
+pub mod cancel;
+pub mod charts;
+pub mod control;
+pub mod device;
+pub mod download;
+pub mod dump;
+pub mod error;
+pub mod health;
+pub mod install;
+pub mod logs;
+pub mod manifest;
+pub mod monitor;
+pub mod ndk;
+pub mod netstats;
+pub mod parsers;
+pub mod profile;
+pub mod report;
+pub mod service;
+pub mod template;
+pub mod transport;
+pub mod upgrade;
+
+pub fn log_message(message: &str) {
+    println!("[INFO] {}", message);
+}
+
+pub fn log_error(message: &str) {
+    eprintln!("[ERROR] {}", message);
+}
@@ -0,0 +1,390 @@
+//! `monocli logs`: live logcat from one or more devices, filtered and
+//! printed as colored text or JSON lines.
+//!
+//! Each device is streamed on its own thread and parsed with
+//! [`monocore::logcat`]. With several devices, records are held back for a
+//! short [`REORDER_WINDOW`] so they can be printed in timestamp order, each
+//! line prefixed with the device serial.
+
+use std::collections::BTreeSet;
+use std::env;
+use std::io::{self, IsTerminal, Write};
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use monocore::logcat::{self, Level, LogRecord};
+use regex::Regex;
+use serde::Serialize;
+
+use crate::cancel;
+use crate::device::Device;
+use crate::error::{MonoError, Result};
+
+/// How long a record may wait for an earlier one from another device.
+pub const REORDER_WINDOW: Duration = Duration::from_millis(250);
+
+/// Logcat for a single device, in its local time.
+const LOGCAT_COMMAND: &str = "logcat -v threadtime";
+/// Records from devices whose clocks are compared get the year and UTC, so
+/// timestamps from different time zones and years still sort.
+const LOGCAT_COMMAND_UTC: &str = "logcat -v threadtime -v year -v UTC";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputFormat {
+    Text { color: bool },
+    Json,
+}
+
+/// Which records to print.
+#[derive(Debug, Clone)]
+pub struct LogFilter {
+    pub min_level: Level,
+    /// Only these tags, if any are given.
+    pub tags: BTreeSet<String>,
+    pub exclude_tags: BTreeSet<String>,
+    /// Only records from processes of these packages, if any are given.
+    pub packages: Vec<String>,
+    /// Matched against the tag and message.
+    pub pattern: Option<Regex>,
+}
+
+impl Default for LogFilter {
+    fn default() -> Self {
+        LogFilter {
+            min_level: Level::Verbose,
+            tags: BTreeSet::new(),
+            exclude_tags: BTreeSet::new(),
+            packages: Vec::new(),
+            pattern: None,
+        }
+    }
+}
+
+impl LogFilter {
+    /// Whether `record` passes every filter except the package one, which
+    /// needs the device's pids.
+    pub fn matches(&self, record: &LogRecord) -> bool {
+        record.level >= self.min_level
+            && (self.tags.is_empty() || self.tags.contains(&record.tag))
+            && !self.exclude_tags.contains(&record.tag)
+            && self
+                .pattern
+                .as_ref()
+                .is_none_or(|pattern| pattern.is_match(&record.tag) || pattern.is_match(&record.message))
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct LogsOptions {
+    pub filter: LogFilter,
+    pub format: OutputFormat,
+}
+
+impl LogsOptions {
+    /// Consumes `--level <L>`, `--tag <tag>`, `--exclude-tag <tag>`,
+    /// `--package <name>`, `--grep <regex>`, `--json` and
+    /// `--color <auto|always|never>` from `args`.
+    ///
+    /// Tags and packages can be repeated or comma separated. Color defaults to
+    /// on when stdout is a terminal and `NO_COLOR` isn't set.
+    pub fn from_args(args: &mut Vec<String>) -> Result<Self> {
+        let mut filter = LogFilter::default();
+        let mut json = false;
+        let mut color = None;
+        let mut rest = Vec::new();
+        let mut iter = args.drain(..);
+
+        while let Some(arg) = iter.next() {
+            let mut value = |name: &str| iter.next().ok_or_else(|| usage(&format!("{} requires a value", name)));
+            match arg.as_str() {
+                "--level" => {
+                    let level = value("--level")?;
+                    filter.min_level = level.parse().map_err(|_| {
+                        usage(&format!("Invalid --level '{}', expected one of V, D, I, W, E, F", level))
+                    })?;
+                }
+                "--tag" => filter.tags.extend(split_list(&value("--tag")?)),
+                "--exclude-tag" => filter.exclude_tags.extend(split_list(&value("--exclude-tag")?)),
+                "--package" => filter.packages.extend(split_list(&value("--package")?)),
+                "--grep" => {
+                    let pattern = value("--grep")?;
+                    filter.pattern = Some(
+                        Regex::new(&pattern).map_err(|e| usage(&format!("Invalid --grep pattern: {}", e)))?,
+                    );
+                }
+                "--json" => json = true,
+                "--color" => {
+                    color = match value("--color")?.as_str() {
+                        "auto" => None,
+                        "always" => Some(true),
+                        "never" => Some(false),
+                        other => return Err(usage(&format!("Invalid --color '{}', expected auto, always or never", other))),
+                    }
+                }
+                _ => rest.push(arg),
+            }
+        }
+
+        drop(iter);
+        *args = rest;
+        let format = if json {
+            OutputFormat::Json
+        } else {
+            let color = color.unwrap_or_else(|| io::stdout().is_terminal() && env::var_os("NO_COLOR").is_none());
+            OutputFormat::Text { color }
+        };
+        Ok(LogsOptions { filter, format })
+    }
+}
+
+/// Streams logcat from every device to `out` until the streams end or Ctrl-C
+/// is pressed, and returns how many records were printed.
+pub fn stream_logs(devices: &[Device], options: &LogsOptions, out: &mut dyn Write) -> Result<usize> {
+    let prefix = devices.len() > 1;
+    let command = if prefix { LOGCAT_COMMAND_UTC } else { LOGCAT_COMMAND };
+    let (sender, receiver) = mpsc::channel::<(usize, LogRecord)>();
+
+    thread::scope(|scope| {
+        let handles: Vec<_> = devices
+            .iter()
+            .enumerate()
+            .map(|(index, device)| {
+                let sender = sender.clone();
+                scope.spawn(move || {
+                    stream_device(device, command, &options.filter, |record| {
+                        // The printer only goes away once every stream has ended.
+                        let _ = sender.send((index, record));
+                    })
+                })
+            })
+            .collect();
+        drop(sender);
+
+        let mut interleaver = Interleaver::new(if prefix { REORDER_WINDOW } else { Duration::ZERO });
+        let mut printed = 0;
+        let mut print = |ready: Vec<(usize, LogRecord)>| -> io::Result<()> {
+            for (index, record) in ready {
+                let serial = prefix.then_some(devices[index].serial.as_str());
+                write_record(out, serial, &record, options.format)?;
+                printed += 1;
+            }
+            out.flush()
+        };
+
+        let written = loop {
+            match receiver.recv_timeout(Duration::from_millis(50)) {
+                Ok((index, record)) => interleaver.push(index, record, Instant::now()),
+                Err(mpsc::RecvTimeoutError::Timeout) => {}
+                Err(mpsc::RecvTimeoutError::Disconnected) => break print(interleaver.drain()),
+            }
+            if let Err(e) = print(interleaver.ready(Instant::now())) {
+                break Err(e);
+            }
+        };
+        let written = match written {
+            Ok(()) => Ok(()),
+            Err(e) => {
+                // Stop the streams; if nobody is reading any more (piped into
+                // `head`, say) that's just the end of the output.
+                cancel::request();
+                match e.kind() {
+                    io::ErrorKind::BrokenPipe => Ok(()),
+                    _ => Err(MonoError::Other(format!("Failed to write logs: {}", e))),
+                }
+            }
+        };
+
+        // Report the first device whose stream failed, once everything it
+        // sent before failing has been printed.
+        for handle in handles {
+            handle
+                .join()
+                .unwrap_or_else(|_| Err(MonoError::Other("worker panicked".to_string())))?;
+        }
+        written.map(|_| printed)
+    })
+}
+
+/// Streams `command` from one device, handing on the records that pass `filter`.
+fn stream_device(device: &Device, command: &str, filter: &LogFilter, mut emit: impl FnMut(LogRecord)) -> Result<()> {
+    let mut pids = if filter.packages.is_empty() {
+        None
+    } else {
+        Some(package_pids(device, &filter.packages)?)
+    };
+
+    let mut partial = Vec::new();
+    let mut handle_line = |line: &[u8]| {
+        let line = String::from_utf8_lossy(line);
+        // Dividers such as "--------- beginning of main" have no header.
+        let Some(record) = logcat::parse_line(line.trim_end_matches('\r')) else {
+            return;
+        };
+        if let Some(pids) = &mut pids {
+            track_started_process(pids, &filter.packages, &record);
+            if !record.pid.is_some_and(|pid| pids.contains(&pid)) {
+                return;
+            }
+        }
+        if filter.matches(&record) {
+            emit(record);
+        }
+    };
+
+    let result = device.shell_stream(command, |chunk| {
+        partial.extend_from_slice(chunk);
+        while let Some(end) = partial.iter().position(|&b| b == b'\n') {
+            let line: Vec<u8> = partial.drain(..=end).collect();
+            handle_line(&line[..end]);
+        }
+    });
+    if !partial.is_empty() {
+        handle_line(&partial);
+    }
+
+    match result {
+        Ok(_) => Ok(()),
+        Err(_) if cancel::requested() => Ok(()),
+        Err(e) => Err(e),
+    }
+}
+
+/// Pids of the packages' running processes, including their `:service`
+/// processes. A package that isn't running yet is picked up when it starts.
+fn package_pids(device: &Device, packages: &[String]) -> Result<BTreeSet<u32>> {
+    let mut pids = BTreeSet::new();
+    for package in packages {
+        let found: Vec<u32> = device
+            .shell(&format!("pidof {}", package))?
+            .split_whitespace()
+            .filter_map(|pid| pid.parse().ok())
+            .collect();
+        if found.is_empty() {
+            device.log(&format!("{} is not running, waiting for it to start", package));
+        }
+        pids.extend(found);
+    }
+    Ok(pids)
+}
+
+/// Adds the pid from ActivityManager's "Start proc 8812:com.example/u0a142"
+/// when it's one of `packages` starting.
+fn track_started_process(pids: &mut BTreeSet<u32>, packages: &[String], record: &LogRecord) {
+    if record.tag != "ActivityManager" {
+        return;
+    }
+    let Some(rest) = record.message.strip_prefix("Start proc ") else {
+        return;
+    };
+    let Some((pid, rest)) = rest.split_once(':') else {
+        return;
+    };
+    let process = rest.split(['/', ' ']).next().unwrap_or("");
+    let package = process.split(':').next().unwrap_or("");
+    if packages.iter().any(|p| p == package) {
+        if let Ok(pid) = pid.parse() {
+            pids.insert(pid);
+        }
+    }
+}
+
+/// Orders records from several numbered streams by timestamp, holding each one back
+/// for `window` in case an earlier one from another stream is still on its
+/// way. Records without a timestamp keep their arrival order.
+pub struct Interleaver {
+    window: Duration,
+    pending: Vec<(Instant, u64, usize, LogRecord)>,
+    sequence: u64,
+}
+
+impl Interleaver {
+    pub fn new(window: Duration) -> Self {
+        Interleaver {
+            window,
+            pending: Vec::new(),
+            sequence: 0,
+        }
+    }
+
+    pub fn push(&mut self, source: usize, record: LogRecord, arrived: Instant) {
+        self.pending.push((arrived, self.sequence, source, record));
+        self.sequence += 1;
+    }
+
+    /// Records that have waited out the window, in timestamp order.
+    pub fn ready(&mut self, now: Instant) -> Vec<(usize, LogRecord)> {
+        self.sort();
+        let mut ready = Vec::new();
+        while self
+            .pending
+            .first()
+            .is_some_and(|(arrived, ..)| now.saturating_duration_since(*arrived) >= self.window)
+        {
+            let (_, _, source, record) = self.pending.remove(0);
+            ready.push((source, record));
+        }
+        ready
+    }
+
+    /// Everything still held back, in timestamp order.
+    pub fn drain(&mut self) -> Vec<(usize, LogRecord)> {
+        self.sort();
+        self.pending.drain(..).map(|(_, _, source, record)| (source, record)).collect()
+    }
+
+    fn sort(&mut self) {
+        self.pending.sort_by_key(|(_, sequence, _, record)| (record.time, *sequence));
+    }
+}
+
+#[derive(Serialize)]
+struct JsonLine<'a> {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    serial: Option<&'a str>,
+    #[serde(flatten)]
+    record: &'a LogRecord,
+}
+
+/// Writes one record as threadtime text (every line prefixed with `serial`
+/// when given) or as a JSON line.
+pub fn write_record(out: &mut dyn Write, serial: Option<&str>, record: &LogRecord, format: OutputFormat) -> io::Result<()> {
+    let color = match format {
+        OutputFormat::Json => {
+            let line = serde_json::to_string(&JsonLine { serial, record }).map_err(io::Error::other)?;
+            return writeln!(out, "{}", line);
+        }
+        OutputFormat::Text { color } => color.then(|| level_color(record.level)),
+    };
+
+    for line in record.to_string().lines() {
+        if let Some(serial) = serial {
+            write!(out, "[{}] ", serial)?;
+        }
+        match color {
+            Some(code) => writeln!(out, "\x1b[{}m{}\x1b[0m", code, line)?,
+            None => writeln!(out, "{}", line)?,
+        }
+    }
+    Ok(())
+}
+
+/// ANSI SGR parameters, in the spirit of `logcat -v color`.
+fn level_color(level: Level) -> &'static str {
+    match level {
+        Level::Fatal | Level::Silent => "1;31",
+        Level::Error => "31",
+        Level::Warn => "33",
+        Level::Info => "32",
+        Level::Debug => "34",
+        Level::Verbose | Level::Unknown => "2",
+    }
+}
+
+fn split_list(value: &str) -> impl Iterator<Item = String> + '_ {
+    value.split(',').map(str::trim).filter(|item| !item.is_empty()).map(str::to_string)
+}
+
+fn usage(message: &str) -> MonoError {
+    MonoError::Usage(message.to_string())
+}
@@ -0,0 +1,119 @@
+//! `manifest.json`: the index of everything captured into a dump folder.
+//!
+//! Each capture command's raw stdout lives in its own file next to the
+//! manifest, so a dump can be re-processed later without the device.
+
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+pub const MANIFEST_FILE: &str = "manifest.json";
+
+/// How a capture command ended.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Outcome {
+    /// Ran to completion; see `exit_code` for whether it succeeded.
+    #[default]
+    Completed,
+    /// Could not be run at all, e.g. the device went away.
+    Failed,
+    /// Abandoned after exceeding its timeout.
+    TimedOut,
+    /// Interrupted or never started because the dump was cancelled.
+    Cancelled,
+    /// Not run because it needs root and the device isn't rooted.
+    Skipped,
+}
+
+/// One capture command and what came back from it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ManifestEntry {
+    pub command: String,
+    /// Report section from the capture profile; dumps from before profiles
+    /// called this `category`.
+    #[serde(alias = "category")]
+    pub section: String,
+    /// Whether the command was run through `su`.
+    #[serde(default)]
+    pub root: bool,
+    /// Path of the raw stdout file, relative to the dump folder.
+    pub file: String,
+    #[serde(default)]
+    pub outcome: Outcome,
+    /// `None` when the command did not run to completion.
+    pub exit_code: Option<i32>,
+    #[serde(default)]
+    pub stderr: String,
+    pub duration_ms: u64,
+    /// Timeout the command ran under.
+    #[serde(default)]
+    pub timeout_ms: Option<u64>,
+    pub bytes: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Manifest {
+    pub serial: String,
+    pub device_name: String,
+    pub timestamp: String,
+    /// Capture profile the dump ran; empty for dumps from before profiles.
+    #[serde(default)]
+    pub profile: String,
+    /// Set when the dump was cut short with Ctrl-C; entries that never ran
+    /// are still listed, marked [`Outcome::Cancelled`].
+    #[serde(default)]
+    pub cancelled: bool,
+    pub entries: Vec<ManifestEntry>,
+    /// CSV of `/proc/net/dev` samples taken during the capture, relative to
+    /// the dump folder; `None` if sampling was disabled or never succeeded.
+    #[serde(default)]
+    pub network_samples: Option<String>,
+}
+
+impl Manifest {
+    pub fn new(serial: &str, device_name: &str, timestamp: &str, profile: &str) -> Self {
+        Manifest {
+            serial: serial.to_string(),
+            device_name: device_name.to_string(),
+            timestamp: timestamp.to_string(),
+            profile: profile.to_string(),
+            cancelled: false,
+            entries: Vec::new(),
+            network_samples: None,
+        }
+    }
+
+    pub fn load(dump_dir: &Path) -> io::Result<Self> {
+        let path = dump_dir.join(MANIFEST_FILE);
+        let text = fs::read_to_string(&path)?;
+        serde_json::from_str(&text)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, format!("Invalid {}: {}", path.display(), e)))
+    }
+
+    pub fn save(&self, dump_dir: &Path) -> io::Result<()> {
+        let text = serde_json::to_string_pretty(self).map_err(io::Error::other)?;
+        fs::write(dump_dir.join(MANIFEST_FILE), text)
+    }
+}
+
+/// Turns a command line into a file name, e.g. `dumpsys battery` -> `dumpsys_battery.txt`.
+pub fn output_file_name(command: &str) -> String {
+    format!("{}.txt", safe_name(command))
+}
+
+/// Keeps letters, digits, `.` and `-`, and turns each run of anything else
+/// into one `_`, so the result is a single path component on any system.
+pub fn safe_name(text: &str) -> String {
+    let mut name = String::new();
+    for c in text.chars() {
+        if c.is_ascii_alphanumeric() || c == '.' || c == '-' {
+            name.push(c);
+        } else if !name.ends_with('_') {
+            name.push('_');
+        }
+    }
+    name.trim_matches(|c| c == '_' || c == '.').to_string()
+}
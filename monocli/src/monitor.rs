@@ -0,0 +1,244 @@
+//! `monocli monitor`: samples CPU, memory, battery and thermal zones at a
+//! fixed interval, for trends a single dump can't show.
+//!
+//! Each run gets its own folder holding `samples.csv` (one
+//! `timestamp,metric,value` row per reading, appended as samples come in),
+//! `samples.json` and a `dashboard.html` of time-series charts.
+
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::Path;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use chrono::{DateTime, FixedOffset, Local, SecondsFormat};
+use monocore::config::Config;
+use serde::{Deserialize, Serialize};
+
+use crate::cancel;
+use crate::device::Device;
+use crate::dump::{get_device_name, run_dir_name};
+use crate::error::{MonoError, Result};
+use crate::netstats::csv_field;
+use crate::parsers::{self, CpuTimes, THERMAL_ZONES_COMMAND};
+use crate::report::{generate_monitor_report, ReportTheme};
+
+pub const SAMPLES_CSV: &str = "samples.csv";
+pub const SAMPLES_JSON: &str = "samples.json";
+
+/// How long a single reading may take before it is left out of the sample.
+const SAMPLE_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Clone)]
+pub struct MonitorOptions {
+    pub interval: Duration,
+    pub duration: Duration,
+}
+
+impl MonitorOptions {
+    /// Reads `monitor.interval` and `monitor.duration`.
+    pub fn from_config(config: &Config) -> Result<Self> {
+        let duration = |key: &str| {
+            let value = config.get(key);
+            parse_duration(value).filter(|d| !d.is_zero()).ok_or_else(|| {
+                MonoError::Usage(format!("Invalid {} '{}', expected a duration such as 2s, 10m or 1h", key, value))
+            })
+        };
+        Ok(MonitorOptions {
+            interval: duration("monitor.interval")?,
+            duration: duration("monitor.duration")?,
+        })
+    }
+}
+
+/// Parses `500ms`, `2s`, `10m`, `1h`, or a bare number of seconds.
+pub fn parse_duration(text: &str) -> Option<Duration> {
+    let text = text.trim();
+    let split = text.find(|c: char| !(c.is_ascii_digit() || c == '.')).unwrap_or(text.len());
+    let (number, unit) = text.split_at(split);
+    let number: f64 = number.parse().ok()?;
+    let seconds = match unit.trim() {
+        "ms" => number / 1000.0,
+        "" | "s" => number,
+        "m" => number * 60.0,
+        "h" => number * 3600.0,
+        _ => return None,
+    };
+    Duration::try_from_secs_f64(seconds).ok()
+}
+
+/// Everything read from the device at one point in time. Readings that
+/// failed or timed out are `None` rather than failing the whole run.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MonitorSample {
+    pub time: DateTime<FixedOffset>,
+    /// Busy share of all CPUs since the previous sample; `None` for the first.
+    pub cpu_percent: Option<f64>,
+    pub mem_used_kb: Option<u64>,
+    pub mem_total_kb: Option<u64>,
+    pub battery_percent: Option<u32>,
+    pub battery_temperature_c: Option<f32>,
+    pub battery_voltage_mv: Option<u32>,
+    /// Degrees Celsius by thermal zone type.
+    pub thermal: BTreeMap<String, f64>,
+}
+
+impl MonitorSample {
+    /// `(metric, value)` pairs as written to the CSV.
+    pub fn metrics(&self) -> Vec<(String, f64)> {
+        let mut metrics = Vec::new();
+        let mut push = |name: &str, value: Option<f64>| {
+            if let Some(value) = value {
+                metrics.push((name.to_string(), value));
+            }
+        };
+        push("cpu_percent", self.cpu_percent);
+        push("mem_used_kb", self.mem_used_kb.map(|kb| kb as f64));
+        push("mem_total_kb", self.mem_total_kb.map(|kb| kb as f64));
+        push("battery_percent", self.battery_percent.map(f64::from));
+        push("battery_temperature_c", self.battery_temperature_c.map(f64::from));
+        push("battery_voltage_mv", self.battery_voltage_mv.map(f64::from));
+        for (zone, celsius) in &self.thermal {
+            metrics.push((format!("thermal.{}", zone), *celsius));
+        }
+        metrics
+    }
+}
+
+/// Samples `device` every `interval` for `duration` (or until Ctrl-C) into
+/// a new timestamped folder under `output_root`, then renders the report.
+/// If the device is lost, the samples taken so far are still saved and
+/// reported before the error is returned.
+pub fn monitor_device(
+    device: &Device,
+    output_root: &Path,
+    options: &MonitorOptions,
+    theme: &ReportTheme,
+) -> Result<String> {
+    let device_name = get_device_name(device)?;
+    let timestamp = Local::now().format("%Y%m%d_%H%M%S").to_string();
+    let dir = output_root.join(run_dir_name(&device_name, &device.serial, &timestamp));
+    fs::create_dir_all(&dir)
+        .map_err(|e| MonoError::Other(format!("Failed to create monitor directory: {}", e)))?;
+
+    let csv_path = dir.join(SAMPLES_CSV);
+    let mut csv = File::create(&csv_path)
+        .and_then(|mut file| writeln!(file, "timestamp,metric,value").map(|_| file))
+        .map_err(|e| MonoError::Other(format!("Failed to write {}: {}", csv_path.display(), e)))?;
+
+    device.log(&format!(
+        "Monitoring every {}s for {}s, Ctrl-C to stop early...",
+        options.interval.as_secs_f64(),
+        options.duration.as_secs_f64()
+    ));
+
+    let started = Instant::now();
+    let mut samples = Vec::new();
+    let mut previous_cpu = None;
+    let mut lost = None;
+    for tick in 1u32.. {
+        let sample = match take_sample(device, &mut previous_cpu) {
+            Ok(sample) => sample,
+            Err(e) => {
+                lost = Some(e);
+                break;
+            }
+        };
+        append_csv(&mut csv, &sample)
+            .map_err(|e| MonoError::Other(format!("Failed to write {}: {}", csv_path.display(), e)))?;
+        samples.push(sample);
+
+        let next = options.interval * tick;
+        if next > options.duration || !wait_until(started + next) {
+            break;
+        }
+    }
+
+    let json_path = dir.join(SAMPLES_JSON);
+    let json = serde_json::to_string_pretty(&samples).map_err(|e| MonoError::Other(e.to_string()))?;
+    fs::write(&json_path, json)
+        .map_err(|e| MonoError::Other(format!("Failed to write {}: {}", json_path.display(), e)))?;
+
+    generate_monitor_report(&dir, &device_name, &timestamp, &samples, theme)?;
+
+    if let Some(e) = lost {
+        device.log(&format!("{} samples saved in {} before the device was lost", samples.len(), dir.display()));
+        return Err(e);
+    }
+    let stopped = if cancel::requested() { ", stopped early" } else { "" };
+    device.log(&format!("{} samples saved in {}{}", samples.len(), dir.display(), stopped));
+    Ok(format!("{} samples in {}{}", samples.len(), dir.display(), stopped))
+}
+
+/// Sleeps until `deadline`; `false` if Ctrl-C was pressed meanwhile.
+fn wait_until(deadline: Instant) -> bool {
+    while !cancel::requested() {
+        let now = Instant::now();
+        if now >= deadline {
+            return true;
+        }
+        thread::sleep((deadline - now).min(Duration::from_millis(100)));
+    }
+    false
+}
+
+fn take_sample(device: &Device, previous_cpu: &mut Option<CpuTimes>) -> Result<MonitorSample> {
+    let time = Local::now().fixed_offset();
+
+    let cpu = read(device, "cat /proc/stat", parsers::parse_proc_stat)?;
+    let cpu_percent = cpu.and_then(|cpu| previous_cpu.and_then(|previous| cpu.usage_since(&previous)));
+    if cpu.is_some() {
+        *previous_cpu = cpu;
+    }
+    let meminfo = read(device, "cat /proc/meminfo", parsers::parse_meminfo)?;
+    let battery = read(device, "dumpsys battery", parsers::parse_battery)?;
+
+    let mut thermal = BTreeMap::new();
+    for zone in read(device, THERMAL_ZONES_COMMAND, parsers::parse_thermal_zones)?.unwrap_or_default() {
+        // Several sensors can share a type; keep them apart.
+        let mut name = zone.name.clone();
+        let mut n = 2;
+        while thermal.contains_key(&name) {
+            name = format!("{}#{}", zone.name, n);
+            n += 1;
+        }
+        thermal.insert(name, zone.celsius);
+    }
+
+    Ok(MonitorSample {
+        time,
+        cpu_percent,
+        mem_used_kb: meminfo.as_ref().map(|mem| mem.used_kb()),
+        mem_total_kb: meminfo.as_ref().map(|mem| mem.total_kb),
+        battery_percent: battery.as_ref().map(|battery| battery.percent()),
+        battery_temperature_c: battery.as_ref().and_then(|battery| battery.temperature_c),
+        battery_voltage_mv: battery.as_ref().and_then(|battery| battery.voltage_mv),
+        thermal,
+    })
+}
+
+/// Runs and parses one reading. A reading that times out, fails or doesn't
+/// parse is just missing from this sample; losing the device is an error.
+fn read<T>(
+    device: &Device,
+    cmd: &str,
+    parser: fn(&str) -> std::result::Result<T, parsers::ParseError>,
+) -> Result<Option<T>> {
+    match device.shell_output_timeout(cmd, SAMPLE_TIMEOUT) {
+        Ok(output) if output.success() => Ok(parser(&output.stdout_lossy()).ok()),
+        Ok(_) => Ok(None),
+        Err(e) if e.kind() == io::ErrorKind::TimedOut || cancel::requested() => Ok(None),
+        Err(e) => Err(MonoError::from_io(&device.serial, cmd, e)),
+    }
+}
+
+fn append_csv(csv: &mut File, sample: &MonitorSample) -> io::Result<()> {
+    let time = sample.time.to_rfc3339_opts(SecondsFormat::Millis, false);
+    let mut rows = String::new();
+    for (metric, value) in sample.metrics() {
+        rows.push_str(&format!("{},{},{}\n", time, csv_field(&metric), value));
+    }
+    csv.write_all(rows.as_bytes())?;
+    csv.flush()
+}
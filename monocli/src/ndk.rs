@@ -0,0 +1,627 @@
+//! Finding the Android NDK, and the clang linker it ships for the host we
+//! build on.
+//!
+//! An NDK is looked for where the Android tooling itself puts or expects
+//! one: `ANDROID_NDK_HOME` / `ANDROID_NDK_ROOT` pointing straight at it, the
+//! `ndk/<version>` folders of an SDK named by `ANDROID_SDK_ROOT` /
+//! `ANDROID_HOME`, Android Studio's default SDK location for the host OS,
+//! and last the NDKs `monocli ndk install` put in the per-user cache.
+//!
+//! `monocli ndk install` reads the same package list as the SDK manager
+//! (`repository2-1.xml`), downloads the archive for this host into the
+//! cache with its published checksum verified, and unpacks it there.
+
+use std::ffi::OsString;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use monocore::config::Config;
+use reqwest::blocking::Client;
+use zip::ZipArchive;
+
+use crate::download::{self, Checksum, Download, Fetched};
+use crate::error::{MonoError, Result};
+use crate::log_message;
+
+/// Android API level the daemon is linked against.
+pub const API_LEVEL: u32 = 21;
+
+/// The OS the NDK's prebuilt toolchain has to run on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HostOs {
+    Linux,
+    MacOs,
+    Windows,
+}
+
+impl HostOs {
+    /// The OS monocli was built for; `None` where the NDK has no toolchain.
+    pub fn current() -> Option<Self> {
+        if cfg!(target_os = "linux") {
+            Some(HostOs::Linux)
+        } else if cfg!(target_os = "macos") {
+            Some(HostOs::MacOs)
+        } else if cfg!(target_os = "windows") {
+            Some(HostOs::Windows)
+        } else {
+            None
+        }
+    }
+
+    /// Folder under `toolchains/llvm/prebuilt`. macOS toolchains are
+    /// universal binaries published as `darwin-x86_64`, Apple silicon included.
+    pub fn prebuilt_tag(self) -> &'static str {
+        match self {
+            HostOs::Linux => "linux-x86_64",
+            HostOs::MacOs => "darwin-x86_64",
+            HostOs::Windows => "windows-x86_64",
+        }
+    }
+
+    /// The `<host-os>` of this host's archives in the SDK repository.
+    pub fn repository_name(self) -> &'static str {
+        match self {
+            HostOs::Linux => "linux",
+            HostOs::MacOs => "macosx",
+            HostOs::Windows => "windows",
+        }
+    }
+
+    /// SDK folder Android Studio creates, relative to the user's home (or
+    /// `%LOCALAPPDATA%` on Windows).
+    fn default_sdk(self) -> &'static str {
+        match self {
+            HostOs::Linux => "Android/Sdk",
+            HostOs::MacOs => "Library/Android/sdk",
+            HostOs::Windows => "Android/Sdk",
+        }
+    }
+}
+
+/// An NDK install and the host it is used from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Ndk {
+    pub root: PathBuf,
+    pub host: HostOs,
+}
+
+impl Ndk {
+    /// The prebuilt clang toolchain's `bin` folder.
+    pub fn bin_dir(&self) -> PathBuf {
+        self.root.join("toolchains/llvm/prebuilt").join(self.host.prebuilt_tag()).join("bin")
+    }
+
+    /// The clang wrapper that links for `clang_target` at `api`, such as
+    /// `aarch64-linux-android21-clang`. On Windows these are `.cmd` scripts.
+    pub fn linker(&self, clang_target: &str, api: u32) -> PathBuf {
+        let suffix = if self.host == HostOs::Windows { ".cmd" } else { "" };
+        self.bin_dir().join(format!("{}{}-clang{}", clang_target, api, suffix))
+    }
+
+    /// `llvm-ar`, the only archiver left in NDK r23 and later.
+    pub fn ar(&self) -> PathBuf {
+        let suffix = if self.host == HostOs::Windows { ".exe" } else { "" };
+        self.bin_dir().join(format!("llvm-ar{}", suffix))
+    }
+}
+
+/// Where an NDK might be, most specific first.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Candidate {
+    /// A folder that should itself be an NDK.
+    Ndk(PathBuf),
+    /// A folder of side-by-side NDKs, one per version.
+    Versions(PathBuf),
+}
+
+impl Candidate {
+    fn path(&self) -> &Path {
+        match self {
+            Candidate::Ndk(path) | Candidate::Versions(path) => path,
+        }
+    }
+}
+
+/// The places to look on `host`, reading environment variables through `var`.
+pub fn candidates(host: HostOs, var: impl Fn(&str) -> Option<OsString>) -> Vec<Candidate> {
+    let mut candidates = Vec::new();
+    for name in ["ANDROID_NDK_HOME", "ANDROID_NDK_ROOT"] {
+        candidates.extend(var(name).map(|path| Candidate::Ndk(PathBuf::from(path))));
+    }
+
+    let mut sdks: Vec<PathBuf> = ["ANDROID_SDK_ROOT", "ANDROID_HOME"]
+        .into_iter()
+        .filter_map(|name| var(name).map(PathBuf::from))
+        .collect();
+    let home = match host {
+        HostOs::Windows => var("LOCALAPPDATA")
+            .map(PathBuf::from)
+            .or_else(|| var("USERPROFILE").map(|profile| Path::new(&profile).join("AppData/Local"))),
+        HostOs::Linux | HostOs::MacOs => var("HOME").map(PathBuf::from),
+    };
+    sdks.extend(home.map(|home| home.join(host.default_sdk())));
+    if host == HostOs::Windows {
+        sdks.push(PathBuf::from(r"C:\Android\Sdk"));
+    }
+    if host == HostOs::Linux {
+        sdks.push(PathBuf::from("/opt/android-sdk"));
+        sdks.push(PathBuf::from("/usr/lib/android-sdk"));
+    }
+
+    let mut push = |candidate: Candidate| {
+        if !candidates.contains(&candidate) {
+            candidates.push(candidate);
+        }
+    };
+    for sdk in sdks {
+        push(Candidate::Versions(sdk.join("ndk")));
+        push(Candidate::Ndk(sdk.join("ndk-bundle")));
+    }
+    if let Some(cache) = cache_dir(host, &var) {
+        push(Candidate::Versions(cache.join("ndk")));
+    }
+    candidates
+}
+
+/// An NDK release as `Pkg.Revision` spells it, e.g. `26.1.10909125` for r26b.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct NdkVersion {
+    pub major: u32,
+    pub minor: u32,
+    pub build: u32,
+}
+
+impl NdkVersion {
+    /// Parses `26.1.10909125`, `26.1`, `26`, or a release name such as
+    /// `r26b` (minor 1), returning how many components were given.
+    fn parse_prefix(text: &str) -> Option<(Self, usize)> {
+        let text = text.trim();
+        if let Some(release) = text.strip_prefix('r') {
+            let split = release.find(|c: char| !c.is_ascii_digit()).unwrap_or(release.len());
+            let (major, letter) = release.split_at(split);
+            let major = major.parse().ok()?;
+            return match letter.as_bytes() {
+                [] => Some((NdkVersion { major, minor: 0, build: 0 }, 1)),
+                [letter @ b'a'..=b'z'] => {
+                    let minor = u32::from(letter - b'a');
+                    Some((NdkVersion { major, minor, build: 0 }, 2))
+                }
+                _ => None,
+            };
+        }
+
+        // Pre-releases such as `26.0.10404224-beta1` sort by their numbers.
+        let numbers = text.split_once('-').map_or(text, |(numbers, _)| numbers);
+        let parts: Vec<u32> = numbers.split('.').map(str::parse).collect::<std::result::Result<_, _>>().ok()?;
+        match parts[..] {
+            [major] => Some((NdkVersion { major, minor: 0, build: 0 }, 1)),
+            [major, minor] => Some((NdkVersion { major, minor, build: 0 }, 2)),
+            [major, minor, build] => Some((NdkVersion { major, minor, build }, 3)),
+            _ => None,
+        }
+    }
+}
+
+impl FromStr for NdkVersion {
+    type Err = String;
+
+    fn from_str(text: &str) -> std::result::Result<Self, Self::Err> {
+        Self::parse_prefix(text)
+            .map(|(version, _)| version)
+            .ok_or_else(|| format!("invalid NDK version '{}'", text))
+    }
+}
+
+impl fmt::Display for NdkVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}.{}", self.major, self.minor, self.build)
+    }
+}
+
+/// A pinned version: every component given has to match, so `26` accepts
+/// any r26 and `26.1` only r26b.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VersionPin {
+    version: NdkVersion,
+    parts: usize,
+    text: String,
+}
+
+impl VersionPin {
+    pub fn matches(&self, version: &NdkVersion) -> bool {
+        let wanted = [self.version.major, self.version.minor, self.version.build];
+        let found = [version.major, version.minor, version.build];
+        wanted[..self.parts] == found[..self.parts]
+    }
+}
+
+impl FromStr for VersionPin {
+    type Err = String;
+
+    fn from_str(text: &str) -> std::result::Result<Self, Self::Err> {
+        let (version, parts) = NdkVersion::parse_prefix(text).ok_or_else(|| format!("invalid NDK version '{}'", text))?;
+        Ok(VersionPin {
+            version,
+            parts,
+            text: text.trim().to_string(),
+        })
+    }
+}
+
+impl fmt::Display for VersionPin {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.text)
+    }
+}
+
+/// Which NDK versions a build may use, from `ndk.version` and `ndk.min_version`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct NdkRequirement {
+    pub pinned: Option<VersionPin>,
+    pub minimum: Option<NdkVersion>,
+}
+
+impl NdkRequirement {
+    pub fn from_config(config: &Config) -> Result<Self> {
+        let pinned = match config.get("ndk.version") {
+            "" => None,
+            _ => Some(config.parse("ndk.version")?),
+        };
+        let minimum = match config.get("ndk.min_version") {
+            "" => None,
+            _ => Some(config.parse("ndk.min_version")?),
+        };
+        Ok(NdkRequirement { pinned, minimum })
+    }
+
+    /// Whether an NDK of `version` is acceptable. One whose version can't
+    /// be read is only used when nothing in particular was asked for.
+    pub fn matches(&self, version: Option<&NdkVersion>) -> bool {
+        match version {
+            Some(version) => {
+                self.pinned.as_ref().is_none_or(|pin| pin.matches(version))
+                    && self.minimum.is_none_or(|minimum| *version >= minimum)
+            }
+            None => *self == NdkRequirement::default(),
+        }
+    }
+}
+
+impl fmt::Display for NdkRequirement {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut parts = Vec::new();
+        if let Some(pin) = &self.pinned {
+            parts.push(format!("ndk.version = {}", pin));
+        }
+        if let Some(minimum) = &self.minimum {
+            parts.push(format!("ndk.min_version = {}", minimum));
+        }
+        match parts.is_empty() {
+            true => f.write_str("any version"),
+            false => f.write_str(&parts.join(", ")),
+        }
+    }
+}
+
+/// An NDK found on disk.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InstalledNdk {
+    pub root: PathBuf,
+    /// `None` when neither `source.properties` nor the folder name says.
+    pub version: Option<NdkVersion>,
+}
+
+/// Every NDK in the searched locations, in search order, with the versions
+/// in an SDK's `ndk` folder newest first.
+pub fn installed(host: HostOs, var: impl Fn(&str) -> Option<OsString>) -> Vec<InstalledNdk> {
+    let mut found: Vec<InstalledNdk> = Vec::new();
+    for candidate in candidates(host, var) {
+        let mut ndks: Vec<InstalledNdk> = match &candidate {
+            Candidate::Ndk(path) if is_ndk(path) => vec![installed_ndk(path)],
+            Candidate::Ndk(_) => Vec::new(),
+            Candidate::Versions(path) => fs::read_dir(path)
+                .into_iter()
+                .flatten()
+                .filter_map(|entry| entry.ok())
+                .map(|entry| entry.path())
+                .filter(|path| is_ndk(path))
+                .map(|path| installed_ndk(&path))
+                .collect(),
+        };
+        ndks.sort_by(|a, b| b.version.cmp(&a.version).then_with(|| a.root.cmp(&b.root)));
+        for ndk in ndks {
+            if !found.iter().any(|f| f.root == ndk.root) {
+                found.push(ndk);
+            }
+        }
+    }
+    found
+}
+
+fn installed_ndk(root: &Path) -> InstalledNdk {
+    let from_properties = fs::read_to_string(root.join("source.properties")).ok().and_then(|text| {
+        text.lines().find_map(|line| {
+            let (key, value) = line.split_once('=')?;
+            (key.trim() == "Pkg.Revision").then(|| value.parse().ok())?
+        })
+    });
+    let from_name = || root.file_name()?.to_str()?.parse().ok();
+    InstalledNdk {
+        root: root.to_path_buf(),
+        version: from_properties.or_else(from_name),
+    }
+}
+
+/// Finds the first NDK in search order that meets `requirement` and has a
+/// toolchain for `host`. Within an SDK that is the newest acceptable version.
+/// An NDK without one is skipped; it's only reported if no NDK qualifies.
+pub fn find_ndk(host: HostOs, var: impl Fn(&str) -> Option<OsString>, requirement: &NdkRequirement) -> Result<Ndk> {
+    let searched = candidates(host, &var);
+    let installed = installed(host, &var);
+
+    let matching: Vec<Ndk> = installed
+        .iter()
+        .filter(|ndk| requirement.matches(ndk.version.as_ref()))
+        .map(|ndk| Ndk {
+            root: ndk.root.clone(),
+            host,
+        })
+        .collect();
+    if let Some(ndk) = matching.iter().find(|ndk| ndk.bin_dir().is_dir()) {
+        return Ok(ndk.clone());
+    }
+    if let Some(ndk) = matching.first() {
+        return Err(MonoError::BuildFailed(format!(
+            "The NDK at {} has no toolchain for this host (expected {})",
+            ndk.root.display(),
+            ndk.bin_dir().display()
+        )));
+    }
+
+    if installed.is_empty() {
+        let searched: Vec<String> = searched.iter().map(|c| format!("  {}", c.path().display())).collect();
+        return Err(MonoError::BuildFailed(format!(
+            "Failed to locate the Android NDK. Set ANDROID_NDK_HOME or install one with the SDK manager. Searched:\n{}",
+            searched.join("\n")
+        )));
+    }
+    let listed: Vec<String> = installed
+        .iter()
+        .map(|ndk| {
+            let version = ndk.version.map_or("unknown version".to_string(), |v| v.to_string());
+            format!("  {}  {}", version, ndk.root.display())
+        })
+        .collect();
+    Err(MonoError::BuildFailed(format!(
+        "No installed NDK meets {}. Installed:\n{}",
+        requirement,
+        listed.join("\n")
+    )))
+}
+
+/// Every NDK since r11 has `source.properties` at its root.
+fn is_ndk(path: &Path) -> bool {
+    path.join("source.properties").is_file() || path.join("toolchains/llvm/prebuilt").is_dir()
+}
+
+/// monocli's per-user cache: `$XDG_CACHE_HOME/mono` (or `~/.cache/mono`) on
+/// Linux, `~/Library/Caches/mono` on macOS, `%LOCALAPPDATA%\\mono\\cache` on
+/// Windows.
+pub fn cache_dir(host: HostOs, var: impl Fn(&str) -> Option<OsString>) -> Option<PathBuf> {
+    match host {
+        HostOs::Linux => var("XDG_CACHE_HOME")
+            .map(PathBuf::from)
+            .or_else(|| var("HOME").map(|home| Path::new(&home).join(".cache")))
+            .map(|cache| cache.join("mono")),
+        HostOs::MacOs => var("HOME").map(|home| Path::new(&home).join("Library/Caches/mono")),
+        HostOs::Windows => var("LOCALAPPDATA").map(|local| Path::new(&local).join("mono").join("cache")),
+    }
+}
+
+/// The SDK repository's package list, relative to `ndk.repository`.
+pub const REPOSITORY_MANIFEST: &str = "repository2-1.xml";
+
+/// A stable NDK release with an archive for the host.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NdkPackage {
+    pub version: NdkVersion,
+    pub url: String,
+    pub size: Option<u64>,
+    pub checksum: Checksum,
+}
+
+impl NdkPackage {
+    pub fn file_name(&self) -> &str {
+        self.url.rsplit('/').next().unwrap_or(&self.url)
+    }
+}
+
+/// Reads the stable `ndk;<version>` packages with an archive for `host` from
+/// an SDK repository manifest, newest first. Archive URLs are resolved
+/// against `base_url`.
+pub fn parse_repository(xml: &str, base_url: &str, host: HostOs) -> Result<Vec<NdkPackage>> {
+    let document = roxmltree::Document::parse(xml)
+        .map_err(|e| MonoError::Other(format!("Invalid SDK repository manifest: {}", e)))?;
+    let root = document.root_element();
+
+    let stable: Vec<&str> = root
+        .children()
+        .filter(|node| node.has_tag_name("channel") && node.text() == Some("stable"))
+        .filter_map(|node| node.attribute("id"))
+        .collect();
+
+    let mut packages = Vec::new();
+    for package in root.children().filter(|node| node.has_tag_name("remotePackage")) {
+        let Some(path_version) = package.attribute("path").and_then(|path| path.strip_prefix("ndk;")) else {
+            continue;
+        };
+        let channel = child(package, "channelRef").and_then(|c| c.attribute("ref"));
+        if channel.is_some_and(|channel| !stable.contains(&channel)) {
+            continue;
+        }
+        let Ok(version) = path_version.parse::<NdkVersion>() else {
+            continue;
+        };
+
+        let archives = child(package, "archives").into_iter().flat_map(|archives| archives.children());
+        for archive in archives.filter(|node| node.has_tag_name("archive")) {
+            if text(archive, "host-os").is_some_and(|os| os != host.repository_name()) {
+                continue;
+            }
+            let Some(complete) = child(archive, "complete") else {
+                continue;
+            };
+            let (Some(url), Some(checksum)) = (text(complete, "url"), child(complete, "checksum")) else {
+                continue;
+            };
+            let hex = checksum.text().unwrap_or("").trim().to_lowercase();
+            let checksum = match checksum.attribute("type").unwrap_or("sha1") {
+                "sha1" => Checksum::Sha1(hex),
+                "sha256" => Checksum::Sha256(hex),
+                _ => continue,
+            };
+            let url = if url.contains("://") {
+                url.to_string()
+            } else {
+                format!("{}/{}", base_url.trim_end_matches('/'), url)
+            };
+            packages.push(NdkPackage {
+                version,
+                url,
+                size: text(complete, "size").and_then(|size| size.parse().ok()),
+                checksum,
+            });
+            break;
+        }
+    }
+    packages.sort_by_key(|package| std::cmp::Reverse(package.version));
+    Ok(packages)
+}
+
+/// Installs the newest stable NDK meeting `requirement` from the repository
+/// at `repository` into `<cache>/ndk/<version>`, unless it is already there.
+///
+/// The archive is kept in `<cache>/downloads`, so reinstalling works
+/// offline, and an interrupted download resumes where it stopped.
+pub fn install_ndk(
+    client: &Client,
+    repository: &str,
+    host: HostOs,
+    cache: &Path,
+    requirement: &NdkRequirement,
+) -> Result<InstalledNdk> {
+    let manifest_url = format!("{}/{}", repository.trim_end_matches('/'), REPOSITORY_MANIFEST);
+    log_message(&format!("Reading {}...", manifest_url));
+    let failed = |e: &dyn fmt::Display| MonoError::Other(format!("Failed to read {}: {}", manifest_url, e));
+    let response = client.get(&manifest_url).send().map_err(|e| failed(&e))?;
+    if !response.status().is_success() {
+        return Err(failed(&response.status()));
+    }
+    let xml = response.text().map_err(|e| failed(&e))?;
+
+    let packages = parse_repository(&xml, repository, host)?;
+    let package = packages.iter().find(|package| requirement.matches(Some(&package.version))).ok_or_else(|| {
+        let available: Vec<String> = packages.iter().take(10).map(|package| package.version.to_string()).collect();
+        MonoError::Usage(format!(
+            "No NDK in the repository meets {} for {}. Newest available: {}",
+            requirement,
+            host.repository_name(),
+            available.join(", ")
+        ))
+    })?;
+
+    let install_dir = cache.join("ndk").join(package.version.to_string());
+    if is_ndk(&install_dir) {
+        log_message(&format!("NDK {} is already installed in {}", package.version, install_dir.display()));
+        return Ok(installed_ndk(&install_dir));
+    }
+
+    let archive = cache.join("downloads").join(package.file_name());
+    log_message(&format!("Downloading {} ({})...", package.url, describe_size(package.size)));
+    let mut reported = 0;
+    let fetched = download::fetch(
+        client,
+        &Download {
+            url: &package.url,
+            checksum: &package.checksum,
+            size: package.size,
+        },
+        &archive,
+        |done, total| {
+            let Some(total) = total.filter(|&total| total > 0) else {
+                return;
+            };
+            let percent = done * 100 / total;
+            if percent >= reported + 10 {
+                reported = percent - percent % 10;
+                log_message(&format!("  {}% of {}", reported, describe_size(Some(total))));
+            }
+        },
+    )?;
+    match fetched {
+        Fetched::Cached => log_message(&format!("Using the verified archive cached in {}", archive.display())),
+        Fetched::Downloaded { resumed_from: 0 } => log_message(&format!("Verified {}", package.checksum)),
+        Fetched::Downloaded { resumed_from } => log_message(&format!(
+            "Resumed after {}, verified {}",
+            describe_size(Some(resumed_from)),
+            package.checksum
+        )),
+    }
+
+    log_message(&format!("Extracting into {}...", install_dir.display()));
+    extract_ndk(&archive, &install_dir)?;
+    Ok(installed_ndk(&install_dir))
+}
+
+/// Unpacks an NDK archive, whose files sit under one `android-ndk-rNN`
+/// folder, so that folder becomes `install_dir`. A failed extraction leaves
+/// nothing behind at `install_dir`.
+fn extract_ndk(archive: &Path, install_dir: &Path) -> Result<()> {
+    let failed = |e: &dyn fmt::Display| MonoError::Other(format!("Failed to extract {}: {}", archive.display(), e));
+    let parent = install_dir.parent().unwrap_or(Path::new("."));
+    let staging = parent.join(format!(
+        ".{}.partial",
+        install_dir.file_name().unwrap_or_default().to_string_lossy()
+    ));
+    let _ = fs::remove_dir_all(&staging);
+
+    let result = (|| {
+        let mut zip = ZipArchive::new(fs::File::open(archive).map_err(|e| failed(&e))?).map_err(|e| failed(&e))?;
+        zip.extract(&staging).map_err(|e| failed(&e))?;
+
+        let entries: Vec<PathBuf> = fs::read_dir(&staging)
+            .map_err(|e| failed(&e))?
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.path())
+            .collect();
+        let top = match &entries[..] {
+            [single] if single.is_dir() => single.clone(),
+            _ => staging.clone(),
+        };
+        if !is_ndk(&top) {
+            return Err(failed(&"the archive doesn't contain an NDK"));
+        }
+        fs::rename(&top, install_dir).map_err(|e| failed(&e))
+    })();
+    let _ = fs::remove_dir_all(&staging);
+    result
+}
+
+fn child<'a, 'input>(node: roxmltree::Node<'a, 'input>, name: &str) -> Option<roxmltree::Node<'a, 'input>> {
+    node.children().find(|child| child.has_tag_name(name))
+}
+
+fn text<'a>(node: roxmltree::Node<'a, '_>, name: &str) -> Option<&'a str> {
+    child(node, name).and_then(|child| child.text()).map(str::trim)
+}
+
+fn describe_size(size: Option<u64>) -> String {
+    match size {
+        Some(size) if size >= 1 << 30 => format!("{:.1} GB", size as f64 / (1u64 << 30) as f64),
+        Some(size) if size >= 1 << 20 => format!("{:.1} MB", size as f64 / (1u64 << 20) as f64),
+        Some(size) => format!("{} bytes", size),
+        None => "unknown size".to_string(),
+    }
+}
@@ -0,0 +1,188 @@
+//! Network throughput, sampled from `/proc/net/dev` while a dump runs.
+//!
+//! The kernel only keeps running totals per interface, so the dump reads them
+//! every `dump.net_interval` seconds and turns consecutive samples into
+//! rx/tx rates. The series is saved in the dump folder as CSV, which is also
+//! what the report's Network Usage chart is drawn from.
+
+use std::borrow::Cow;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use chrono::{DateTime, FixedOffset, Local, SecondsFormat};
+
+use crate::cancel;
+use crate::device::Device;
+use crate::parsers::{parse_net_dev, NetDevCounters};
+
+pub const NET_DEV_COMMAND: &str = "cat /proc/net/dev";
+
+/// Where the series is saved, relative to the dump folder.
+pub const SAMPLES_FILE: &str = "network/net_dev.csv";
+
+const CSV_HEADER: &str = "timestamp,interface,rx_bytes,tx_bytes,rx_bytes_per_sec,tx_bytes_per_sec";
+
+/// All interfaces' counters at one point in time.
+#[derive(Debug, Clone)]
+pub struct NetSample {
+    pub time: DateTime<FixedOffset>,
+    pub interfaces: Vec<NetDevCounters>,
+}
+
+/// One interface at one sample, with its rates since the previous sample.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RateRow {
+    pub time: DateTime<FixedOffset>,
+    pub interface: String,
+    pub rx_bytes: u64,
+    pub tx_bytes: u64,
+    /// `None` for an interface's first sample, or when its counters reset.
+    pub rx_rate: Option<f64>,
+    pub tx_rate: Option<f64>,
+}
+
+/// Reads the counters once; `None` if the command fails or can't be parsed.
+pub fn sample(device: &Device, timeout: Duration) -> Option<NetSample> {
+    let time = Local::now().fixed_offset();
+    let output = device.shell_output_timeout(NET_DEV_COMMAND, timeout).ok()?;
+    if !output.success() {
+        return None;
+    }
+    let interfaces = parse_net_dev(&output.stdout_lossy()).ok()?;
+    Some(NetSample { time, interfaces })
+}
+
+/// Samples every `interval` until `done` is set, then once more so even a
+/// short dump spans at least two samples. Stops early on Ctrl-C.
+pub fn sample_until(device: &Device, interval: Duration, timeout: Duration, done: &AtomicBool) -> Vec<NetSample> {
+    let mut samples = Vec::new();
+    loop {
+        let next = Instant::now() + interval;
+        samples.extend(sample(device, timeout));
+
+        while Instant::now() < next && !done.load(Ordering::SeqCst) && !cancel::requested() {
+            thread::sleep(Duration::from_millis(20).min(interval));
+        }
+        if cancel::requested() {
+            return samples;
+        }
+        if done.load(Ordering::SeqCst) {
+            samples.extend(sample(device, timeout));
+            return samples;
+        }
+    }
+}
+
+/// Turns samples into per-interface rows, in sample order.
+pub fn rates(samples: &[NetSample]) -> Vec<RateRow> {
+    let mut rows = Vec::new();
+    for (index, sample) in samples.iter().enumerate() {
+        let previous = index.checked_sub(1).map(|i| &samples[i]);
+        for counters in &sample.interfaces {
+            let before = previous.and_then(|p| {
+                let elapsed = (sample.time - p.time).num_milliseconds() as f64 / 1000.0;
+                let counters = p.interfaces.iter().find(|c| c.interface == counters.interface)?;
+                (elapsed > 0.0).then_some((counters, elapsed))
+            });
+            let rate = |now: u64, then: u64, elapsed: f64| now.checked_sub(then).map(|delta| delta as f64 / elapsed);
+            rows.push(RateRow {
+                time: sample.time,
+                interface: counters.interface.clone(),
+                rx_bytes: counters.rx_bytes,
+                tx_bytes: counters.tx_bytes,
+                rx_rate: before.and_then(|(b, elapsed)| rate(counters.rx_bytes, b.rx_bytes, elapsed)),
+                tx_rate: before.and_then(|(b, elapsed)| rate(counters.tx_bytes, b.tx_bytes, elapsed)),
+            });
+        }
+    }
+    rows
+}
+
+pub fn write_csv(path: &Path, rows: &[RateRow]) -> io::Result<()> {
+    let rate = |rate: Option<f64>| rate.map_or(String::new(), |r| format!("{:.1}", r));
+    let mut csv = format!("{}\n", CSV_HEADER);
+    for row in rows {
+        csv.push_str(&format!(
+            "{},{},{},{},{},{}\n",
+            row.time.to_rfc3339_opts(SecondsFormat::Millis, false),
+            csv_field(&row.interface),
+            row.rx_bytes,
+            row.tx_bytes,
+            rate(row.rx_rate),
+            rate(row.tx_rate)
+        ));
+    }
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(path, csv)
+}
+
+pub fn read_csv(path: &Path) -> io::Result<Vec<RateRow>> {
+    let text = fs::read_to_string(path)?;
+    let invalid = |line: usize, message: &str| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{}: line {}: {}", path.display(), line, message),
+        )
+    };
+
+    let mut rows = Vec::new();
+    for (index, line) in text.lines().enumerate().skip(1) {
+        if line.is_empty() {
+            continue;
+        }
+        let fields = split_csv_line(line);
+        let [time, interface, rx_bytes, tx_bytes, rx_rate, tx_rate] = &fields[..] else {
+            return Err(invalid(index + 1, "expected 6 fields"));
+        };
+        let number = |field: &str| field.parse::<u64>().map_err(|_| invalid(index + 1, "invalid byte count"));
+        let rate = |field: &str| match field {
+            "" => Ok(None),
+            _ => field.parse::<f64>().map(Some).map_err(|_| invalid(index + 1, "invalid rate")),
+        };
+        rows.push(RateRow {
+            time: DateTime::parse_from_rfc3339(time).map_err(|_| invalid(index + 1, "invalid timestamp"))?,
+            interface: interface.to_string(),
+            rx_bytes: number(rx_bytes)?,
+            tx_bytes: number(tx_bytes)?,
+            rx_rate: rate(rx_rate)?,
+            tx_rate: rate(tx_rate)?,
+        });
+    }
+    Ok(rows)
+}
+
+/// `text` as a CSV field: quoted, with quotes doubled, if it holds a comma,
+/// quote or line break.
+pub fn csv_field(text: &str) -> Cow<'_, str> {
+    if text.contains([',', '"', '\n', '\r']) {
+        Cow::Owned(format!("\"{}\"", text.replace('"', "\"\"")))
+    } else {
+        Cow::Borrowed(text)
+    }
+}
+
+/// Splits a CSV line, undoing [`csv_field`]'s quoting.
+fn split_csv_line(line: &str) -> Vec<String> {
+    let mut fields = vec![String::new()];
+    let mut quoted = false;
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        let field = fields.last_mut().unwrap();
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                chars.next();
+                field.push('"');
+            }
+            '"' => quoted = !quoted,
+            ',' if !quoted => fields.push(String::new()),
+            c => field.push(c),
+        }
+    }
+    fields
+}
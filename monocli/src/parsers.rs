@@ -0,0 +1,800 @@
+//! Typed parsers for the shell outputs the report relies on.
+//!
+//! Android has shipped several generations of these tools (toolbox up to
+//! Android 5, toybox since 6, with column changes along the way), so table
+//! formats are read by header rather than by fixed positions. Every parser
+//! names its format in the error so a broken section is easy to trace back.
+
+use std::collections::BTreeMap;
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    /// Which output this came from, e.g. `"dumpsys battery"`.
+    pub format: &'static str,
+    /// 1-based line number, when the problem is tied to one line.
+    pub line: Option<usize>,
+    pub message: String,
+}
+
+impl ParseError {
+    fn new(format: &'static str, message: impl Into<String>) -> Self {
+        ParseError {
+            format,
+            line: None,
+            message: message.into(),
+        }
+    }
+
+    fn at(format: &'static str, line: usize, message: impl Into<String>) -> Self {
+        ParseError {
+            format,
+            line: Some(line),
+            message: message.into(),
+        }
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.line {
+            Some(line) => write!(f, "{}: line {}: {}", self.format, line, self.message),
+            None => write!(f, "{}: {}", self.format, self.message),
+        }
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+/// System properties from `getprop`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Props(BTreeMap<String, String>);
+
+impl Props {
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.0.get(name).map(|v| v.as_str())
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.0.iter().map(|(k, v)| (k.as_str(), v.as_str()))
+    }
+}
+
+/// Parses `[name]: [value]` lines. Values spanning several lines are joined
+/// back together with `\n`.
+pub fn parse_getprop(text: &str) -> Result<Props, ParseError> {
+    const FORMAT: &str = "getprop";
+    let mut props = BTreeMap::new();
+    let mut open: Option<(String, String)> = None;
+
+    for (index, line) in text.lines().enumerate() {
+        if let Some((name, value)) = open.as_mut() {
+            match line.strip_suffix(']') {
+                Some(rest) => {
+                    value.push('\n');
+                    value.push_str(rest);
+                    props.insert(std::mem::take(name), std::mem::take(value));
+                    open = None;
+                }
+                None => {
+                    value.push('\n');
+                    value.push_str(line);
+                }
+            }
+            continue;
+        }
+
+        let line = line.trim_end();
+        if line.is_empty() {
+            continue;
+        }
+        let (name, value) = line
+            .strip_prefix('[')
+            .and_then(|rest| rest.split_once("]: ["))
+            .ok_or_else(|| ParseError::at(FORMAT, index + 1, format!("expected '[name]: [value]', got '{}'", line)))?;
+
+        match value.strip_suffix(']') {
+            Some(value) => {
+                props.insert(name.to_string(), value.to_string());
+            }
+            None => open = Some((name.to_string(), value.to_string())),
+        }
+    }
+
+    if let Some((name, _)) = open {
+        return Err(ParseError::new(FORMAT, format!("value of '{}' is never closed", name)));
+    }
+    if props.is_empty() {
+        return Err(ParseError::new(FORMAT, "no properties found"));
+    }
+    Ok(Props(props))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BatteryStatus {
+    Unknown,
+    Charging,
+    Discharging,
+    NotCharging,
+    Full,
+}
+
+impl BatteryStatus {
+    /// Values of `BatteryManager.BATTERY_STATUS_*`.
+    fn from_code(code: u32) -> Self {
+        match code {
+            2 => BatteryStatus::Charging,
+            3 => BatteryStatus::Discharging,
+            4 => BatteryStatus::NotCharging,
+            5 => BatteryStatus::Full,
+            _ => BatteryStatus::Unknown,
+        }
+    }
+}
+
+/// `dumpsys battery`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BatteryState {
+    pub level: u32,
+    pub scale: u32,
+    pub status: BatteryStatus,
+    pub health: Option<u32>,
+    pub present: Option<bool>,
+    pub ac_powered: bool,
+    pub usb_powered: bool,
+    pub wireless_powered: bool,
+    pub voltage_mv: Option<u32>,
+    /// Degrees Celsius; dumpsys reports tenths of a degree.
+    pub temperature_c: Option<f32>,
+    pub technology: Option<String>,
+}
+
+impl BatteryState {
+    /// Charge as a percentage, honoring `scale` for devices that don't use 100.
+    pub fn percent(&self) -> u32 {
+        (u64::from(self.level) * 100)
+            .checked_div(u64::from(self.scale))
+            .map_or(self.level, |percent| percent.min(100) as u32)
+    }
+}
+
+pub fn parse_battery(text: &str) -> Result<BatteryState, ParseError> {
+    const FORMAT: &str = "dumpsys battery";
+    let mut fields = BTreeMap::new();
+
+    for line in text.lines() {
+        // The first occurrence wins; vendor sections appended after the
+        // standard block sometimes reuse the same names.
+        if let Some((key, value)) = line.trim().split_once(':') {
+            fields.entry(key.trim().to_string()).or_insert_with(|| value.trim().to_string());
+        }
+    }
+
+    let number = |key: &str| -> Result<Option<u32>, ParseError> {
+        match fields.get(key) {
+            None => Ok(None),
+            Some(value) => value
+                .parse()
+                .map(Some)
+                .map_err(|_| ParseError::new(FORMAT, format!("'{}' is not a number: '{}'", key, value))),
+        }
+    };
+    let flag = |key: &str| fields.get(key).map(|v| v == "true");
+
+    let level = number("level")?.ok_or_else(|| ParseError::new(FORMAT, "missing 'level'"))?;
+    let temperature_c = match fields.get("temperature") {
+        None => None,
+        Some(value) => Some(
+            value
+                .parse::<i32>()
+                .map_err(|_| ParseError::new(FORMAT, format!("'temperature' is not a number: '{}'", value)))?
+                as f32
+                / 10.0,
+        ),
+    };
+
+    Ok(BatteryState {
+        level,
+        scale: number("scale")?.unwrap_or(100),
+        status: BatteryStatus::from_code(number("status")?.unwrap_or(1)),
+        health: number("health")?,
+        present: flag("present"),
+        ac_powered: flag("AC powered").unwrap_or(false),
+        usb_powered: flag("USB powered").unwrap_or(false),
+        wireless_powered: flag("Wireless powered").unwrap_or(false),
+        voltage_mv: number("voltage")?,
+        temperature_c,
+        technology: fields.get("technology").cloned(),
+    })
+}
+
+/// `/proc/meminfo`, all values in kB.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MemInfo {
+    pub total_kb: u64,
+    pub free_kb: u64,
+    /// Only reported by kernels 3.14 and later.
+    pub available_kb: Option<u64>,
+    pub buffers_kb: u64,
+    pub cached_kb: u64,
+    pub swap_total_kb: u64,
+    pub swap_free_kb: u64,
+    /// Every field as listed, including the ones above.
+    pub fields: BTreeMap<String, u64>,
+}
+
+impl MemInfo {
+    /// Memory not available to new allocations. Uses `MemAvailable` where the
+    /// kernel has it, otherwise the classic free + buffers + cached estimate.
+    pub fn used_kb(&self) -> u64 {
+        let available = self
+            .available_kb
+            .unwrap_or(self.free_kb + self.buffers_kb + self.cached_kb);
+        self.total_kb.saturating_sub(available)
+    }
+}
+
+pub fn parse_meminfo(text: &str) -> Result<MemInfo, ParseError> {
+    const FORMAT: &str = "/proc/meminfo";
+    let mut fields = BTreeMap::new();
+
+    for (index, line) in text.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let (key, value) = line
+            .split_once(':')
+            .ok_or_else(|| ParseError::at(FORMAT, index + 1, format!("expected 'Key: value kB', got '{}'", line)))?;
+        let number = value.split_whitespace().next().unwrap_or("");
+        let number = number
+            .parse()
+            .map_err(|_| ParseError::at(FORMAT, index + 1, format!("'{}' is not a number: '{}'", key, number)))?;
+        fields.insert(key.trim().to_string(), number);
+    }
+
+    let total_kb = *fields
+        .get("MemTotal")
+        .ok_or_else(|| ParseError::new(FORMAT, "missing 'MemTotal'"))?;
+    let get = |key: &str| fields.get(key).copied().unwrap_or(0);
+
+    Ok(MemInfo {
+        total_kb,
+        free_kb: get("MemFree"),
+        available_kb: fields.get("MemAvailable").copied(),
+        buffers_kb: get("Buffers"),
+        cached_kb: get("Cached"),
+        swap_total_kb: get("SwapTotal"),
+        swap_free_kb: get("SwapFree"),
+        fields,
+    })
+}
+
+/// One filesystem from `df`, sizes normalized to bytes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Mount {
+    pub filesystem: String,
+    pub size_bytes: u64,
+    pub used_bytes: u64,
+    pub available_bytes: u64,
+    pub mounted_on: String,
+}
+
+impl Mount {
+    pub fn use_percent(&self) -> f64 {
+        if self.size_bytes == 0 {
+            0.0
+        } else {
+            self.used_bytes as f64 * 100.0 / self.size_bytes as f64
+        }
+    }
+}
+
+/// Parses `df`, `df -h` and `df -k` from toybox, and the Android 5 toolbox
+/// layout (`Filesystem Size Used Free Blksize`, which has no mount point
+/// column because the first column already is the mount point).
+pub fn parse_df(text: &str) -> Result<Vec<Mount>, ParseError> {
+    const FORMAT: &str = "df";
+    let mut lines = text.lines().enumerate().filter(|(_, line)| !line.trim().is_empty());
+    let (_, header) = lines.next().ok_or_else(|| ParseError::new(FORMAT, "empty output"))?;
+
+    let columns: Vec<&str> = header.split_whitespace().collect();
+    if columns.first() != Some(&"Filesystem") {
+        return Err(ParseError::at(FORMAT, 1, format!("unrecognized header '{}'", header)));
+    }
+    let toolbox = columns.contains(&"Blksize");
+    // Numbers are 1K blocks unless the header says otherwise or they carry a unit.
+    let block_size: u64 = if columns.iter().any(|c| c.starts_with("512")) { 512 } else { 1024 };
+
+    let mut mounts = Vec::new();
+    let mut pending: Option<String> = None;
+
+    for (index, line) in lines {
+        let mut fields: Vec<&str> = line.split_whitespace().collect();
+
+        // Long device names push the numbers onto the next line.
+        if fields.len() == 1 {
+            pending = Some(fields[0].to_string());
+            continue;
+        }
+        let filesystem = match pending.take() {
+            Some(name) => name,
+            None => fields.remove(0).to_string(),
+        };
+
+        let needed = if toolbox { 3 } else { 5 };
+        if fields.len() < needed {
+            return Err(ParseError::at(FORMAT, index + 1, format!("expected {} columns, got '{}'", needed + 1, line)));
+        }
+
+        let size = |value: &str| {
+            parse_size(value, block_size)
+                .ok_or_else(|| ParseError::at(FORMAT, index + 1, format!("invalid size '{}'", value)))
+        };
+
+        let mount = if toolbox {
+            Mount {
+                mounted_on: filesystem.clone(),
+                filesystem,
+                size_bytes: size(fields[0])?,
+                used_bytes: size(fields[1])?,
+                available_bytes: size(fields[2])?,
+            }
+        } else {
+            Mount {
+                filesystem,
+                size_bytes: size(fields[0])?,
+                used_bytes: size(fields[1])?,
+                available_bytes: size(fields[2])?,
+                mounted_on: fields[4..].join(" "),
+            }
+        };
+        mounts.push(mount);
+    }
+
+    Ok(mounts)
+}
+
+/// `"110G"`, `"2.6M"`, `"4.0K"`, `"0"` or a raw block count, in bytes.
+fn parse_size(value: &str, block_size: u64) -> Option<u64> {
+    let multiplier = match value.chars().last()? {
+        'K' | 'k' => 1u64 << 10,
+        'M' => 1 << 20,
+        'G' => 1 << 30,
+        'T' => 1 << 40,
+        'P' => 1 << 50,
+        c if c.is_ascii_digit() => {
+            return value.parse::<u64>().ok().map(|blocks| blocks * block_size);
+        }
+        _ => return None,
+    };
+    let number: f64 = value[..value.len() - 1].parse().ok()?;
+    Some((number * multiplier as f64).round() as u64)
+}
+
+/// One row of `ps`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProcessEntry {
+    pub user: String,
+    pub pid: u32,
+    pub ppid: Option<u32>,
+    /// Resident set size in kB, when the format has it.
+    pub rss_kb: Option<u64>,
+    pub state: Option<char>,
+    /// `CMD`/`NAME`/`ARGS`, whichever the format provides.
+    pub name: String,
+}
+
+/// Parses `ps`, `ps -A`, `ps -ef` from toybox and the Android 5 toolbox `ps`.
+pub fn parse_ps(text: &str) -> Result<Vec<ProcessEntry>, ParseError> {
+    const FORMAT: &str = "ps";
+    let table = Table::parse(FORMAT, text)?;
+    let user = table.require(&["USER", "UID"])?;
+    let pid = table.require(&["PID"])?;
+    let ppid = table.find(&["PPID"]);
+    let rss = table.find(&["RSS", "RES"]);
+    let state = table.find(&["S"]);
+
+    table
+        .rows
+        .iter()
+        .map(|row| {
+            Ok(ProcessEntry {
+                user: row.fields[user].clone(),
+                pid: row.number(FORMAT, pid)?,
+                ppid: ppid.map(|c| row.number(FORMAT, c)).transpose()?,
+                rss_kb: rss.and_then(|c| parse_size(&row.fields[c], 1024)).map(|bytes| bytes / 1024),
+                state: state.or(row.extra_state).and_then(|c| row.fields[c].chars().next()),
+                name: row.last.clone(),
+            })
+        })
+        .collect()
+}
+
+/// Overall CPU split from `top`, as percentages of total capacity.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct CpuUsage {
+    pub user: f64,
+    pub system: f64,
+    pub iowait: f64,
+    pub irq: f64,
+    pub idle: f64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TopProcess {
+    pub pid: u32,
+    pub user: String,
+    pub cpu_percent: f64,
+    pub mem_percent: Option<f64>,
+    pub name: String,
+}
+
+/// One iteration of `top -n 1`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TopSnapshot {
+    pub cpu: CpuUsage,
+    pub processes: Vec<TopProcess>,
+}
+
+/// Parses toybox `top` (`800%cpu 12%user ...`) and the Android 5 toolbox
+/// `top` (`User 5%, System 3%, IOW 0%, IRQ 0%`).
+pub fn parse_top(text: &str) -> Result<TopSnapshot, ParseError> {
+    const FORMAT: &str = "top";
+    let mut cpu = None;
+    let mut table_start = None;
+
+    for (index, line) in text.lines().enumerate() {
+        let trimmed = line.trim();
+        if cpu.is_none() {
+            cpu = parse_toybox_cpu(trimmed).or_else(|| parse_toolbox_cpu(trimmed));
+        }
+        if trimmed.starts_with("PID ") {
+            table_start = Some(index);
+            break;
+        }
+    }
+
+    let cpu = cpu.ok_or_else(|| ParseError::new(FORMAT, "no CPU summary line found"))?;
+    let Some(start) = table_start else {
+        return Ok(TopSnapshot { cpu, processes: Vec::new() });
+    };
+
+    // Toybox glues the state and CPU columns together as `S[%CPU]`.
+    let body: Vec<String> = text
+        .lines()
+        .skip(start)
+        .map(|line| line.replace("S[%CPU]", "S %CPU"))
+        .collect();
+    let mut table = Table::parse(FORMAT, &body.join("\n"))?;
+    // Toolbox leaves the scheduling policy blank for kernel threads.
+    if let Some(pcy) = table.find(&["PCY"]) {
+        table.fill_blank_column(pcy, |value| matches!(value, "fg" | "bg" | "ta" | "un" | "rt" | "sys"));
+    }
+    let pid = table.require(&["PID"])?;
+    let user = table.require(&["USER", "UID"])?;
+    let cpu_column = table.require(&["%CPU", "CPU%"])?;
+    let mem_column = table.find(&["%MEM"]);
+
+    let percent = |value: &str| value.trim_end_matches('%').parse::<f64>().ok();
+    let processes = table
+        .rows
+        .iter()
+        .map(|row| {
+            Ok(TopProcess {
+                pid: row.number(FORMAT, pid)?,
+                user: row.fields[user].clone(),
+                cpu_percent: percent(&row.fields[cpu_column]).ok_or_else(|| {
+                    ParseError::at(FORMAT, row.line, format!("invalid CPU value '{}'", row.fields[cpu_column]))
+                })?,
+                mem_percent: mem_column.and_then(|c| percent(&row.fields[c])),
+                name: row.last.clone(),
+            })
+        })
+        .collect::<Result<Vec<_>, ParseError>>()?;
+
+    Ok(TopSnapshot { cpu, processes })
+}
+
+fn parse_toybox_cpu(line: &str) -> Option<CpuUsage> {
+    let mut values = BTreeMap::new();
+    for token in line.split_whitespace() {
+        let (number, name) = token.split_once('%')?;
+        values.insert(name, number.parse::<f64>().ok()?);
+    }
+    let total = *values.get("cpu")?;
+    if total <= 0.0 {
+        return None;
+    }
+    let share = |name: &str| values.get(name).copied().unwrap_or(0.0) * 100.0 / total;
+
+    Some(CpuUsage {
+        user: share("user") + share("nice"),
+        system: share("sys"),
+        iowait: share("iow"),
+        irq: share("irq") + share("sirq"),
+        idle: share("idle"),
+    })
+}
+
+fn parse_toolbox_cpu(line: &str) -> Option<CpuUsage> {
+    if !line.starts_with("User ") || !line.contains("System") {
+        return None;
+    }
+    let mut values = BTreeMap::new();
+    for part in line.split(',') {
+        let (name, value) = part.trim().rsplit_once(' ')?;
+        values.insert(name, value.trim_end_matches('%').parse::<f64>().ok()?);
+    }
+    let get = |name: &str| values.get(name).copied().unwrap_or(0.0);
+    let busy = get("User") + get("System") + get("IOW") + get("IRQ");
+
+    Some(CpuUsage {
+        user: get("User"),
+        system: get("System"),
+        iowait: get("IOW"),
+        irq: get("IRQ"),
+        idle: (100.0 - busy).max(0.0),
+    })
+}
+
+/// Traffic counters of one interface from `/proc/net/dev`, totals since boot.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NetDevCounters {
+    pub interface: String,
+    pub rx_bytes: u64,
+    pub rx_packets: u64,
+    pub tx_bytes: u64,
+    pub tx_packets: u64,
+}
+
+/// Parses `/proc/net/dev`: two header lines, then `iface: <8 receive
+/// fields> <8 transmit fields>`. Old kernels don't put a space after the
+/// colon, so the name is split off there rather than by whitespace.
+pub fn parse_net_dev(text: &str) -> Result<Vec<NetDevCounters>, ParseError> {
+    const FORMAT: &str = "/proc/net/dev";
+    let mut interfaces = Vec::new();
+
+    for (index, line) in text.lines().enumerate() {
+        if line.trim().is_empty() || line.contains('|') {
+            continue;
+        }
+        let (name, counters) = line
+            .split_once(':')
+            .ok_or_else(|| ParseError::at(FORMAT, index + 1, format!("expected 'iface: counters', got '{}'", line)))?;
+        let fields = counters
+            .split_whitespace()
+            .map(|field| {
+                field
+                    .parse::<u64>()
+                    .map_err(|_| ParseError::at(FORMAT, index + 1, format!("'{}' is not a number", field)))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        if fields.len() < 16 {
+            return Err(ParseError::at(
+                FORMAT,
+                index + 1,
+                format!("expected 16 counters, got {}", fields.len()),
+            ));
+        }
+        interfaces.push(NetDevCounters {
+            interface: name.trim().to_string(),
+            rx_bytes: fields[0],
+            rx_packets: fields[1],
+            tx_bytes: fields[8],
+            tx_packets: fields[9],
+        });
+    }
+
+    if interfaces.is_empty() {
+        return Err(ParseError::new(FORMAT, "no interfaces listed"));
+    }
+    Ok(interfaces)
+}
+
+/// Aggregate CPU time from the `cpu` line of `/proc/stat`, in clock ticks
+/// since boot. Kernels before 2.6.11 stop after `softirq`, so later fields
+/// default to zero.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CpuTimes {
+    pub user: u64,
+    pub nice: u64,
+    pub system: u64,
+    pub idle: u64,
+    pub iowait: u64,
+    pub irq: u64,
+    pub softirq: u64,
+    pub steal: u64,
+}
+
+impl CpuTimes {
+    /// Guest time is already included in `user` and `nice`.
+    pub fn total(&self) -> u64 {
+        self.user + self.nice + self.system + self.idle + self.iowait + self.irq + self.softirq + self.steal
+    }
+
+    pub fn idle_total(&self) -> u64 {
+        self.idle + self.iowait
+    }
+
+    /// Percentage of CPU time spent busy since `earlier`; `None` if no time
+    /// passed or the counters went backwards.
+    pub fn usage_since(&self, earlier: &CpuTimes) -> Option<f64> {
+        let total = self.total().checked_sub(earlier.total()).filter(|&total| total > 0)?;
+        let idle = self.idle_total().checked_sub(earlier.idle_total())?;
+        Some(total.saturating_sub(idle) as f64 * 100.0 / total as f64)
+    }
+}
+
+pub fn parse_proc_stat(text: &str) -> Result<CpuTimes, ParseError> {
+    const FORMAT: &str = "/proc/stat";
+    let (index, line) = text
+        .lines()
+        .enumerate()
+        .find(|(_, line)| line.split_whitespace().next() == Some("cpu"))
+        .ok_or_else(|| ParseError::new(FORMAT, "missing 'cpu' line"))?;
+
+    let fields = line
+        .split_whitespace()
+        .skip(1)
+        .map(|field| {
+            field
+                .parse::<u64>()
+                .map_err(|_| ParseError::at(FORMAT, index + 1, format!("'{}' is not a number", field)))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    if fields.len() < 4 {
+        return Err(ParseError::at(
+            FORMAT,
+            index + 1,
+            format!("expected at least 4 cpu fields, got {}", fields.len()),
+        ));
+    }
+
+    let field = |i: usize| fields.get(i).copied().unwrap_or(0);
+    Ok(CpuTimes {
+        user: field(0),
+        nice: field(1),
+        system: field(2),
+        idle: field(3),
+        iowait: field(4),
+        irq: field(5),
+        softirq: field(6),
+        steal: field(7),
+    })
+}
+
+/// One `/sys/class/thermal/thermal_zone*` sensor.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ThermalZone {
+    /// The zone's `type`, e.g. `battery` or `cpu-0-0-usr`.
+    pub name: String,
+    pub celsius: f64,
+}
+
+/// Shell loop that prints `<type> <temp>` for every thermal zone.
+pub const THERMAL_ZONES_COMMAND: &str =
+    "for zone in /sys/class/thermal/thermal_zone*; do echo \"$(cat $zone/type) $(cat $zone/temp)\"; done 2>/dev/null";
+
+/// Parses the `<type> <temp>` lines of [`THERMAL_ZONES_COMMAND`]. Most
+/// kernels report millidegrees, a few whole degrees; zones whose
+/// temperature can't be read (offline sensors) are left out.
+pub fn parse_thermal_zones(text: &str) -> Result<Vec<ThermalZone>, ParseError> {
+    const FORMAT: &str = "thermal zones";
+    let zones: Vec<ThermalZone> = text
+        .lines()
+        .filter_map(|line| {
+            let (name, temp) = line.trim().rsplit_once(' ')?;
+            let temp: f64 = temp.parse().ok()?;
+            let celsius = if temp.abs() >= 1000.0 { temp / 1000.0 } else { temp };
+            Some(ThermalZone {
+                name: name.trim().to_string(),
+                celsius,
+            })
+        })
+        .collect();
+
+    if zones.is_empty() {
+        return Err(ParseError::new(FORMAT, "no readable thermal zones"));
+    }
+    Ok(zones)
+}
+
+/// A whitespace separated table whose last column may contain spaces.
+struct Table {
+    format: &'static str,
+    columns: Vec<String>,
+    rows: Vec<Row>,
+}
+
+struct Row {
+    line: usize,
+    /// Every column but the last.
+    fields: Vec<String>,
+    last: String,
+    /// Index of an unlabelled one-letter state column (old toolbox `ps`).
+    extra_state: Option<usize>,
+}
+
+impl Table {
+    fn parse(format: &'static str, text: &str) -> Result<Self, ParseError> {
+        let mut lines = text.lines().enumerate().filter(|(_, line)| !line.trim().is_empty());
+        let (_, header) = lines.next().ok_or_else(|| ParseError::new(format, "empty output"))?;
+        let mut columns: Vec<String> = header.split_whitespace().map(str::to_string).collect();
+        if columns.len() < 2 {
+            return Err(ParseError::at(format, 1, format!("unrecognized header '{}'", header)));
+        }
+        let leading = columns.len() - 1;
+        let has_state = columns.iter().any(|c| c == "S");
+
+        let mut rows = Vec::new();
+        for (index, line) in lines {
+            let mut parts = line.split_whitespace();
+            let mut fields: Vec<String> = parts.by_ref().take(leading).map(str::to_string).collect();
+            if fields.len() < leading {
+                return Err(ParseError::at(
+                    format,
+                    index + 1,
+                    format!("expected {} columns, got '{}'", columns.len(), line.trim()),
+                ));
+            }
+            let mut rest: Vec<&str> = parts.collect();
+
+            // Toolbox `ps` prints the process state between PC and NAME
+            // without a header for it.
+            let mut extra_state = None;
+            if !has_state && rest.len() > 1 && rest[0].len() == 1 && rest[0].chars().all(|c| c.is_ascii_uppercase()) {
+                fields.push(rest.remove(0).to_string());
+                extra_state = Some(fields.len() - 1);
+            }
+
+            rows.push(Row {
+                line: index + 1,
+                fields,
+                last: rest.join(" "),
+                extra_state,
+            });
+        }
+
+        columns.truncate(leading);
+        Ok(Table { format, columns, rows })
+    }
+
+    /// Re-aligns rows where `column` was left empty, which shifts everything
+    /// after it one field to the left.
+    fn fill_blank_column(&mut self, column: usize, is_value: impl Fn(&str) -> bool) {
+        for row in &mut self.rows {
+            if is_value(&row.fields[column]) {
+                continue;
+            }
+            row.fields.insert(column, String::new());
+            if let Some(shifted) = row.fields.pop() {
+                row.last = format!("{} {}", shifted, row.last).trim().to_string();
+            }
+        }
+    }
+
+    fn find(&self, names: &[&str]) -> Option<usize> {
+        names.iter().find_map(|name| self.columns.iter().position(|c| c == name))
+    }
+
+    fn require(&self, names: &[&str]) -> Result<usize, ParseError> {
+        self.find(names)
+            .ok_or_else(|| ParseError::new(self.format, format!("missing {} column", names.join("/"))))
+    }
+}
+
+impl Row {
+    fn number<T: std::str::FromStr>(&self, format: &'static str, column: usize) -> Result<T, ParseError> {
+        self.fields[column]
+            .parse()
+            .map_err(|_| ParseError::at(format, self.line, format!("'{}' is not a number", self.fields[column])))
+    }
+}
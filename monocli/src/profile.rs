@@ -0,0 +1,162 @@
+//! Capture profiles: named lists of the commands `monocli dump` runs.
+//!
+//! The built-in profiles live in `profiles.toml`; mono.toml can add its own
+//! under `[profiles.<name>]` or replace a built-in one of the same name.
+//!
+//! ```toml
+//! [profiles.battery]
+//! description = "Battery drain investigation"
+//! commands = [
+//!     { section = "device", command = "getprop" },
+//!     { section = "power", command = "dumpsys batterystats", timeout = 120 },
+//!     { section = "power", command = "cat /sys/kernel/debug/wakeup_sources", root = true },
+//! ]
+//! ```
+
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::time::Duration;
+
+use monocore::config::{Config, Source};
+use serde::Deserialize;
+
+use crate::error::{MonoError, Result};
+
+const BUILTIN_PROFILES: &str = include_str!("../profiles.toml");
+
+/// One command in a profile.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CaptureCommand {
+    pub command: String,
+    /// Report section the output is shown under, also its folder in the dump.
+    pub section: String,
+    /// Seconds this command may run, overriding `dump.timeout`.
+    #[serde(default)]
+    pub timeout: Option<f64>,
+    /// Run through `su`; skipped on devices that aren't rooted.
+    #[serde(default)]
+    pub root: bool,
+}
+
+impl CaptureCommand {
+    pub fn timeout(&self) -> Option<Duration> {
+        self.timeout.map(Duration::from_secs_f64)
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Profile {
+    #[serde(skip)]
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    pub commands: Vec<CaptureCommand>,
+    /// Built in (`Source::Default`) or the config file that defined it.
+    #[serde(skip, default = "default_source")]
+    pub source: Source,
+}
+
+fn default_source() -> Source {
+    Source::Default
+}
+
+impl Profile {
+    /// Sections in the order they first appear, which is the report order.
+    pub fn sections(&self) -> Vec<&str> {
+        let mut sections: Vec<&str> = Vec::new();
+        for command in &self.commands {
+            if !sections.contains(&command.section.as_str()) {
+                sections.push(&command.section);
+            }
+        }
+        sections
+    }
+
+    fn validate(&self) -> std::result::Result<(), String> {
+        if self.commands.is_empty() {
+            return Err("has no commands".to_string());
+        }
+        for command in &self.commands {
+            if command.command.trim().is_empty() {
+                return Err(format!("has an empty command in section '{}'", command.section));
+            }
+            let valid_section = !command.section.is_empty()
+                && command
+                    .section
+                    .chars()
+                    .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-' || c == '_');
+            if !valid_section {
+                return Err(format!(
+                    "section '{}' of '{}' must be lowercase letters, digits, '-' or '_'",
+                    command.section, command.command
+                ));
+            }
+            if let Some(timeout) = command.timeout {
+                if !(timeout > 0.0 && timeout.is_finite()) {
+                    return Err(format!("timeout of '{}' must be a positive number of seconds", command.command));
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Every profile available to `dump`, by name.
+#[derive(Debug, Clone)]
+pub struct Profiles {
+    profiles: BTreeMap<String, Profile>,
+}
+
+impl Profiles {
+    pub fn builtin() -> Self {
+        let table: toml::Table = BUILTIN_PROFILES.parse().expect("profiles.toml is valid TOML");
+        let mut profiles = Profiles {
+            profiles: BTreeMap::new(),
+        };
+        let section = table["profiles"].as_table().expect("profiles.toml has a [profiles] table");
+        profiles.add_all(section, Source::Default).expect("built-in profiles are valid");
+        profiles
+    }
+
+    /// The built-in profiles plus any `[profiles.<name>]` from the config file.
+    pub fn load(config: &Config) -> Result<Self> {
+        let mut profiles = Profiles::builtin();
+        if let Some(section) = config.section("profiles") {
+            let file = config.file().unwrap_or(Path::new("mono.toml"));
+            profiles
+                .add_all(section, Source::File(file.to_path_buf()))
+                .map_err(|e| MonoError::Usage(format!("{}: {}", file.display(), e)))?;
+        }
+        Ok(profiles)
+    }
+
+    fn add_all(&mut self, section: &toml::Table, source: Source) -> std::result::Result<(), String> {
+        for (name, value) in section {
+            let mut profile: Profile = value
+                .clone()
+                .try_into()
+                .map_err(|e: toml::de::Error| format!("profile '{}': {}", name, e.message()))?;
+            profile.validate().map_err(|e| format!("profile '{}' {}", name, e))?;
+            profile.name = name.clone();
+            profile.source = source.clone();
+            self.profiles.insert(name.clone(), profile);
+        }
+        Ok(())
+    }
+
+    pub fn get(&self, name: &str) -> Result<&Profile> {
+        self.profiles.get(name).ok_or_else(|| {
+            MonoError::Usage(format!(
+                "Unknown capture profile '{}'. Available: {}",
+                name,
+                self.profiles.keys().cloned().collect::<Vec<_>>().join(", ")
+            ))
+        })
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Profile> {
+        self.profiles.values()
+    }
+}
@@ -0,0 +1,522 @@
+use std::cmp::Reverse;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, FixedOffset};
+use plotters::style::RGBColor;
+
+use crate::charts::{doughnut_svg, line_svg, Series, Slice};
+use crate::dump::CapturedData;
+use crate::error::{MonoError, Result};
+use crate::monitor::MonitorSample;
+use crate::netstats::RateRow;
+use crate::parsers::{self, BatteryState, MemInfo, Mount, ParseError, Props, TopSnapshot};
+use crate::template::{escape_html, Context, Template};
+use monocore::logcat::{self, Level, LogRecord};
+
+/// The logcat capture the Logcat card summarizes.
+const LOGCAT_COMMAND: &str = "logcat -d -v threadtime";
+
+/// How many of the most recent errors the Logcat card lists.
+const RECENT_ERRORS: usize = 10;
+
+/// Typed views over the captured text. Anything that fails to parse is kept
+/// in `errors` and shown in the report instead of silently becoming zero.
+#[derive(Default)]
+struct ParsedData {
+    props: Option<Props>,
+    battery: Option<BatteryState>,
+    meminfo: Option<MemInfo>,
+    mounts: Option<Vec<Mount>>,
+    top: Option<TopSnapshot>,
+    logs: Option<Vec<LogRecord>>,
+    errors: Vec<ParseError>,
+}
+
+impl ParsedData {
+    fn from_captured(data: &CapturedData) -> Self {
+        let mut parsed = ParsedData::default();
+        parsed.props = parsed.parse(data, "getprop", parsers::parse_getprop);
+        parsed.battery = parsed.parse(data, "dumpsys battery", parsers::parse_battery);
+        parsed.meminfo = parsed.parse(data, "cat /proc/meminfo", parsers::parse_meminfo);
+        parsed.mounts = parsed.parse(data, "df -h", parsers::parse_df);
+        parsed.top = parsed.parse(data, "top -n 1", parsers::parse_top);
+        parsed.logs = data.command_output(LOGCAT_COMMAND).map(logcat::parse_log);
+        parsed
+    }
+
+    /// Commands the profile didn't capture are skipped without a warning.
+    fn parse<T>(
+        &mut self,
+        data: &CapturedData,
+        cmd: &str,
+        parser: fn(&str) -> std::result::Result<T, ParseError>,
+    ) -> Option<T> {
+        let output = data.command_output(cmd)?;
+        parser(output).map_err(|e| self.errors.push(e)).ok()
+    }
+
+    fn data_mount(&self) -> Option<&Mount> {
+        self.mounts.as_ref()?.iter().find(|m| m.mounted_on == "/data")
+    }
+}
+
+/// Page template and stylesheet used to render `dashboard.html`.
+///
+/// The defaults live in `templates/`; `--template` and `--theme` swap either
+/// one out, e.g. for internal branding.
+pub struct ReportTheme {
+    template: Template,
+    stylesheet: String,
+}
+
+/// Placeholders the dashboard template may use. `device_name` and `timestamp`
+/// are escaped text; the rest is markup built by the report code.
+pub const TEMPLATE_PLACEHOLDERS: [&str; 5] = ["device_name", "timestamp", "stylesheet", "cards", "sections"];
+
+const DEFAULT_TEMPLATE: &str = include_str!("../templates/dashboard.html");
+const DEFAULT_STYLESHEET: &str = include_str!("../templates/theme.css");
+
+impl Default for ReportTheme {
+    fn default() -> Self {
+        ReportTheme {
+            template: Template::parse(DEFAULT_TEMPLATE).expect("built-in dashboard template is valid"),
+            stylesheet: DEFAULT_STYLESHEET.to_string(),
+        }
+    }
+}
+
+impl ReportTheme {
+    pub fn new(template: Option<&Path>, theme: Option<&Path>) -> Result<Self> {
+        let mut report_theme = ReportTheme::default();
+        if let Some(path) = template {
+            let template = Template::load(path).map_err(MonoError::Usage)?;
+            if let Some(name) = template.placeholders().find(|name| !TEMPLATE_PLACEHOLDERS.contains(name)) {
+                return Err(MonoError::Usage(format!(
+                    "{}: unknown placeholder '{{{{{}}}}}', expected one of: {}",
+                    path.display(),
+                    name,
+                    TEMPLATE_PLACEHOLDERS.join(", ")
+                )));
+            }
+            report_theme.template = template;
+        }
+        if let Some(path) = theme {
+            report_theme.stylesheet = fs::read_to_string(path)
+                .map_err(|e| MonoError::Usage(format!("Failed to read {}: {}", path.display(), e)))?;
+        }
+        Ok(report_theme)
+    }
+
+    /// Consumes `--template <file>` and `--theme <file.css>` from `args`.
+    pub fn from_args(args: &mut Vec<String>) -> Result<Self> {
+        let mut template = None;
+        let mut theme = None;
+        let mut rest = Vec::new();
+        let mut iter = args.drain(..);
+
+        while let Some(arg) = iter.next() {
+            match arg.as_str() {
+                "--template" => {
+                    let path = iter.next().ok_or_else(|| MonoError::Usage("--template requires a file".to_string()))?;
+                    template = Some(PathBuf::from(path));
+                }
+                "--theme" => {
+                    let path = iter.next().ok_or_else(|| MonoError::Usage("--theme requires a CSS file".to_string()))?;
+                    theme = Some(PathBuf::from(path));
+                }
+                _ => rest.push(arg),
+            }
+        }
+
+        drop(iter);
+        *args = rest;
+        ReportTheme::new(template.as_deref(), theme.as_deref())
+    }
+}
+
+pub fn generate_html_report(
+    dump_dir: &Path,
+    device_name: &str,
+    timestamp: &str,
+    data: &CapturedData,
+    theme: &ReportTheme,
+) -> Result<()> {
+    let parsed = ParsedData::from_captured(data);
+
+    // Quick stats and charts
+    let mut cards = String::new();
+    add_quick_stats(&mut cards, &parsed);
+    add_chart(&mut cards, "storageChart", "Storage Usage", &parse_storage_info(&parsed));
+    add_chart(&mut cards, "batteryChart", "Battery Status", &parse_battery_info(&parsed));
+    add_chart(&mut cards, "memoryChart", "Memory Usage", &parse_memory_info(&parsed));
+    add_chart(&mut cards, "cpuChart", "CPU Usage", &parse_cpu_info(&parsed));
+    add_chart(&mut cards, "networkChart", "Network Usage", &parse_network_info(&data.network));
+
+    if let Some(logs) = &parsed.logs {
+        add_logcat_summary(&mut cards, logs);
+    }
+
+    if !parsed.errors.is_empty() {
+        add_parse_warnings(&mut cards, &parsed.errors);
+    }
+
+    // Collapsible sections, one per section of the profile that ran
+    let mut sections = String::new();
+    for section in &data.sections {
+        add_collapsible_section(&mut sections, &section_title(&section.name), &section.text);
+    }
+
+    write_dashboard(dump_dir, device_name, timestamp, &cards, &sections, theme)
+}
+
+/// Renders `monocli monitor` samples as time-series charts, through the same
+/// template and theme as dump dashboards.
+pub fn generate_monitor_report(
+    dir: &Path,
+    device_name: &str,
+    timestamp: &str,
+    samples: &[MonitorSample],
+    theme: &ReportTheme,
+) -> Result<()> {
+    let offset = samples.first().map_or(FixedOffset::east_opt(0).unwrap(), |sample| *sample.time.offset());
+    let x_format = |x: &f64| clock_label(*x, &offset);
+    let points = |value: &dyn Fn(&MonitorSample) -> Option<f64>| -> Vec<(f64, f64)> {
+        samples
+            .iter()
+            .filter_map(|sample| Some((sample.time.timestamp_millis() as f64 / 1000.0, value(sample)?)))
+            .collect()
+    };
+    let series = |label: &str, color: RGBColor, value: &dyn Fn(&MonitorSample) -> Option<f64>| Series {
+        label: label.to_string(),
+        color,
+        points: points(value),
+    };
+
+    let mut cards = String::new();
+    add_monitor_summary(&mut cards, samples);
+
+    let cpu = series("CPU", RGBColor(0xFF, 0x9F, 0x40), &|s| s.cpu_percent);
+    add_chart(&mut cards, "cpuChart", "CPU Usage", &line_svg(&[cpu], "%", &x_format));
+
+    let memory = series("Used", RGBColor(0x4B, 0xC0, 0xC0), &|s| s.mem_used_kb.map(|kb| kb as f64 / 1024.0));
+    add_chart(&mut cards, "memoryChart", "Memory Usage", &line_svg(&[memory], "MB", &x_format));
+
+    let battery = series("Level", RGBColor(0x36, 0xA2, 0xEB), &|s| s.battery_percent.map(f64::from));
+    add_chart(&mut cards, "batteryChart", "Battery Level", &line_svg(&[battery], "%", &x_format));
+
+    // The battery and the hottest few zones; every zone is in the CSV.
+    let mut temperatures = vec![series("battery", RGBColor(0x36, 0xA2, 0xEB), &|s| {
+        s.battery_temperature_c.map(f64::from)
+    })];
+    let zones = thermal_stats(samples);
+    let mut hottest: Vec<_> = zones.iter().collect();
+    hottest.sort_by(|a, b| b.1 .2.total_cmp(&a.1 .2));
+    for ((zone, _), color) in hottest.iter().zip(TEMPERATURE_COLORS) {
+        temperatures.push(series(zone, color, &|s| s.thermal.get(zone.as_str()).copied()));
+    }
+    add_chart(&mut cards, "thermalChart", "Temperatures", &line_svg(&temperatures, "°C", &x_format));
+
+    let mut sections = String::new();
+    if !zones.is_empty() {
+        let lines: Vec<String> = zones
+            .iter()
+            .map(|(zone, (min, avg, max))| format!("{}: min {:.1} / avg {:.1} / max {:.1} °C", zone, min, avg, max))
+            .collect();
+        add_collapsible_section(&mut sections, "Thermal Zones", &lines.join("\n"));
+    }
+
+    write_dashboard(dir, device_name, timestamp, &cards, &sections, theme)
+}
+
+const TEMPERATURE_COLORS: [RGBColor; 4] = [
+    RGBColor(0xFF, 0x63, 0x84),
+    RGBColor(0xFF, 0x9F, 0x40),
+    RGBColor(0x99, 0x66, 0xFF),
+    RGBColor(0x4B, 0xC0, 0xC0),
+];
+
+fn write_dashboard(
+    dir: &Path,
+    device_name: &str,
+    timestamp: &str,
+    cards: &str,
+    sections: &str,
+    theme: &ReportTheme,
+) -> Result<()> {
+    let mut context = Context::new();
+    context
+        .text("device_name", device_name)
+        .text("timestamp", timestamp)
+        .markup("stylesheet", &theme.stylesheet)
+        .markup("cards", cards)
+        .markup("sections", sections);
+    let html = theme.template.render(&context).map_err(MonoError::Usage)?;
+
+    let html_file = dir.join("dashboard.html");
+    fs::write(&html_file, html)
+        .map_err(|e| MonoError::Other(format!("Failed to write {}: {}", html_file.display(), e)))
+}
+
+/// Min, average and max temperature of every thermal zone seen.
+fn thermal_stats(samples: &[MonitorSample]) -> BTreeMap<String, (f64, f64, f64)> {
+    let mut readings: BTreeMap<String, Vec<f64>> = BTreeMap::new();
+    for sample in samples {
+        for (zone, celsius) in &sample.thermal {
+            readings.entry(zone.clone()).or_default().push(*celsius);
+        }
+    }
+    readings
+        .into_iter()
+        .map(|(zone, values)| {
+            let min = values.iter().copied().fold(f64::MAX, f64::min);
+            let max = values.iter().copied().fold(f64::MIN, f64::max);
+            let avg = values.iter().sum::<f64>() / values.len() as f64;
+            (zone, (min, avg, max))
+        })
+        .collect()
+}
+
+fn add_monitor_summary(html: &mut String, samples: &[MonitorSample]) {
+    html.push_str("<div class=\"card\">\n<h2>Summary</h2>\n<ul>\n");
+
+    let span = match (samples.first(), samples.last()) {
+        (Some(first), Some(last)) => (last.time - first.time).num_milliseconds() as f64 / 1000.0,
+        _ => 0.0,
+    };
+    html.push_str(&format!("<li>Samples: {} over {:.0}s</li>\n", samples.len(), span));
+
+    let cpu: Vec<f64> = samples.iter().filter_map(|s| s.cpu_percent).collect();
+    if !cpu.is_empty() {
+        let avg = cpu.iter().sum::<f64>() / cpu.len() as f64;
+        let max = cpu.iter().copied().fold(0.0, f64::max);
+        html.push_str(&format!("<li>CPU: avg {:.1}%, peak {:.1}%</li>\n", avg, max));
+    }
+    if let Some(peak) = samples.iter().filter_map(|s| s.mem_used_kb).max() {
+        html.push_str(&format!("<li>Peak Memory Used: {} KB</li>\n", peak));
+    }
+    let levels: Vec<u32> = samples.iter().filter_map(|s| s.battery_percent).collect();
+    if let (Some(first), Some(last)) = (levels.first(), levels.last()) {
+        html.push_str(&format!("<li>Battery: {}% to {}%</li>\n", first, last));
+    }
+    html.push_str("</ul>\n</div>\n");
+}
+
+/// Formats a chart's x value (seconds since the epoch) as a wall-clock time
+/// in `offset`, the device's UTC offset when it was sampled, so a report
+/// redrawn elsewhere shows the same times.
+fn clock_label(x: f64, offset: &FixedOffset) -> String {
+    DateTime::from_timestamp_millis((x * 1000.0) as i64)
+        .map_or(String::new(), |time| time.with_timezone(offset).format("%H:%M:%S").to_string())
+}
+
+fn add_quick_stats(html: &mut String, parsed: &ParsedData) {
+    html.push_str("<div class=\"card\">\n<h2>Quick Stats</h2>\n<ul>\n");
+
+    let prop = |name: &str| {
+        parsed
+            .props
+            .as_ref()
+            .and_then(|props| props.get(name))
+            .unwrap_or("N/A")
+            .to_string()
+    };
+    let model = prop("ro.product.model");
+    let android_version = prop("ro.build.version.release");
+    let total_ram = parsed
+        .meminfo
+        .as_ref()
+        .map_or("N/A".to_string(), |mem| format!("{} KB", mem.total_kb));
+    let total_storage = parsed
+        .data_mount()
+        .map_or("N/A".to_string(), |data| format!("{} KB", data.size_bytes / 1024));
+
+    html.push_str(&format!("<li>Model: {}</li>\n", escape_html(&model)));
+    html.push_str(&format!("<li>Android Version: {}</li>\n", escape_html(&android_version)));
+    html.push_str(&format!("<li>Total RAM: {}</li>\n", total_ram));
+    html.push_str(&format!("<li>Total Storage: {}</li>\n", total_storage));
+
+    html.push_str("</ul>\n</div>\n");
+}
+
+/// Record counts by level, and the latest errors with the first line of their message.
+fn add_logcat_summary(html: &mut String, records: &[LogRecord]) {
+    html.push_str("<div class=\"card\">\n<h2>Logcat</h2>\n<ul>\n");
+    for (level, name) in [
+        (Level::Fatal, "Fatal"),
+        (Level::Error, "Errors"),
+        (Level::Warn, "Warnings"),
+        (Level::Info, "Info"),
+        (Level::Debug, "Debug"),
+        (Level::Verbose, "Verbose"),
+    ] {
+        let count = records.iter().filter(|record| record.level == level).count();
+        html.push_str(&format!("<li>{}: {}</li>\n", name, count));
+    }
+    html.push_str("</ul>\n");
+
+    let errors: Vec<&LogRecord> = records.iter().filter(|record| record.level >= Level::Error).collect();
+    if !errors.is_empty() {
+        html.push_str("<table>\n<tr><th>Time</th><th>Tag</th><th>Message</th></tr>\n");
+        for record in &errors[errors.len().saturating_sub(RECENT_ERRORS)..] {
+            let time = record.time.map_or(String::new(), |time| time.to_string());
+            html.push_str(&format!(
+                "<tr><td>{}</td><td>{}</td><td>{}</td></tr>\n",
+                escape_html(&time),
+                escape_html(&record.tag),
+                escape_html(record.message.lines().next().unwrap_or(""))
+            ));
+        }
+        html.push_str("</table>\n");
+    }
+    html.push_str("</div>\n");
+}
+
+fn add_parse_warnings(html: &mut String, errors: &[ParseError]) {
+    html.push_str("<div class=\"card\">\n<h2>Parser Warnings</h2>\n<ul>\n");
+    for error in errors {
+        html.push_str(&format!("<li>{}</li>\n", escape_html(&error.to_string())));
+    }
+    html.push_str("</ul>\n</div>\n");
+}
+
+fn add_chart(html: &mut String, chart_id: &str, title: &str, svg: &str) {
+    html.push_str(&format!(
+        "<div class=\"card\">\n<h2>{}</h2>\n<div class=\"chart\" id=\"{}\">\n{}\n</div>\n</div>\n",
+        title, chart_id, svg
+    ));
+}
+
+fn add_collapsible_section(html: &mut String, title: &str, content: &str) {
+    html.push_str(&format!("<button class=\"collapsible\">{}</button>\n", title));
+    html.push_str("<div class=\"content\">\n<table>\n");
+    html.push_str("<tr><th>Property</th><th>Value</th></tr>\n");
+    for line in content.lines() {
+        if let Some((key, value)) = line.split_once(':') {
+            html.push_str(&format!(
+                "<tr><td>{}</td><td>{}</td></tr>\n",
+                escape_html(key.trim()),
+                escape_html(value.trim())
+            ));
+        }
+    }
+    html.push_str("</table>\n</div>\n");
+}
+
+/// Heading for a profile section: the classic sections keep their old
+/// titles, anything else is title-cased, e.g. `wifi-debug` -> `Wifi Debug`.
+fn section_title(name: &str) -> String {
+    let title = match name {
+        "device" => "Device Information",
+        "network" => "Network Information",
+        "storage" => "Storage Information",
+        "security" => "Security Information",
+        "settings" => "System Settings",
+        "processes" => "Processes and Services",
+        _ => {
+            return name
+                .split(['-', '_'])
+                .filter(|word| !word.is_empty())
+                .map(|word| {
+                    let mut chars = word.chars();
+                    chars.next().map_or(String::new(), |first| first.to_uppercase().chain(chars).collect())
+                })
+                .collect::<Vec<_>>()
+                .join(" ")
+        }
+    };
+    title.to_string()
+}
+
+fn parse_storage_info(parsed: &ParsedData) -> String {
+    let (used, free) = parsed
+        .data_mount()
+        .map_or((0, 0), |data| (data.used_bytes / 1024, data.available_bytes / 1024));
+
+    doughnut_svg(&[
+        Slice { label: "Used", value: used as f64, color: RGBColor(0xFF, 0x63, 0x84) },
+        Slice { label: "Free", value: free as f64, color: RGBColor(0x36, 0xA2, 0xEB) },
+    ])
+}
+
+fn parse_battery_info(parsed: &ParsedData) -> String {
+    let level = parsed.battery.as_ref().map_or(0, |battery| battery.percent());
+
+    doughnut_svg(&[
+        Slice { label: "Battery", value: level as f64, color: RGBColor(0xFF, 0xCE, 0x56) },
+        Slice { label: "Remaining", value: (100 - level) as f64, color: RGBColor(0xE7, 0xE9, 0xED) },
+    ])
+}
+
+fn parse_memory_info(parsed: &ParsedData) -> String {
+    let (used, free) = parsed
+        .meminfo
+        .as_ref()
+        .map_or((0, 0), |mem| (mem.used_kb(), mem.total_kb - mem.used_kb()));
+
+    doughnut_svg(&[
+        Slice { label: "Used", value: used as f64, color: RGBColor(0x4B, 0xC0, 0xC0) },
+        Slice { label: "Available", value: free as f64, color: RGBColor(0x99, 0x66, 0xFF) },
+    ])
+}
+
+fn parse_cpu_info(parsed: &ParsedData) -> String {
+    let cpu = parsed.top.as_ref().map(|top| top.cpu).unwrap_or_default();
+
+    doughnut_svg(&[
+        Slice { label: "User", value: cpu.user, color: RGBColor(0xFF, 0x9F, 0x40) },
+        Slice { label: "System", value: cpu.system, color: RGBColor(0xFF, 0x63, 0x84) },
+        Slice { label: "Idle", value: cpu.idle, color: RGBColor(0x4B, 0xC0, 0xC0) },
+    ])
+}
+
+/// Rx/tx rates of the busiest interfaces (loopback aside) over the capture,
+/// against the wall-clock time of each sample.
+fn parse_network_info(rows: &[RateRow]) -> String {
+    const COLORS: [RGBColor; 6] = [
+        RGBColor(0x36, 0xA2, 0xEB),
+        RGBColor(0xFF, 0x63, 0x84),
+        RGBColor(0x4B, 0xC0, 0xC0),
+        RGBColor(0xFF, 0x9F, 0x40),
+        RGBColor(0x99, 0x66, 0xFF),
+        RGBColor(0xC9, 0xCB, 0xCF),
+    ];
+
+    let mut interfaces: Vec<&str> = Vec::new();
+    for row in rows {
+        if row.interface != "lo" && !interfaces.contains(&row.interface.as_str()) {
+            interfaces.push(&row.interface);
+        }
+    }
+    let moved = |interface: &str| {
+        let mut samples = rows.iter().filter(|row| row.interface == interface);
+        let first = samples.next().map_or(0, |row| row.rx_bytes + row.tx_bytes);
+        let last = samples.next_back().map_or(first, |row| row.rx_bytes + row.tx_bytes);
+        last.saturating_sub(first)
+    };
+    interfaces.sort_by_key(|interface| Reverse(moved(interface)));
+
+    let mut series = Vec::new();
+    for (index, interface) in interfaces.iter().take(COLORS.len() / 2).enumerate() {
+        let points = |rate: fn(&RateRow) -> Option<f64>| -> Vec<(f64, f64)> {
+            rows.iter()
+                .filter(|row| row.interface == *interface)
+                .filter_map(|row| Some((row.time.timestamp_millis() as f64 / 1000.0, rate(row)?)))
+                .collect()
+        };
+        series.push(Series {
+            label: format!("{} rx", interface),
+            color: COLORS[index * 2],
+            points: points(|row| row.rx_rate),
+        });
+        series.push(Series {
+            label: format!("{} tx", interface),
+            color: COLORS[index * 2 + 1],
+            points: points(|row| row.tx_rate),
+        });
+    }
+
+    let offset = rows.first().map_or(FixedOffset::east_opt(0).unwrap(), |row| *row.time.offset());
+    line_svg(&series, "Bytes/s", &|x| clock_label(*x, &offset))
+}
@@ -0,0 +1,293 @@
+//! The daemon's boot service on rooted devices, packaged as a Magisk-style
+//! module in `/data/adb/modules/monodeamon`. Magisk, KernelSU and APatch run
+//! its `service.sh` once boot completes and list it in their module screens,
+//! where it can be disabled or removed like any other module.
+//!
+//! `service.sh` supervises the daemon, restarting it if it exits, and
+//! records its pid so `monocli service stop` can end both. A `stopped` flag
+//! in the module folder keeps a stopped service stopped across reboots.
+//!
+//! The daemon itself is stopped through its control socket (see
+//! [`crate::control`]), which lets it send its spool first. Daemons from
+//! before the socket are killed instead.
+
+use std::fmt;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use monocore::control::Request;
+
+use crate::control;
+use crate::device::Device;
+use crate::error::{MonoError, Result};
+use crate::install::{CONFIG_PATH, DAEMON_PATH, STATUS_PATH};
+
+pub const MODULE_ID: &str = "monodeamon";
+pub const MODULE_DIR: &str = "/data/adb/modules/monodeamon";
+/// Where the module files are pushed before `su` copies them into place.
+pub const MODULE_STAGING_DIR: &str = "/data/local/tmp/monodeamon_module";
+
+/// Left behind by installs from before the module: `/etc/init.d` isn't run
+/// on current Android, so these never started anything.
+const LEGACY_FILES: [&str; 2] = ["/data/local/tmp/monodeamon_service.sh", "/etc/init.d/monodeamon_service.sh"];
+
+const SERVICE_SCRIPT: &str = r#"#!/system/bin/sh
+# Started by the root manager once boot completes, and by `monocli service start`.
+MODDIR=${0%/*}
+[ -f "$MODDIR/stopped" ] && exit 0
+echo $$ > "$MODDIR/service.pid"
+while true; do
+    /data/local/tmp/monodeamon
+    sleep 10
+done
+"#;
+
+/// `(file name, contents, mode)` of the module's files.
+pub fn module_files() -> Vec<(&'static str, String, u32)> {
+    let module_prop = format!(
+        "id={}\nname=Mono log collector\nversion=v{}\nversionCode=1\nauthor=mono\n\
+         description=Runs monodeamon, which forwards logcat to monoserve. Manage with monocli service start|stop|status.\n",
+        MODULE_ID,
+        env!("CARGO_PKG_VERSION")
+    );
+    vec![
+        ("module.prop", module_prop, 0o644),
+        ("service.sh", SERVICE_SCRIPT.to_string(), 0o755),
+    ]
+}
+
+/// What `monocli service status` reports.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ServiceStatus {
+    /// The module is in `/data/adb/modules`.
+    pub installed: bool,
+    /// Turned off in the root manager; it won't start at the next boot.
+    pub disabled: bool,
+    /// Marked for removal in the root manager, at the next boot.
+    pub removing: bool,
+    /// Stopped with `monocli service stop`.
+    pub stopped: bool,
+    /// Pid of the `service.sh` loop, if it is running.
+    pub supervisor: Option<u32>,
+    /// Pids of running daemons, supervised or not.
+    pub daemons: Vec<u32>,
+}
+
+impl ServiceStatus {
+    /// Reads the output of [`status_command`].
+    pub fn parse(output: &str) -> Self {
+        let mut status = ServiceStatus::default();
+        for line in output.lines().map(str::trim) {
+            match line.split_once('=') {
+                Some(("supervisor", pid)) => status.supervisor = pid.parse().ok(),
+                Some(("daemon", pids)) => status.daemons = pids.split_whitespace().filter_map(|pid| pid.parse().ok()).collect(),
+                _ => match line {
+                    "installed" => status.installed = true,
+                    "disabled" => status.disabled = true,
+                    "removing" => status.removing = true,
+                    "stopped" => status.stopped = true,
+                    _ => {}
+                },
+            }
+        }
+        status
+    }
+
+    pub fn is_running(&self) -> bool {
+        !self.daemons.is_empty()
+    }
+}
+
+impl fmt::Display for ServiceStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let pids: Vec<String> = self.daemons.iter().map(u32::to_string).collect();
+        match (self.is_running(), self.supervisor.is_some()) {
+            (true, true) => write!(f, "running (pid {})", pids.join(", "))?,
+            (true, false) => write!(f, "running unsupervised (pid {})", pids.join(", "))?,
+            (false, true) => f.write_str("restarting")?,
+            (false, false) => f.write_str("not running")?,
+        }
+        if !self.installed {
+            return f.write_str(", service not installed");
+        }
+        let notes = [
+            (self.stopped, "stopped with monocli service stop"),
+            (self.disabled, "disabled in the root manager"),
+            (self.removing, "removal pending reboot"),
+        ];
+        for (_, note) in notes.iter().filter(|(set, _)| *set) {
+            write!(f, ", {}", note)?;
+        }
+        Ok(())
+    }
+}
+
+fn su(command: &str) -> String {
+    format!("su -c '{}'", command)
+}
+
+/// Prints one line per flag of the module, then the pids.
+pub fn status_command() -> String {
+    su(&format!(
+        "d={}; [ -f $d/module.prop ] && echo installed; [ -f $d/disable ] && echo disabled; \
+         [ -f $d/remove ] && echo removing; [ -f $d/stopped ] && echo stopped; \
+         p=$(cat $d/service.pid 2>/dev/null) && kill -0 $p 2>/dev/null && echo supervisor=$p; \
+         echo daemon=$(pidof monodeamon)",
+        MODULE_DIR
+    ))
+}
+
+/// Clears the `stopped` flag and starts the `service.sh` loop in the background.
+pub fn start_command() -> String {
+    su(&format!(
+        "rm -f {dir}/stopped; nohup sh {dir}/service.sh > /dev/null 2>&1 &",
+        dir = MODULE_DIR
+    ))
+}
+
+/// Ends the `service.sh` loop, so it doesn't restart the daemon. A `sticky`
+/// stop also sets the `stopped` flag, so the service stays stopped after a
+/// reboot.
+pub fn stop_supervisor_command(sticky: bool) -> String {
+    su(&supervisor_stop(sticky))
+}
+
+fn supervisor_stop(sticky: bool) -> String {
+    format!(
+        "{}if [ -f {dir}/service.pid ]; then kill $(cat {dir}/service.pid) 2>/dev/null; rm -f {dir}/service.pid; fi",
+        if sticky { format!("touch {}/stopped; ", MODULE_DIR) } else { String::new() },
+        dir = MODULE_DIR
+    )
+}
+
+/// Ends the `service.sh` loop and kills the daemon, for when there is no
+/// asking it: rollbacks, and daemons without a control socket.
+pub fn stop_command(sticky: bool) -> String {
+    su(&format!("{}; pkill -x monodeamon; true", supervisor_stop(sticky)))
+}
+
+/// Copies the module files, already pushed to [`MODULE_STAGING_DIR`], into place.
+pub fn install_module_command() -> String {
+    su(&format!(
+        "mkdir -p {dir} && cp {staging}/* {dir}/ && chmod 644 {dir}/module.prop && chmod 755 {dir}/service.sh",
+        dir = MODULE_DIR,
+        staging = MODULE_STAGING_DIR
+    ))
+}
+
+pub fn remove_module_command() -> String {
+    su(&format!("rm -rf {}", MODULE_DIR))
+}
+
+/// Fails unless `su` works, which everything here needs.
+fn require_root(device: &Device) -> Result<()> {
+    if !device.is_rooted()? {
+        return Err(MonoError::Other(format!(
+            "Device {} isn't rooted; the monodeamon service needs su",
+            device.serial
+        )));
+    }
+    Ok(())
+}
+
+/// Stops the service and, if `running`, asks the daemon to stop once its
+/// spool is sent, falling back to killing it when it doesn't answer.
+pub fn stop_daemon(device: &Device, running: bool, sticky: bool) -> Result<()> {
+    if !running {
+        return device.run(&stop_command(sticky)).map(drop);
+    }
+    device.run(&stop_supervisor_command(sticky))?;
+    match control::request(device, Request::Stop) {
+        Ok(note) => device.log(&format!("monodeamon {}", note)),
+        Err(e) => {
+            device.log(&format!("monodeamon didn't stop through its control socket, killing it: {}", e));
+            device.run(&stop_command(sticky))?;
+        }
+    }
+    Ok(())
+}
+
+pub fn status(device: &Device) -> Result<ServiceStatus> {
+    require_root(device)?;
+    Ok(ServiceStatus::parse(&device.run(&status_command())?))
+}
+
+/// `monocli service status`.
+pub fn report_status(device: &Device) -> Result<String> {
+    let status = status(device)?;
+    device.log(&format!("monodeamon service: {}", status));
+    Ok(status.to_string())
+}
+
+/// `monocli service start`: starts the service unless it's already running,
+/// and waits up to `timeout` for the daemon to come up.
+pub fn start(device: &Device, timeout: Duration) -> Result<String> {
+    let status = status(device)?;
+    if !status.installed {
+        return Err(MonoError::Other(format!(
+            "The monodeamon service isn't installed on {}; run monocli install first",
+            device.serial
+        )));
+    }
+    if status.supervisor.is_some() {
+        return Ok(format!("already {}", status));
+    }
+    if status.disabled {
+        device.log("The module is disabled in the root manager, so it won't start at boot");
+    }
+    device.run(&start_command())?;
+
+    let deadline = Instant::now() + timeout;
+    loop {
+        let status = ServiceStatus::parse(&device.run(&status_command())?);
+        if status.is_running() {
+            return Ok(format!("started, {}", status));
+        }
+        if Instant::now() >= deadline {
+            return Err(MonoError::CommandFailed {
+                serial: device.serial.clone(),
+                command: start_command(),
+                exit_code: None,
+                message: format!("monodeamon didn't start; run {} by hand to see why", DAEMON_PATH),
+            });
+        }
+        thread::sleep(Duration::from_millis(500));
+    }
+}
+
+/// `monocli service stop`: stops the daemon until `monocli service start`,
+/// reboots included.
+pub fn stop(device: &Device) -> Result<String> {
+    let status = status(device)?;
+    if !status.installed && !status.is_running() {
+        return Ok(status.to_string());
+    }
+    stop_daemon(device, status.is_running(), status.installed)?;
+    Ok("stopped".to_string())
+}
+
+/// Stops the daemon and removes the module, the daemon's files and anything
+/// older installs left behind.
+pub fn remove(device: &Device) -> Result<String> {
+    if device.is_rooted()? {
+        let status = ServiceStatus::parse(&device.run(&status_command())?);
+        stop_daemon(device, status.is_running(), false)?;
+        device.run(&remove_module_command())?;
+        device.run(&su(&format!("rm -f {}", LEGACY_FILES.join(" "))))?;
+    } else {
+        // Only an unsupervised daemon from an older install can be running,
+        // which may predate the control socket; pkill exits 1 when there is none.
+        if control::request(device, Request::Stop).is_err() {
+            device.shell("pkill -x monodeamon")?;
+        }
+    }
+    device.run(&format!(
+        "rm -rf {daemon} {daemon}.bak {config} {config}.bak {status} {staging}",
+        daemon = DAEMON_PATH,
+        config = CONFIG_PATH,
+        status = STATUS_PATH,
+        staging = MODULE_STAGING_DIR
+    ))?;
+    device.log("Log collector daemon removed successfully.");
+    Ok("removed".to_string())
+}
@@ -0,0 +1,112 @@
+//! Minimal `{{name}}` templates for the HTML reports.
+//!
+//! Values are escaped as they are added to a [`Context`] unless they are
+//! explicitly added as markup, so device output can't end up in a page raw
+//! no matter which template renders it.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+/// Escapes text for use in HTML element content and quoted attribute values.
+pub fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Values available to a template, already escaped where needed.
+#[derive(Default)]
+pub struct Context {
+    values: BTreeMap<String, String>,
+}
+
+impl Context {
+    pub fn new() -> Self {
+        Context::default()
+    }
+
+    /// Adds plain text, escaped for HTML.
+    pub fn text(&mut self, name: &str, value: &str) -> &mut Self {
+        self.values.insert(name.to_string(), escape_html(value));
+        self
+    }
+
+    /// Adds trusted markup as-is. Never pass device output here.
+    pub fn markup(&mut self, name: &str, value: &str) -> &mut Self {
+        self.values.insert(name.to_string(), value.to_string());
+        self
+    }
+}
+
+enum Part {
+    Literal(String),
+    Placeholder(String),
+}
+
+pub struct Template {
+    parts: Vec<Part>,
+}
+
+impl Template {
+    pub fn parse(source: &str) -> Result<Self, String> {
+        let mut parts = Vec::new();
+        let mut rest = source;
+
+        while let Some(start) = rest.find("{{") {
+            let line = source[..source.len() - rest.len() + start].lines().count().max(1);
+            let end = rest[start..]
+                .find("}}")
+                .ok_or_else(|| format!("line {}: unterminated '{{{{'", line))?;
+            let name = rest[start + 2..start + end].trim();
+            if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+                return Err(format!("line {}: invalid placeholder '{{{{{}}}}}'", line, name));
+            }
+
+            parts.push(Part::Literal(rest[..start].to_string()));
+            parts.push(Part::Placeholder(name.to_string()));
+            rest = &rest[start + end + 2..];
+        }
+        parts.push(Part::Literal(rest.to_string()));
+
+        Ok(Template { parts })
+    }
+
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let source = fs::read_to_string(path).map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+        Template::parse(&source).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    pub fn placeholders(&self) -> impl Iterator<Item = &str> {
+        self.parts.iter().filter_map(|part| match part {
+            Part::Placeholder(name) => Some(name.as_str()),
+            Part::Literal(_) => None,
+        })
+    }
+
+    pub fn render(&self, context: &Context) -> Result<String, String> {
+        let mut output = String::new();
+        for part in &self.parts {
+            match part {
+                Part::Literal(text) => output.push_str(text),
+                Part::Placeholder(name) => {
+                    let value = context
+                        .values
+                        .get(name)
+                        .ok_or_else(|| format!("Unknown template placeholder '{{{{{}}}}}'", name))?;
+                    output.push_str(value);
+                }
+            }
+        }
+        Ok(output)
+    }
+}
@@ -0,0 +1,335 @@
+//! The device operations the rest of monocli is built on.
+//!
+//! Everything that touches a phone goes through [`DeviceTransport`], so the
+//! capture, install and report code can run against a real device over adb or
+//! against recorded fixtures with no device attached.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::adb::{AdbClient, ShellOutput};
+
+pub trait DeviceTransport: Send + Sync {
+    /// Serial used to identify the device in logs and dump names.
+    fn serial(&self) -> &str;
+
+    fn shell(&self, cmd: &str) -> io::Result<ShellOutput>;
+
+    /// Runs `cmd`, failing with [`io::ErrorKind::TimedOut`] if it takes longer
+    /// than `timeout`. Transports that can't block ignore the limit.
+    fn shell_timeout(&self, cmd: &str, timeout: Duration) -> io::Result<ShellOutput> {
+        let _ = timeout;
+        self.shell(cmd)
+    }
+
+    /// Like [`shell_timeout`](Self::shell_timeout), but carries on after
+    /// Ctrl-C, for cleanup that has to run once work is cancelled.
+    /// Transports that don't watch for Ctrl-C have nothing to ignore.
+    fn shell_uncancellable(&self, cmd: &str, timeout: Duration) -> io::Result<ShellOutput> {
+        self.shell_timeout(cmd, timeout)
+    }
+
+    /// Runs `cmd`, handing stdout to `on_stdout` as it arrives, and returns
+    /// the exit status. Transports that can't stream deliver it all at once.
+    fn shell_stream(&self, cmd: &str, on_stdout: &mut dyn FnMut(&[u8])) -> io::Result<i32> {
+        let output = self.shell(cmd)?;
+        on_stdout(&output.stdout);
+        Ok(output.exit_code)
+    }
+
+    fn push(&self, local: &Path, remote: &str, mode: u32) -> io::Result<()>;
+
+    fn pull(&self, remote: &str, local: &Path) -> io::Result<u64>;
+
+    fn getprop(&self, name: &str) -> io::Result<String> {
+        Ok(self.shell(&format!("getprop {}", name))?.stdout_lossy().trim().to_string())
+    }
+
+    /// Forwards a local TCP port to `remote` on the device and returns the
+    /// port, until [`remove_forward`](Self::remove_forward). Only live
+    /// devices can do this.
+    fn forward(&self, remote: &str) -> io::Result<u16> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            format!("can't forward to {} without a live device", remote),
+        ))
+    }
+
+    fn remove_forward(&self, port: u16) -> io::Result<()> {
+        let _ = port;
+        Ok(())
+    }
+}
+
+/// A device reached through the adb server.
+pub struct AdbTransport {
+    client: AdbClient,
+    serial: String,
+}
+
+impl AdbTransport {
+    pub fn new(client: &AdbClient, serial: &str) -> Self {
+        AdbTransport {
+            client: client.clone(),
+            serial: serial.to_string(),
+        }
+    }
+}
+
+impl DeviceTransport for AdbTransport {
+    fn serial(&self) -> &str {
+        &self.serial
+    }
+
+    fn shell(&self, cmd: &str) -> io::Result<ShellOutput> {
+        self.client.shell(&self.serial, cmd)
+    }
+
+    fn shell_timeout(&self, cmd: &str, timeout: Duration) -> io::Result<ShellOutput> {
+        self.client.shell_with_timeout(&self.serial, cmd, Some(timeout))
+    }
+
+    fn shell_uncancellable(&self, cmd: &str, timeout: Duration) -> io::Result<ShellOutput> {
+        self.client.shell_uncancellable(&self.serial, cmd, timeout)
+    }
+
+    fn shell_stream(&self, cmd: &str, on_stdout: &mut dyn FnMut(&[u8])) -> io::Result<i32> {
+        self.client.shell_stream(&self.serial, cmd, on_stdout, |_| {})
+    }
+
+    fn push(&self, local: &Path, remote: &str, mode: u32) -> io::Result<()> {
+        self.client.push(&self.serial, local, remote, mode)
+    }
+
+    fn pull(&self, remote: &str, local: &Path) -> io::Result<u64> {
+        self.client.pull(&self.serial, remote, local)
+    }
+
+    fn forward(&self, remote: &str) -> io::Result<u16> {
+        self.client.forward(&self.serial, remote)
+    }
+
+    fn remove_forward(&self, port: u16) -> io::Result<()> {
+        self.client.remove_forward(&self.serial, port)
+    }
+}
+
+/// One recorded shell invocation.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RecordedCommand {
+    pub stdout: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub stderr: String,
+    #[serde(default)]
+    pub exit_code: i32,
+}
+
+/// On-disk fixture format shared by [`ReplayTransport`] and [`RecordingTransport`].
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Fixture {
+    pub serial: String,
+    /// Shell command line to its recorded result.
+    #[serde(default)]
+    pub commands: BTreeMap<String, RecordedCommand>,
+    /// Remote path to file contents served by `pull`.
+    #[serde(default)]
+    pub files: BTreeMap<String, String>,
+}
+
+impl Fixture {
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let text = fs::read_to_string(path)?;
+        serde_json::from_str(&text).map_err(|e| {
+            io::Error::new(io::ErrorKind::InvalidData, format!("Invalid fixture {}: {}", path.display(), e))
+        })
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let text = serde_json::to_string_pretty(self).map_err(io::Error::other)?;
+        fs::write(path, text)
+    }
+}
+
+/// Serves shell and pull requests from a [`Fixture`] instead of a device.
+///
+/// Commands that were never recorded behave like a missing binary (exit 127)
+/// so a replayed dump still completes, just with that section empty. Pushes
+/// are kept in memory so install flows can be asserted on.
+pub struct ReplayTransport {
+    fixture: Fixture,
+    pushed: Mutex<Vec<(String, u32, Vec<u8>)>>,
+}
+
+impl ReplayTransport {
+    pub fn new(fixture: Fixture) -> Self {
+        ReplayTransport {
+            fixture,
+            pushed: Mutex::new(Vec::new()),
+        }
+    }
+
+    pub fn load(path: &Path) -> io::Result<Self> {
+        Ok(Self::new(Fixture::load(path)?))
+    }
+
+    /// `(remote path, mode, contents)` of every push so far, in order.
+    pub fn pushed(&self) -> Vec<(String, u32, Vec<u8>)> {
+        self.pushed.lock().unwrap().clone()
+    }
+}
+
+impl DeviceTransport for ReplayTransport {
+    fn serial(&self) -> &str {
+        &self.fixture.serial
+    }
+
+    fn shell(&self, cmd: &str) -> io::Result<ShellOutput> {
+        Ok(match self.fixture.commands.get(cmd) {
+            Some(recorded) => ShellOutput {
+                stdout: recorded.stdout.clone().into_bytes(),
+                stderr: recorded.stderr.clone().into_bytes(),
+                exit_code: recorded.exit_code,
+            },
+            None => ShellOutput {
+                stdout: Vec::new(),
+                stderr: format!("{}: not recorded in fixture", cmd).into_bytes(),
+                exit_code: 127,
+            },
+        })
+    }
+
+    fn push(&self, local: &Path, remote: &str, mode: u32) -> io::Result<()> {
+        let contents = fs::read(local)?;
+        self.pushed.lock().unwrap().push((remote.to_string(), mode, contents));
+        Ok(())
+    }
+
+    fn pull(&self, remote: &str, local: &Path) -> io::Result<u64> {
+        let contents = self
+            .fixture
+            .files
+            .get(remote)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, format!("{}: not recorded in fixture", remote)))?;
+        fs::write(local, contents)?;
+        Ok(contents.len() as u64)
+    }
+
+    /// Falls back to the full `getprop` listing when the single property
+    /// wasn't recorded on its own.
+    fn getprop(&self, name: &str) -> io::Result<String> {
+        if let Some(recorded) = self.fixture.commands.get(&format!("getprop {}", name)) {
+            return Ok(recorded.stdout.trim().to_string());
+        }
+        let listing = self.fixture.commands.get("getprop").map(|r| r.stdout.as_str()).unwrap_or("");
+        let key = format!("[{}]: [", name);
+        Ok(listing
+            .lines()
+            .find_map(|line| line.strip_prefix(&key)?.strip_suffix(']'))
+            .unwrap_or("")
+            .to_string())
+    }
+}
+
+/// Wraps another transport and records every shell call and pull, producing fixtures
+/// for [`ReplayTransport`] from a real device.
+pub struct RecordingTransport {
+    inner: Box<dyn DeviceTransport>,
+    path: PathBuf,
+    fixture: Mutex<Fixture>,
+}
+
+impl RecordingTransport {
+    pub fn new(inner: Box<dyn DeviceTransport>, path: &Path) -> Self {
+        let fixture = Fixture {
+            serial: inner.serial().to_string(),
+            ..Fixture::default()
+        };
+        RecordingTransport {
+            inner,
+            path: path.to_path_buf(),
+            fixture: Mutex::new(fixture),
+        }
+    }
+
+    /// Records the result and rewrites the fixture file, so a run that is
+    /// interrupted still leaves everything captured so far on disk.
+    fn record(&self, cmd: &str, output: ShellOutput) -> io::Result<ShellOutput> {
+        let mut fixture = self.fixture.lock().unwrap();
+        fixture.commands.insert(
+            cmd.to_string(),
+            RecordedCommand {
+                stdout: output.stdout_lossy(),
+                stderr: output.stderr_lossy(),
+                exit_code: output.exit_code,
+            },
+        );
+        fixture.save(&self.path)?;
+        Ok(output)
+    }
+}
+
+impl DeviceTransport for RecordingTransport {
+    fn serial(&self) -> &str {
+        self.inner.serial()
+    }
+
+    fn shell(&self, cmd: &str) -> io::Result<ShellOutput> {
+        let output = self.inner.shell(cmd)?;
+        self.record(cmd, output)
+    }
+
+    fn shell_timeout(&self, cmd: &str, timeout: Duration) -> io::Result<ShellOutput> {
+        let output = self.inner.shell_timeout(cmd, timeout)?;
+        self.record(cmd, output)
+    }
+
+    fn shell_uncancellable(&self, cmd: &str, timeout: Duration) -> io::Result<ShellOutput> {
+        let output = self.inner.shell_uncancellable(cmd, timeout)?;
+        self.record(cmd, output)
+    }
+
+    /// Passes the stream through and records it once the command ends, so a
+    /// stream cut short by Ctrl-C isn't recorded.
+    fn shell_stream(&self, cmd: &str, on_stdout: &mut dyn FnMut(&[u8])) -> io::Result<i32> {
+        let mut stdout = Vec::new();
+        let exit_code = self.inner.shell_stream(cmd, &mut |chunk| {
+            stdout.extend_from_slice(chunk);
+            on_stdout(chunk);
+        })?;
+        let output = ShellOutput {
+            stdout,
+            stderr: Vec::new(),
+            exit_code,
+        };
+        self.record(cmd, output).map(|output| output.exit_code)
+    }
+
+    fn push(&self, local: &Path, remote: &str, mode: u32) -> io::Result<()> {
+        self.inner.push(local, remote, mode)
+    }
+
+    fn pull(&self, remote: &str, local: &Path) -> io::Result<u64> {
+        let size = self.inner.pull(remote, local)?;
+        if let Ok(contents) = fs::read_to_string(local) {
+            let mut fixture = self.fixture.lock().unwrap();
+            fixture.files.insert(remote.to_string(), contents);
+            fixture.save(&self.path)?;
+        }
+        Ok(size)
+    }
+
+    /// Not recorded: what comes through the port isn't shell output.
+    fn forward(&self, remote: &str) -> io::Result<u16> {
+        self.inner.forward(remote)
+    }
+
+    fn remove_forward(&self, port: u16) -> io::Result<()> {
+        self.inner.remove_forward(port)
+    }
+}
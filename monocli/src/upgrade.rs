@@ -0,0 +1,250 @@
+//! `monocli upgrade`: replaces the daemon on devices running a different
+//! build than the local one, optionally on a canary share of them first.
+//!
+//! Canaries are picked by ranking devices on a hash of their serial, so the
+//! same devices are picked on every run and a larger share always includes
+//! a smaller one: `--canary 10` then `--canary 50` then a full run grows the
+//! rollout without touching a device twice.
+
+use std::fs;
+use std::path::Path;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use monocore::version::{BuildInfo, MARKER};
+use sha2::{Digest, Sha256};
+
+use crate::cancel;
+use crate::control;
+use crate::device::{self, Device, DeviceReport};
+use crate::error::{MonoError, Result};
+use crate::install::{self, Install, DAEMON_PATH};
+use crate::log_message;
+use crate::monitor::parse_duration;
+use crate::service;
+
+/// How often canaries are checked on while they soak.
+pub const HEALTH_INTERVAL: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct UpgradeOptions {
+    /// Percentage of the selected devices to upgrade first.
+    pub canary: Option<u8>,
+    /// How long the canaries have to stay healthy before the rest follow;
+    /// without it the rollout stops after the canaries.
+    pub soak: Option<Duration>,
+    /// Reinstall an identical build, or downgrade a newer one.
+    pub force: bool,
+}
+
+impl UpgradeOptions {
+    /// Takes `--canary <percent>`, `--soak <duration>` and `--force` out of `args`.
+    pub fn from_args(args: &mut Vec<String>) -> Result<Self> {
+        let mut options = UpgradeOptions::default();
+        let mut rest = Vec::new();
+        let mut iter = args.drain(..);
+        while let Some(arg) = iter.next() {
+            match arg.as_str() {
+                "--canary" => {
+                    let value = iter.next().ok_or_else(|| usage("--canary needs a percentage"))?;
+                    let percent = value.trim_end_matches('%').parse().ok().filter(|percent| (1..=100).contains(percent));
+                    options.canary = Some(percent.ok_or_else(|| {
+                        usage(&format!("Invalid --canary '{}', expected a percentage from 1 to 100", value))
+                    })?);
+                }
+                "--soak" => {
+                    let value = iter.next().ok_or_else(|| usage("--soak needs a duration"))?;
+                    options.soak = Some(parse_duration(&value).ok_or_else(|| {
+                        usage(&format!("Invalid --soak '{}', expected a duration such as 30s, 10m or 1h", value))
+                    })?);
+                }
+                "--force" => options.force = true,
+                _ => rest.push(arg),
+            }
+        }
+        drop(iter);
+        *args = rest;
+
+        if options.soak.is_some() && options.canary.is_none() {
+            return Err(usage("--soak only applies with --canary"));
+        }
+        Ok(options)
+    }
+}
+
+fn usage(message: &str) -> MonoError {
+    MonoError::Usage(message.to_string())
+}
+
+/// Indices of the devices, out of `serials`, in a canary of `percent`:
+/// at least one, in selection order.
+pub fn canary_indices(serials: &[&str], percent: u8) -> Vec<usize> {
+    let count = (serials.len() * percent as usize).div_ceil(100).max(1).min(serials.len());
+    let mut ranked: Vec<(Vec<u8>, usize)> = serials
+        .iter()
+        .enumerate()
+        .map(|(index, serial)| (Sha256::digest(serial.as_bytes()).to_vec(), index))
+        .collect();
+    ranked.sort();
+    let mut picked: Vec<usize> = ranked.into_iter().take(count).map(|(_, index)| index).collect();
+    picked.sort();
+    picked
+}
+
+/// The build of a daemon binary on this machine.
+pub fn local_build(binary: &Path) -> Result<BuildInfo> {
+    let bytes = fs::read(binary).map_err(|e| MonoError::Other(format!("Cannot read {}: {}", binary.display(), e)))?;
+    BuildInfo::find_in(&bytes).ok_or_else(|| {
+        MonoError::BuildFailed(format!("{} has no build info; rebuild it from this checkout", binary.display()))
+    })
+}
+
+/// What is installed on a device.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Installed {
+    Nothing,
+    /// A build from before the daemon recorded its version.
+    Unversioned,
+    Build(BuildInfo),
+}
+
+impl std::fmt::Display for Installed {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Installed::Nothing => f.write_str("nothing"),
+            Installed::Unversioned => f.write_str("an unversioned build"),
+            Installed::Build(build) => build.fmt(f),
+        }
+    }
+}
+
+/// The build of the daemon on a device: the running daemon's, as it reports
+/// it on its control socket, or else the one embedded in the binary.
+pub fn installed_build(device: &Device) -> Result<Installed> {
+    if let Some(build) = control::status(device).ok().and_then(|status| status.build.parse().ok()) {
+        return Ok(Installed::Build(build));
+    }
+    binary_build(device)
+}
+
+/// Reads the build embedded in the installed binary. grep stops at the
+/// first match, so only that line comes back rather than the whole binary.
+pub fn binary_build(device: &Device) -> Result<Installed> {
+    let output = device.shell(&format!(
+        "[ -e {path} ] && {{ grep -a -o -m1 '{marker}version=[^ ;]* git=[^ ;]*;' {path}; echo installed; }}",
+        path = DAEMON_PATH,
+        marker = MARKER
+    ))?;
+    if !output.lines().any(|line| line.trim() == "installed") {
+        return Ok(Installed::Nothing);
+    }
+    Ok(BuildInfo::parse(&output).map_or(Installed::Unversioned, Installed::Build))
+}
+
+/// Upgrades one device to `install.binary` unless it already runs that
+/// build, or a newer one, and `force` isn't set.
+pub fn upgrade_device(device: &Device, install: &Install, force: bool) -> Result<String> {
+    let local = local_build(install.binary)?;
+    let installed = installed_build(device)?;
+    if !force {
+        match &installed {
+            Installed::Build(build) if *build == local => return Ok(format!("up to date, {}", local)),
+            Installed::Build(build) if build.semver() > local.semver() => {
+                return Ok(format!("skipped, runs {} which is newer than {}; --force to downgrade", build, local))
+            }
+            _ => {}
+        }
+    }
+    device.log(&format!("Upgrading monodeamon from {} to {}", installed, local));
+    install::upgrade_daemon(device, install)?;
+    Ok(format!("upgraded {} to {}", installed, local))
+}
+
+/// Runs `upgrade` on the canaries, if any, then, unless they fail or there
+/// is no soak time to watch them for, on the rest. Reports come back in
+/// selection order, for the devices that were upgraded or checked.
+pub fn rollout<F>(devices: &[Device], options: &UpgradeOptions, server: &str, upgrade: F) -> Vec<DeviceReport>
+where
+    F: Fn(&Device) -> Result<String> + Sync,
+{
+    let Some(percent) = options.canary else {
+        return device::run_on_devices(devices, upgrade);
+    };
+
+    let serials: Vec<&str> = devices.iter().map(|device| device.serial.as_str()).collect();
+    let picked = canary_indices(&serials, percent);
+    let (canaries, rest): (Vec<_>, Vec<_>) = devices.iter().enumerate().partition(|(index, _)| picked.contains(index));
+    let canary_devices: Vec<Device> = canaries.iter().map(|(_, device)| (*device).clone()).collect();
+    log_message(&format!(
+        "Upgrading {} canary device(s) ({}% of {}): {}",
+        canary_devices.len(),
+        percent,
+        devices.len(),
+        canary_devices.iter().map(|device| device.serial.as_str()).collect::<Vec<_>>().join(", ")
+    ));
+    // A stopped service stays stopped through an upgrade, so there is no
+    // daemon to watch on those canaries.
+    let watched: Vec<bool> = canary_devices
+        .iter()
+        .map(|device| !service::status(device).is_ok_and(|status| status.stopped))
+        .collect();
+    let mut reports = device::run_on_devices(&canary_devices, &upgrade);
+
+    let held = |reason: &str| log_message(&format!("{}; {} device(s) not upgraded", reason, rest.len()));
+    if reports.iter().any(|report| report.result.is_err()) {
+        held("A canary failed");
+        return reports;
+    }
+    if rest.is_empty() {
+        return reports;
+    }
+    let Some(soak) = options.soak else {
+        held("Canaries upgraded; check on them, then run upgrade again to continue");
+        return reports;
+    };
+
+    log_message(&format!("Watching the canaries for {:?} before upgrading the rest", soak));
+    if !soak_canaries(&canary_devices, &watched, &mut reports, server, soak) {
+        held("Canaries unhealthy or rollout cancelled");
+        return reports;
+    }
+
+    let rest_devices: Vec<Device> = rest.iter().map(|(_, device)| (*device).clone()).collect();
+    let rest_reports = device::run_on_devices(&rest_devices, &upgrade);
+    let mut ordered: Vec<(usize, DeviceReport)> = canaries
+        .iter()
+        .map(|(index, _)| *index)
+        .zip(reports)
+        .chain(rest.iter().map(|(index, _)| *index).zip(rest_reports))
+        .collect();
+    ordered.sort_by_key(|(index, _)| *index);
+    ordered.into_iter().map(|(_, report)| report).collect()
+}
+
+/// Checks the `watched` canaries every [`HEALTH_INTERVAL`] until `soak` is
+/// up, turning the report of any that falls over into a failure. `false` if
+/// one did or Ctrl-C was pressed.
+fn soak_canaries(canaries: &[Device], watched: &[bool], reports: &mut [DeviceReport], server: &str, soak: Duration) -> bool {
+    let end = Instant::now() + soak;
+    loop {
+        let watched = canaries.iter().zip(watched).zip(reports.iter_mut()).filter(|((_, watched), _)| **watched);
+        for ((device, _), report) in watched {
+            if let Err(e) = install::check_health(device, server) {
+                device.log(&format!("Unhealthy after the upgrade: {}", e));
+                report.result = Err(e);
+                return false;
+            }
+        }
+        let now = Instant::now();
+        if now >= end {
+            return true;
+        }
+        let next = now + HEALTH_INTERVAL.min(end - now);
+        while Instant::now() < next {
+            if cancel::requested() {
+                return false;
+            }
+            thread::sleep(Duration::from_millis(100).min(next.saturating_duration_since(Instant::now())));
+        }
+    }
+}
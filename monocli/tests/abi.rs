@@ -0,0 +1,86 @@
+//! Device ABI detection and the ELF check done before pushing the daemon.
+
+mod common;
+
+use std::fs;
+use std::path::Path;
+
+use common::scratch;
+use monocli::abi::{check_binary, device_abi, elf_abi, parse_abilist, AndroidAbi};
+use monocli::device::Device;
+use monocli::transport::{Fixture, RecordedCommand, ReplayTransport};
+
+fn replay(name: &str) -> Device {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/replay").join(name);
+    Device::from_transport(ReplayTransport::load(&path).unwrap())
+}
+
+/// An ELF header for `class` (1 = 32-bit, 2 = 64-bit) and `machine`.
+fn write_elf(path: &Path, class: u8, machine: u16) {
+    let mut header = vec![0x7f, b'E', b'L', b'F', class, 1, 1, 0];
+    header.resize(18, 0);
+    header.extend_from_slice(&machine.to_le_bytes());
+    header.resize(64, 0);
+    fs::write(path, header).unwrap();
+}
+
+#[test]
+fn abis_map_to_rust_and_ndk_targets() {
+    assert_eq!(parse_abilist("arm64-v8a,armeabi-v7a,armeabi"), [AndroidAbi::Arm64V8a, AndroidAbi::ArmeabiV7a]);
+    assert_eq!(parse_abilist("riscv64"), []);
+
+    let arm = AndroidAbi::from_name("armeabi-v7a").unwrap();
+    assert_eq!(arm.rust_target(), "armv7-linux-androideabi");
+    assert_eq!(arm.clang_target(), "armv7a-linux-androideabi");
+    assert_eq!(AndroidAbi::X86.rust_target(), "i686-linux-android");
+    assert_eq!(AndroidAbi::X86_64.clang_target(), "x86_64-linux-android");
+}
+
+#[test]
+fn device_abi_prefers_the_abilist() {
+    assert_eq!(device_abi(&replay("pixel7.json")).unwrap(), AndroidAbi::Arm64V8a);
+
+    // Old devices only have ro.product.cpu.abi.
+    let mut fixture = Fixture {
+        serial: "emulator-5554".to_string(),
+        ..Fixture::default()
+    };
+    let prop = |value: &str| RecordedCommand {
+        stdout: format!("{}\n", value),
+        ..RecordedCommand::default()
+    };
+    fixture.commands.insert("getprop ro.product.cpu.abilist".to_string(), prop(""));
+    fixture.commands.insert("getprop ro.product.cpu.abi".to_string(), prop("x86"));
+    let old = Device::from_transport(ReplayTransport::new(fixture.clone()));
+    assert_eq!(device_abi(&old).unwrap(), AndroidAbi::X86);
+
+    fixture.commands.insert("getprop ro.product.cpu.abi".to_string(), prop("mips"));
+    let error = device_abi(&Device::from_transport(ReplayTransport::new(fixture))).unwrap_err();
+    assert!(error.to_string().contains("no supported ABI (reports 'mips'"));
+}
+
+#[test]
+fn binaries_for_another_abi_are_refused() {
+    let dir = scratch("abi-elf");
+    let binary = dir.join("monodeamon");
+
+    write_elf(&binary, 2, 183);
+    assert_eq!(elf_abi(&binary).unwrap(), Some(AndroidAbi::Arm64V8a));
+    check_binary(&binary, AndroidAbi::Arm64V8a).unwrap();
+
+    write_elf(&binary, 2, 62);
+    let error = check_binary(&binary, AndroidAbi::Arm64V8a).unwrap_err();
+    assert_eq!(error.exit_code(), 6);
+    assert!(error.to_string().contains("is built for x86_64, not the device's arm64-v8a"));
+
+    // Same machine, wrong width: a 32-bit x86 build is not x86_64.
+    write_elf(&binary, 1, 62);
+    assert_eq!(elf_abi(&binary).unwrap(), None);
+    write_elf(&binary, 1, 40);
+    assert_eq!(elf_abi(&binary).unwrap(), Some(AndroidAbi::ArmeabiV7a));
+
+    fs::write(&binary, "#!/system/bin/sh\n").unwrap();
+    assert!(check_binary(&binary, AndroidAbi::X86).unwrap_err().to_string().contains("not an ELF binary"));
+
+    fs::remove_dir_all(&dir).unwrap();
+}
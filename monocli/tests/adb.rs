@@ -0,0 +1,277 @@
+//! Exercises the adb client against an in-process mock of the adb server.
+
+use std::collections::HashMap;
+use std::fs;
+use std::io::{self, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use monocli::adb::AdbClient;
+use monocli::device::Device;
+use monocli::error::MonoError;
+
+type Files = Arc<Mutex<HashMap<String, Vec<u8>>>>;
+
+/// Starts a mock server on an ephemeral port and returns a client pointed at it.
+fn mock_server() -> (AdbClient, Files) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap().to_string();
+    let files: Files = Arc::new(Mutex::new(HashMap::new()));
+
+    let shared = files.clone();
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let files = shared.clone();
+            thread::spawn(move || handle(stream.unwrap(), files));
+        }
+    });
+
+    (AdbClient::new(&address), files)
+}
+
+fn read_request(stream: &mut TcpStream) -> Option<String> {
+    let mut length = [0u8; 4];
+    stream.read_exact(&mut length).ok()?;
+    let length = usize::from_str_radix(std::str::from_utf8(&length).ok()?, 16).ok()?;
+    let mut payload = vec![0u8; length];
+    stream.read_exact(&mut payload).ok()?;
+    Some(String::from_utf8(payload).unwrap())
+}
+
+fn okay_with(stream: &mut TcpStream, payload: &str) {
+    write!(stream, "OKAY{:04x}{}", payload.len(), payload).unwrap();
+}
+
+fn fail(stream: &mut TcpStream, message: &str) {
+    write!(stream, "FAIL{:04x}{}", message.len(), message).unwrap();
+}
+
+fn shell_packet(stream: &mut TcpStream, id: u8, data: &[u8]) {
+    stream.write_all(&[id]).unwrap();
+    stream.write_all(&(data.len() as u32).to_le_bytes()).unwrap();
+    stream.write_all(data).unwrap();
+}
+
+fn handle(mut stream: TcpStream, files: Files) {
+    let Some(request) = read_request(&mut stream) else { return };
+
+    match request.as_str() {
+        "host:version" => return okay_with(&mut stream, "0029"),
+        "host:devices" => return okay_with(&mut stream, "SER1\tdevice\nSER2\tunauthorized\n"),
+        "host:transport:SER1" => stream.write_all(b"OKAY").unwrap(),
+        "host-serial:SER1:forward:tcp:0;localabstract:monodeamon" => {
+            stream.write_all(b"OKAY").unwrap();
+            return okay_with(&mut stream, "41234");
+        }
+        "host-serial:SER1:killforward:tcp:41234" => return stream.write_all(b"OKAYOKAY").unwrap(),
+        other if other.starts_with("host-serial:SER1:killforward:") => {
+            stream.write_all(b"OKAY").unwrap();
+            return fail(&mut stream, "listener 'tcp:1' not found");
+        }
+        other if other.starts_with("host:transport:") => {
+            return fail(&mut stream, &format!("device '{}' not found", &other[15..]));
+        }
+        _ => return fail(&mut stream, "unknown host service"),
+    }
+
+    let Some(service) = read_request(&mut stream) else { return };
+    if let Some(cmd) = service.strip_prefix("shell,v2,raw:") {
+        stream.write_all(b"OKAY").unwrap();
+        // Clients close stdin first; a command reading it would wait otherwise.
+        let mut close_stdin = [0u8; 5];
+        stream.read_exact(&mut close_stdin).unwrap();
+        assert_eq!(close_stdin, [4, 0, 0, 0, 0]);
+        match cmd {
+            "echo hi; echo oops >&2; exit 3" => {
+                shell_packet(&mut stream, 1, b"hi\n");
+                shell_packet(&mut stream, 2, b"oops\n");
+                shell_packet(&mut stream, 3, &[3]);
+            }
+            // Exits once stdin is closed, which happened above.
+            "cat" => shell_packet(&mut stream, 3, &[0]),
+            "sleep 60" => {
+                shell_packet(&mut stream, 1, b"partial\n");
+                thread::sleep(Duration::from_secs(5));
+            }
+            _ => shell_packet(&mut stream, 3, &[127]),
+        }
+    } else if service == "sync:" {
+        stream.write_all(b"OKAY").unwrap();
+        handle_sync(&mut stream, &files);
+    } else {
+        fail(&mut stream, "unknown service");
+    }
+}
+
+fn handle_sync(stream: &mut TcpStream, files: &Files) {
+    let mut header = [0u8; 8];
+    let mut target = String::new();
+    let mut content = Vec::new();
+
+    while stream.read_exact(&mut header).is_ok() {
+        let len = u32::from_le_bytes(header[4..8].try_into().unwrap()) as usize;
+        match &header[..4] {
+            b"SEND" => {
+                let mut spec = vec![0u8; len];
+                stream.read_exact(&mut spec).unwrap();
+                let spec = String::from_utf8(spec).unwrap();
+                target = spec.rsplit_once(',').unwrap().0.to_string();
+            }
+            b"DATA" => {
+                let mut data = vec![0u8; len];
+                stream.read_exact(&mut data).unwrap();
+                content.extend_from_slice(&data);
+            }
+            b"DONE" => {
+                files.lock().unwrap().insert(target.clone(), std::mem::take(&mut content));
+                stream.write_all(b"OKAY\0\0\0\0").unwrap();
+            }
+            b"RECV" => {
+                let mut path = vec![0u8; len];
+                stream.read_exact(&mut path).unwrap();
+                let path = String::from_utf8(path).unwrap();
+                match files.lock().unwrap().get(&path) {
+                    Some(data) => {
+                        for chunk in data.chunks(3) {
+                            stream.write_all(b"DATA").unwrap();
+                            stream.write_all(&(chunk.len() as u32).to_le_bytes()).unwrap();
+                            stream.write_all(chunk).unwrap();
+                        }
+                        stream.write_all(b"DONE\0\0\0\0").unwrap();
+                    }
+                    None => {
+                        let message = b"No such file or directory";
+                        stream.write_all(b"FAIL").unwrap();
+                        stream.write_all(&(message.len() as u32).to_le_bytes()).unwrap();
+                        stream.write_all(message).unwrap();
+                    }
+                }
+            }
+            b"QUIT" => return,
+            _ => panic!("unexpected sync packet"),
+        }
+    }
+}
+
+#[test]
+fn lists_devices_and_version() {
+    let (client, _) = mock_server();
+
+    assert_eq!(client.version().unwrap(), 0x29);
+
+    let devices = client.devices().unwrap();
+    assert_eq!(devices.len(), 2);
+    assert_eq!(devices[0].serial, "SER1");
+    assert_eq!(devices[0].state, "device");
+    assert_eq!(devices[1].state, "unauthorized");
+}
+
+#[test]
+fn shell_reports_streams_and_exit_status() {
+    let (client, _) = mock_server();
+
+    let output = client.shell("SER1", "echo hi; echo oops >&2; exit 3").unwrap();
+    assert_eq!(output.stdout_lossy(), "hi\n");
+    assert_eq!(output.stderr_lossy(), "oops\n");
+    assert_eq!(output.exit_code, 3);
+    assert!(!output.success());
+
+    // stdin is closed, so commands reading it don't wait for input.
+    let output = client.shell_with_timeout("SER1", "cat", Some(Duration::from_secs(2))).unwrap();
+    assert!(output.success());
+
+    let mut streamed = Vec::new();
+    let code = client
+        .shell_stream("SER1", "missing-binary", |chunk| streamed.extend_from_slice(chunk), |_| {})
+        .unwrap();
+    assert_eq!(code, 127);
+    assert!(streamed.is_empty());
+}
+
+#[test]
+fn shell_gives_up_after_timeout() {
+    let (client, _) = mock_server();
+
+    let started = Instant::now();
+    let error = client
+        .shell_with_timeout("SER1", "sleep 60", Some(Duration::from_millis(300)))
+        .unwrap_err();
+    assert_eq!(error.kind(), io::ErrorKind::TimedOut);
+    assert!(started.elapsed() < Duration::from_secs(2));
+
+    let output = client
+        .shell_with_timeout("SER1", "echo hi; echo oops >&2; exit 3", Some(Duration::from_secs(5)))
+        .unwrap();
+    assert_eq!(output.exit_code, 3);
+}
+
+#[test]
+fn unknown_device_is_an_error() {
+    let (client, _) = mock_server();
+
+    let error = client.shell("NOPE", "id").unwrap_err();
+    assert!(error.to_string().contains("device 'NOPE' not found"), "{}", error);
+
+    // Commands map it to the "device unavailable" exit code.
+    let error = Device::new(&client, "NOPE").run("id").unwrap_err();
+    assert!(matches!(error, MonoError::DeviceUnavailable(_)), "{}", error);
+    assert_eq!(error.exit_code(), 4);
+}
+
+#[test]
+fn forwards_are_made_and_removed() {
+    let (client, _) = mock_server();
+    assert_eq!(client.forward("SER1", "localabstract:monodeamon").unwrap(), 41234);
+    client.remove_forward("SER1", 41234).unwrap();
+    let error = client.remove_forward("SER1", 1).unwrap_err();
+    assert!(error.to_string().contains("listener 'tcp:1' not found"));
+}
+
+#[test]
+fn failed_commands_carry_status_and_stderr() {
+    let (client, _) = mock_server();
+    let device = Device::new(&client, "SER1");
+
+    let error = device.run("echo hi; echo oops >&2; exit 3").unwrap_err();
+    assert_eq!(error.exit_code(), 5);
+    assert_eq!(error.to_string(), "'echo hi; echo oops >&2; exit 3' exited with status 3: oops");
+
+    // `shell` only cares about stdout.
+    assert_eq!(device.shell("echo hi; echo oops >&2; exit 3").unwrap(), "hi\n");
+}
+
+#[test]
+fn missing_adb_server_is_reported_as_such() {
+    // Bind then drop a listener to get a port nothing is listening on.
+    let address = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().to_string();
+    let client = AdbClient::new(&address);
+
+    let error = monocli::device::list_devices(&client).unwrap_err();
+    assert!(matches!(error, MonoError::AdbUnavailable(_)), "{}", error);
+    assert_eq!(error.exit_code(), 3);
+}
+
+#[test]
+fn push_then_pull_round_trips() {
+    let (client, files) = mock_server();
+    let dir = std::env::temp_dir().join(format!("monocli-adb-test-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+
+    let local = dir.join("payload.bin");
+    fs::write(&local, b"monodeamon payload").unwrap();
+    client.push("SER1", &local, "/data/local/tmp/payload.bin", 0o755).unwrap();
+    assert_eq!(files.lock().unwrap()["/data/local/tmp/payload.bin"], b"monodeamon payload");
+
+    let pulled = dir.join("pulled.bin");
+    let size = client.pull("SER1", "/data/local/tmp/payload.bin", &pulled).unwrap();
+    assert_eq!(size, 18);
+    assert_eq!(fs::read(&pulled).unwrap(), b"monodeamon payload");
+
+    let error = client.pull("SER1", "/missing", &dir.join("missing")).unwrap_err();
+    assert!(error.to_string().contains("No such file"), "{}", error);
+    assert!(!dir.join("missing").exists());
+
+    fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,36 @@
+//! Cancellation is process-wide, so it gets a test binary of its own.
+
+use std::fs;
+use std::path::Path;
+
+use monocli::cancel;
+use monocli::device::Device;
+use monocli::dump::{dump_device_data, CaptureOptions};
+use monocli::manifest::{Manifest, Outcome};
+use monocli::report::ReportTheme;
+use monocli::transport::ReplayTransport;
+
+#[test]
+fn cancelled_dump_is_partial_but_valid() {
+    let output_root = std::env::temp_dir().join(format!("monocli-cancel-{}", std::process::id()));
+    let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/replay/pixel7.json");
+    let device = Device::from_transport(ReplayTransport::load(&path).unwrap());
+
+    cancel::request();
+    let error = dump_device_data(&device, &output_root, &CaptureOptions::default(), &ReportTheme::default())
+        .unwrap_err();
+    assert_eq!(error.exit_code(), 130);
+    assert!(error.to_string().starts_with("Cancelled, partial dump saved in"));
+
+    let dump_dir = fs::read_dir(&output_root).unwrap().next().unwrap().unwrap().path();
+    let manifest = Manifest::load(&dump_dir).unwrap();
+    assert!(manifest.cancelled);
+    assert!(!manifest.entries.is_empty());
+    for entry in &manifest.entries {
+        assert_eq!(entry.outcome, Outcome::Cancelled);
+        assert!(dump_dir.join(&entry.file).exists());
+    }
+    assert!(dump_dir.join("dashboard.html").exists());
+
+    fs::remove_dir_all(&output_root).unwrap();
+}
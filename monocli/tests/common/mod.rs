@@ -0,0 +1,27 @@
+//! Fixtures shared by several test binaries. Each binary uses only some of
+//! them.
+#![allow(dead_code)]
+
+use std::fs;
+use std::path::PathBuf;
+use std::sync::{RwLock, RwLockReadGuard, RwLockWriteGuard};
+
+/// Cancellation is process-wide: tests hold this shared, and the one that
+/// presses Ctrl-C holds it exclusively.
+static CANCEL: RwLock<()> = RwLock::new(());
+
+pub fn shared() -> RwLockReadGuard<'static, ()> {
+    CANCEL.read().unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+pub fn exclusive() -> RwLockWriteGuard<'static, ()> {
+    CANCEL.write().unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+/// An empty folder of the test's own under the temp dir.
+pub fn scratch(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("monocli-{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
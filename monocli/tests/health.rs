@@ -0,0 +1,169 @@
+//! `monocli check` reports against scripted devices.
+
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::TcpListener;
+use std::path::Path;
+use std::thread;
+
+use monocli::adb::ShellOutput;
+use monocli::device::Device;
+use monocli::health::{check, root_method, Health, ROOT_METHOD_COMMAND};
+use monocli::transport::DeviceTransport;
+use monocore::control::reply;
+
+const SERVER: &str = "10.0.0.5:12345";
+const NOW: u64 = 1_723_460_400;
+
+/// Answers commands starting with one of its prefixes; anything else exits 1
+/// with no output, like a missing file or an unmatched `pidof`. Forwards go
+/// to a daemon answering with the control reply, if any; without one, the
+/// request goes unanswered, as when nothing listens on the device.
+struct Scripted(Vec<(&'static str, String)>, Option<String>);
+
+impl DeviceTransport for Scripted {
+    fn serial(&self) -> &str {
+        "scripted"
+    }
+
+    fn shell(&self, cmd: &str) -> io::Result<ShellOutput> {
+        let answer = self.0.iter().find(|(prefix, _)| cmd.starts_with(prefix));
+        Ok(ShellOutput {
+            stdout: answer.map_or_else(Vec::new, |(_, stdout)| stdout.clone().into_bytes()),
+            stderr: Vec::new(),
+            exit_code: if answer.is_some() { 0 } else { 1 },
+        })
+    }
+
+    fn push(&self, _local: &Path, remote: &str, _mode: u32) -> io::Result<()> {
+        Err(io::Error::new(io::ErrorKind::Unsupported, remote.to_string()))
+    }
+
+    fn pull(&self, remote: &str, _local: &Path) -> io::Result<u64> {
+        Err(io::Error::new(io::ErrorKind::Unsupported, remote.to_string()))
+    }
+
+    fn forward(&self, _remote: &str) -> io::Result<u16> {
+        let listener = TcpListener::bind("127.0.0.1:0")?;
+        let port = listener.local_addr()?.port();
+        let answer = self.1.clone();
+        thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request = String::new();
+            BufReader::new(&stream).read_line(&mut request).unwrap();
+            assert_eq!(request, "status\n");
+            if let Some(answer) = answer {
+                stream.write_all(answer.as_bytes()).unwrap();
+            }
+        });
+        Ok(port)
+    }
+}
+
+/// A Magisk device running the service, whose daemon answers with `control`
+/// and wrote `status`.
+fn rooted(control: Option<&str>, status: &str) -> Device {
+    let control = control.map(|status| reply(Ok(status.to_string())));
+    Device::from_transport(Scripted(
+        vec![
+            ("getprop ro.product.model", "Pixel 7\n".to_string()),
+            ("su -c id", "uid=0(root) gid=0(root)\n".to_string()),
+            (ROOT_METHOD_COMMAND, "27.0:MAGISK:R\nmagisk\nmodules\n".to_string()),
+            ("su -c 'd=/data/adb/modules/monodeamon", "installed\nsupervisor=100\ndaemon=4242\n".to_string()),
+            ("su -c 'cat /data/local/tmp/monodeamon.status", format!("{}now={}\n", status, NOW)),
+        ],
+        control,
+    ))
+}
+
+fn status(connected: bool, updated: u64, server: &str) -> String {
+    format!(
+        "pid=4242\nbuild=0.2.0 (1a2b3c4d5e6f)\nserver={}\nstarted={}\nupdated={}\nconnected={}\nlast_send={}\nspool=3\ndropped=0\n",
+        server,
+        NOW - 3 * 3600 - 12 * 60,
+        updated,
+        u8::from(connected),
+        NOW - 2
+    )
+}
+
+#[test]
+fn connected_daemon_is_healthy() {
+    let health = check(&rooted(Some(&status(true, NOW, SERVER)), ""), SERVER);
+    assert!(health.healthy, "{:?}", health.problems);
+    assert_eq!(health.exit_code(), 0);
+    assert_eq!(health.model, "Pixel 7");
+    assert_eq!(health.root.as_deref(), Some("Magisk"));
+    assert_eq!(health.service.as_deref(), Some("running (pid 4242)"));
+    assert_eq!(health.pid, Some(4242));
+    assert_eq!(health.uptime_secs, Some(3 * 3600 + 12 * 60));
+    assert_eq!(
+        health.summary(),
+        "monodeamon 0.2.0 (1a2b3c4d5e6f) pid 4242, up 3h 12m, connected to 10.0.0.5:12345, last send 2s ago, spool 3"
+    );
+    assert!(health.details().contains(&"spool backlog: 3 lines, 0 dropped".to_string()));
+
+    let json: serde_json::Value = serde_json::to_value(&health).unwrap();
+    assert_eq!(json["healthy"], true);
+    assert_eq!(json["last_send_secs_ago"], 2);
+    assert_eq!(json["version"], "0.2.0 (1a2b3c4d5e6f)");
+    assert!(json.get("error").is_none());
+    assert_eq!(health.into_report().result.unwrap(), "monodeamon 0.2.0 (1a2b3c4d5e6f) pid 4242, up 3h 12m, connected to 10.0.0.5:12345, last send 2s ago, spool 3");
+}
+
+#[test]
+fn problems_make_the_device_unhealthy() {
+    // Disconnected and sending to an old address.
+    let health = check(&rooted(Some(&status(false, NOW, "10.0.0.9:12345")), ""), SERVER);
+    assert!(!health.healthy);
+    assert_eq!(health.exit_code(), 7);
+    assert_eq!(
+        health.problems,
+        [
+            "monodeamon isn't connected to 10.0.0.9:12345; is monoserve running there?",
+            "monodeamon sends to 10.0.0.9:12345 but daemon.server is 10.0.0.5:12345; run monocli service reload to update its config",
+        ]
+    );
+    assert_eq!(serde_json::to_value(&health).unwrap()["connected"], false);
+    let report = health.into_report();
+    assert_eq!(report.result.unwrap_err().exit_code(), 7);
+
+    // Not answering, and silent for a minute: its status file is all there is.
+    let health = check(&rooted(None, &status(true, NOW - 60, SERVER)), SERVER);
+    assert_eq!(
+        health.problems,
+        [
+            "monodeamon doesn't answer on its control socket: 'monodeamon control: status' failed: no reply; is monodeamon running?",
+            "monodeamon last reported 1m 0s ago; it may be stuck",
+        ]
+    );
+    assert_eq!(health.uptime_secs, Some(3 * 3600 + 12 * 60));
+
+    // A status file from an earlier run doesn't count.
+    let health = check(&rooted(None, &status(true, NOW, SERVER).replace("pid=4242", "pid=17")), SERVER);
+    assert_eq!(health.problems[1], "monodeamon writes no status; upgrade it with monocli upgrade");
+
+    // Unrooted, with nothing running or installed.
+    let device = Device::from_transport(Scripted(
+        vec![("cat /data/local/tmp/monodeamon.status", format!("now={}\n", NOW))],
+        None,
+    ));
+    let health = check(&device, SERVER);
+    assert_eq!(health.root, None);
+    assert_eq!(health.version, None);
+    assert_eq!(health.problems, ["monodeamon isn't running"]);
+    assert_eq!(health.summary(), "monodeamon not running");
+
+    // A device adb can't talk to.
+    let health = Health::unavailable("R58M123", "unauthorized", SERVER);
+    assert_eq!(health.exit_code(), 4);
+    assert_eq!(health.problems, ["Device R58M123 is unauthorized, not ready"]);
+}
+
+#[test]
+fn root_managers_are_told_apart() {
+    assert_eq!(root_method("27.0:MAGISK:R\nmagisk\nmodules\n"), "Magisk");
+    assert_eq!(root_method("v1.0.1:KernelSU\nksud\nmodules\n"), "KernelSU");
+    assert_eq!(root_method("modules\nksud\n"), "KernelSU");
+    assert_eq!(root_method("10763\nap\napd\nmodules\n"), "APatch");
+    assert_eq!(root_method("16 com.android.shell\n"), "su");
+}
@@ -0,0 +1,505 @@
+//! `monocli install` and `monocli upgrade` steps, their rollback and the
+//! service commands against a scripted device.
+
+mod common;
+
+use std::collections::HashMap;
+use std::fs;
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::TcpListener;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use common::{exclusive, shared};
+use monocli::adb::ShellOutput;
+use monocli::cancel;
+use monocli::device::Device;
+use monocli::install::{install_daemon, Install, CONFIG_PATH, DAEMON_PATH};
+use monocli::service::{self, ServiceStatus};
+use monocli::transport::DeviceTransport;
+use monocli::upgrade::upgrade_device;
+use monocore::control::{reply, Request};
+use monocore::version::BuildInfo;
+use sha2::{Digest, Sha256};
+
+/// A rooted device that keeps just enough state to play along with an
+/// install, the service commands and the daemon's control socket.
+#[derive(Default)]
+struct State {
+    unrooted: bool,
+    module: bool,
+    stopped: bool,
+    supervised: bool,
+    running: bool,
+    /// Whether a started daemon reaches its server.
+    connects: bool,
+    /// A daemon from before the control socket.
+    deaf: bool,
+    /// Flips a byte of every push, like a broken transfer.
+    corrupt_pushes: bool,
+    /// Presses Ctrl-C once this file is pushed.
+    cancel_after_push: Option<&'static str>,
+    /// Presses Ctrl-C while a command starting with this runs.
+    cancel_during: Option<&'static str>,
+    /// Commands that exit with status 1.
+    failing: Vec<&'static str>,
+    files: HashMap<String, Vec<u8>>,
+    ran: Vec<String>,
+}
+
+#[derive(Clone, Default)]
+struct FakeDevice(Arc<Mutex<State>>);
+
+impl FakeDevice {
+    fn with(setup: impl FnOnce(&mut State)) -> Self {
+        let fake = FakeDevice::default();
+        setup(&mut fake.0.lock().unwrap());
+        fake
+    }
+
+    /// Commands run after the first one starting with `from`.
+    fn ran_after(&self, from: &str) -> Vec<String> {
+        let ran = self.0.lock().unwrap().ran.clone();
+        let start = ran.iter().position(|cmd| cmd.starts_with(from)).unwrap();
+        ran[start + 1..].to_vec()
+    }
+}
+
+impl DeviceTransport for FakeDevice {
+    fn serial(&self) -> &str {
+        "fake"
+    }
+
+    /// Fails once Ctrl-C is pressed, as reads from adb do.
+    fn shell(&self, cmd: &str) -> io::Result<ShellOutput> {
+        let cancel_during = self.0.lock().unwrap().cancel_during;
+        if cancel_during.is_some_and(|prefix| cmd.starts_with(prefix)) {
+            self.0.lock().unwrap().ran.push(cmd.to_string());
+            cancel::request();
+        }
+        if cancel::requested() {
+            return Err(cancel::error());
+        }
+        self.shell_uncancellable(cmd, Duration::ZERO)
+    }
+
+    fn shell_uncancellable(&self, cmd: &str, _timeout: Duration) -> io::Result<ShellOutput> {
+        let mut state = self.0.lock().unwrap();
+        state.ran.push(cmd.to_string());
+        let failing = state.failing.iter().any(|failing| cmd.starts_with(failing));
+        let mut stdout = String::new();
+        if cmd == "su -c id" && !state.unrooted {
+            stdout = "uid=0(root) gid=0(root)\n".to_string();
+        } else if cmd.contains("echo daemon=") {
+            for (set, line) in [(state.module, "installed\n"), (state.stopped, "stopped\n"), (state.supervised, "supervisor=100\n")] {
+                if set {
+                    stdout.push_str(line);
+                }
+            }
+            stdout.push_str(if state.running { "daemon=4242\n" } else { "daemon=\n" });
+        } else if let Some(path) = cmd.strip_prefix("sha256sum ") {
+            let hash = sha256_hex(state.files.get(path).map_or(&[][..], Vec::as_slice));
+            stdout = format!("{}  {}\n", hash, path);
+        } else if failing {
+        } else if cmd.contains("service.sh > /dev/null") {
+            state.stopped = false;
+            state.supervised = true;
+            state.running = true;
+        } else if cmd.starts_with("nohup /data/local/tmp/monodeamon") {
+            state.running = true;
+        } else if cmd.contains("kill $(cat /data/adb/modules/monodeamon/service.pid)") {
+            state.stopped |= cmd.contains("touch");
+            state.supervised = false;
+            state.running &= !cmd.contains("pkill -x monodeamon");
+        } else if cmd.contains("cp /data/local/tmp/monodeamon_module/*") {
+            state.module = true;
+        } else if cmd == "su -c 'rm -rf /data/adb/modules/monodeamon'" {
+            state.module = false;
+        } else if cmd.starts_with(&format!("[ -e {} ] && {{ grep -a", DAEMON_PATH)) {
+            if let Some(binary) = state.files.get(DAEMON_PATH) {
+                let build = BuildInfo::find_in(binary).map(|build| format!("{}\n", build.line()));
+                stdout = format!("{}installed\n", build.unwrap_or_default());
+            }
+        } else if cmd.starts_with("for f in") {
+            // Backup, swap and restore of the installed files.
+            for path in [DAEMON_PATH, CONFIG_PATH] {
+                let (bak, new) = (format!("{}.bak", path), format!("{}.new", path));
+                if cmd.contains("cp -p $f $f.bak") {
+                    match state.files.get(path).cloned() {
+                        Some(contents) => state.files.insert(bak.clone(), contents),
+                        None => state.files.remove(&bak),
+                    };
+                }
+                if cmd.contains("mv $f.new $f") {
+                    if let Some(contents) = state.files.remove(&new) {
+                        state.files.insert(path.to_string(), contents);
+                    }
+                }
+                if cmd.contains("mv $f.bak $f") {
+                    match state.files.remove(&bak) {
+                        Some(contents) => state.files.insert(path.to_string(), contents),
+                        None => state.files.remove(path),
+                    };
+                }
+            }
+        }
+        Ok(ShellOutput {
+            stdout: stdout.into_bytes(),
+            stderr: Vec::new(),
+            exit_code: if failing { 1 } else { 0 },
+        })
+    }
+
+    fn push(&self, local: &Path, remote: &str, _mode: u32) -> io::Result<()> {
+        let mut state = self.0.lock().unwrap();
+        let mut contents = fs::read(local)?;
+        if state.corrupt_pushes {
+            contents[0] ^= 0xff;
+        }
+        state.ran.push(format!("push {}", remote));
+        state.files.insert(remote.to_string(), contents);
+        if state.cancel_after_push == Some(remote) {
+            cancel::request();
+        }
+        Ok(())
+    }
+
+    fn pull(&self, remote: &str, local: &Path) -> io::Result<u64> {
+        let state = self.0.lock().unwrap();
+        let contents = state.files.get(remote).ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, remote.to_string()))?;
+        fs::write(local, contents)?;
+        Ok(contents.len() as u64)
+    }
+
+    /// Serves one request as the daemon would, recorded as `control <request>`.
+    fn forward(&self, _remote: &str) -> io::Result<u16> {
+        let listener = TcpListener::bind("127.0.0.1:0")?;
+        let port = listener.local_addr()?.port();
+        let fake = self.clone();
+        thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut line = String::new();
+            BufReader::new(&stream).read_line(&mut line).unwrap();
+            let mut state = fake.0.lock().unwrap();
+            state.ran.push(format!("control {}", line.trim()));
+            if !state.running || state.deaf {
+                return;
+            }
+            let answer = match Request::parse(&line) {
+                Some(Request::Status) => format!(
+                    "pid=4242\nbuild={}\nserver=10.0.0.5:12345\nstarted=0\nupdated=0\nconnected={}\nspool=0\ndropped=0\n",
+                    state.files.get(DAEMON_PATH).and_then(|binary| BuildInfo::find_in(binary)).map_or_else(String::new, |build| build.to_string()),
+                    u8::from(state.connects)
+                ),
+                Some(Request::Stop) => {
+                    state.running = false;
+                    "stopped".to_string()
+                }
+                _ => String::new(),
+            };
+            stream.write_all(reply(Ok(answer)).as_bytes()).unwrap();
+        });
+        Ok(port)
+    }
+}
+
+fn sha256_hex(bytes: &[u8]) -> String {
+    Sha256::digest(bytes).iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn binary(name: &str) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("monocli-install-{}-{}", name, std::process::id()));
+    fs::write(&path, b"\x7fELF fake daemon").unwrap();
+    path
+}
+
+/// A daemon binary carrying the build info of `version` and `git`.
+fn build(version: &str, git: &str) -> Vec<u8> {
+    let info = BuildInfo {
+        version: version.to_string(),
+        git: git.to_string(),
+    };
+    [&b"\x7fELF\0"[..], info.line().as_bytes(), b"\0rest"].concat()
+}
+
+fn options(binary: &Path) -> Install<'_> {
+    Install {
+        binary,
+        config: "[daemon]\nserver = \"10.0.0.5:12345\"\n",
+        server: "10.0.0.5:12345",
+        timeout: Duration::ZERO,
+    }
+}
+
+fn install(fake: &FakeDevice, binary: &Path) -> monocli::error::Result<String> {
+    install_daemon(&Device::from_transport(fake.clone()), &options(binary))
+}
+
+fn upgrade(fake: &FakeDevice, binary: &Path, force: bool) -> monocli::error::Result<String> {
+    upgrade_device(&Device::from_transport(fake.clone()), &options(binary), force)
+}
+
+/// A device running `installed` under the service.
+fn running(installed: Vec<u8>, connects: bool) -> FakeDevice {
+    FakeDevice::with(|state| {
+        state.module = true;
+        state.supervised = true;
+        state.running = true;
+        state.connects = connects;
+        state.files.insert(DAEMON_PATH.to_string(), installed);
+    })
+}
+
+#[test]
+fn installs_verifies_and_confirms_the_connection() {
+    let _shared = shared();
+    let path = binary("ok");
+    let fake = FakeDevice::with(|state| state.connects = true);
+
+    assert_eq!(install(&fake, &path).unwrap(), "installed and started, connected to 10.0.0.5:12345");
+
+    let state = fake.0.lock().unwrap();
+    assert_eq!(state.files[DAEMON_PATH], fs::read(&path).unwrap());
+    assert!(String::from_utf8_lossy(&state.files[CONFIG_PATH]).contains("10.0.0.5:12345"));
+    let module_prop = String::from_utf8_lossy(&state.files["/data/local/tmp/monodeamon_module/module.prop"]);
+    assert!(module_prop.starts_with("id=monodeamon\nname=Mono log collector\n"));
+    assert!(state.module && state.supervised && state.running);
+    let ran: Vec<&str> = state.ran.iter().map(String::as_str).collect();
+    let push = ran.iter().position(|cmd| *cmd == format!("push {}", DAEMON_PATH)).unwrap();
+    assert_eq!(ran[push + 1], format!("sha256sum {}", DAEMON_PATH));
+    assert!(ran.last().unwrap().starts_with("rm -rf /data/local/tmp/monodeamon.bak"));
+    drop(state);
+
+    fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn corrupt_push_restores_the_previous_install() {
+    let _shared = shared();
+    let path = binary("corrupt");
+    let fake = FakeDevice::with(|state| {
+        state.module = true;
+        state.supervised = true;
+        state.running = true;
+        state.corrupt_pushes = true;
+    });
+
+    let error = install(&fake, &path).unwrap_err();
+    assert_eq!(error.exit_code(), 5);
+    let message = error.to_string();
+    assert!(message.starts_with("verify the pushed daemon failed (rolled back): 'sha256sum /data/local/tmp/monodeamon' failed"));
+    assert!(message.contains(&format!("the local build {}", sha256_hex(&fs::read(&path).unwrap()))));
+
+    // The backup goes back into place, then the old service is restarted.
+    let undo = fake.ran_after("sha256sum");
+    assert_eq!(undo.len(), 2);
+    assert!(undo[0].starts_with("for f in") && undo[0].contains("mv $f.bak $f"));
+    assert_eq!(undo[1], service::start_command());
+    let state = fake.0.lock().unwrap();
+    assert!(state.module && state.running);
+    assert!(!state.files.contains_key(CONFIG_PATH));
+    drop(state);
+
+    fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn daemon_that_never_connects_is_stopped_and_undone() {
+    let _shared = shared();
+    let path = binary("unreachable");
+    let fake = FakeDevice::default();
+
+    let error = install(&fake, &path).unwrap_err();
+    assert_eq!(error.exit_code(), 5);
+    assert!(error.to_string().starts_with("check the daemon is connected failed (rolled back)"));
+    assert!(error.to_string().contains("not connected to 10.0.0.5:12345"));
+
+    let undo = fake.ran_after("control status");
+    assert_eq!(undo[0], service::stop_command(false));
+    assert_eq!(undo[1], "su -c 'rm -rf /data/adb/modules/monodeamon'");
+    assert!(undo[2].contains("mv $f.bak $f"));
+    assert_eq!(undo.len(), 3);
+    let state = fake.0.lock().unwrap();
+    assert!(!state.module && !state.running);
+    drop(state);
+
+    // A failed undo is reported, and the rest is still undone.
+    let fake = FakeDevice::with(|state| state.failing = vec!["su -c 'mkdir", "su -c 'rm -rf"]);
+    let error = install(&fake, &path).unwrap_err();
+    assert!(error.to_string().starts_with("install the service module failed (rollback incomplete)"));
+    assert!(fake.ran_after("su -c 'rm -rf").iter().any(|cmd| cmd.contains("mv $f.bak $f")));
+
+    // Without root there is nothing to install the service with.
+    let fake = FakeDevice::with(|state| state.unrooted = true);
+    assert!(install(&fake, &path).unwrap_err().to_string().contains("isn't rooted"));
+    assert!(fake.0.lock().unwrap().files.is_empty());
+
+    fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn service_starts_stops_and_reports() {
+    let _shared = shared();
+    let fake = FakeDevice::with(|state| state.module = true);
+    let device = Device::from_transport(fake.clone());
+
+    assert_eq!(service::report_status(&device).unwrap(), "not running");
+    assert_eq!(service::start(&device, Duration::ZERO).unwrap(), "started, running (pid 4242)");
+    assert_eq!(service::start(&device, Duration::ZERO).unwrap(), "already running (pid 4242)");
+    assert_eq!(service::stop(&device).unwrap(), "stopped");
+    // The daemon is asked to stop, so it isn't killed.
+    let stop = fake.ran_after(&service::stop_supervisor_command(true));
+    assert_eq!(stop, ["control stop"]);
+    assert_eq!(service::report_status(&device).unwrap(), "not running, stopped with monocli service stop");
+
+    assert_eq!(service::remove(&device).unwrap(), "removed");
+    assert!(!fake.0.lock().unwrap().module);
+    assert!(fake
+        .ran_after("su -c 'rm -rf /data/adb/modules/monodeamon'")
+        .iter()
+        .any(|cmd| cmd.contains("/etc/init.d/monodeamon_service.sh")));
+    let error = service::start(&device, Duration::ZERO).unwrap_err();
+    assert!(error.to_string().contains("isn't installed on fake; run monocli install first"));
+
+    // A daemon from before the control socket is killed instead.
+    let fake = FakeDevice::with(|state| {
+        state.module = true;
+        state.running = true;
+        state.deaf = true;
+    });
+    let device = Device::from_transport(fake.clone());
+    assert_eq!(service::stop(&device).unwrap(), "stopped");
+    assert_eq!(fake.ran_after("control stop"), [service::stop_command(true)]);
+    assert!(!fake.0.lock().unwrap().running);
+
+    let status = ServiceStatus::parse("installed\ndisabled\nremoving\ndaemon=4242 4300\n");
+    assert_eq!(
+        status.to_string(),
+        "running unsupervised (pid 4242, 4300), disabled in the root manager, removal pending reboot"
+    );
+}
+
+#[test]
+fn upgrades_only_devices_behind_the_local_build() {
+    let _shared = shared();
+    let path = binary("upgrade");
+    fs::write(&path, build("0.2.0", "def456")).unwrap();
+    let fake = running(build("0.1.0", "abc123"), true);
+
+    assert_eq!(upgrade(&fake, &path, false).unwrap(), "upgraded 0.1.0 (abc123) to 0.2.0 (def456)");
+    let state = fake.0.lock().unwrap();
+    assert_eq!(state.files[DAEMON_PATH], fs::read(&path).unwrap());
+    assert!(String::from_utf8_lossy(&state.files[CONFIG_PATH]).contains("10.0.0.5:12345"));
+    assert!(state.supervised && state.running);
+    // The new build is pushed and verified before the old one is stopped.
+    let ran: Vec<&str> = state.ran.iter().map(String::as_str).collect();
+    let verify = ran.iter().position(|cmd| *cmd == format!("sha256sum {}.new", DAEMON_PATH)).unwrap();
+    let stop = ran.iter().position(|cmd| *cmd == "control stop").unwrap();
+    assert!(verify < stop);
+    drop(state);
+
+    assert_eq!(upgrade(&fake, &path, false).unwrap(), "up to date, 0.2.0 (def456)");
+    fs::write(&path, build("0.1.5", "fed987")).unwrap();
+    assert_eq!(
+        upgrade(&fake, &path, false).unwrap(),
+        "skipped, runs 0.2.0 (def456) which is newer than 0.1.5 (fed987); --force to downgrade"
+    );
+    assert_eq!(upgrade(&fake, &path, true).unwrap(), "upgraded 0.2.0 (def456) to 0.1.5 (fed987)");
+
+    // Builds from before the version was embedded are always upgraded.
+    let fake = running(b"\x7fELF old daemon".to_vec(), true);
+    assert_eq!(upgrade(&fake, &path, false).unwrap(), "upgraded an unversioned build to 0.1.5 (fed987)");
+
+    // Without the service there is nothing to upgrade in place.
+    let fake = FakeDevice::default();
+    assert!(upgrade(&fake, &path, false).unwrap_err().to_string().contains("run monocli install first"));
+
+    fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn upgrade_that_loses_the_connection_puts_the_old_build_back() {
+    let _shared = shared();
+    let path = binary("upgrade-unreachable");
+    fs::write(&path, build("0.2.0", "def456")).unwrap();
+    let old = build("0.1.0", "abc123");
+    let fake = running(old.clone(), false);
+
+    let error = upgrade(&fake, &path, false).unwrap_err();
+    assert_eq!(error.exit_code(), 5);
+    assert!(error.to_string().starts_with("check the daemon is connected failed (rolled back)"));
+
+    let state = fake.0.lock().unwrap();
+    assert_eq!(state.files[DAEMON_PATH], old);
+    assert!(state.ran.iter().any(|cmd| *cmd == format!("rm -f {0}.new {1}.new", DAEMON_PATH, CONFIG_PATH)));
+    assert!(state.supervised && state.running);
+    drop(state);
+
+    // A stopped service stays stopped, so there is no connection to check.
+    let fake = FakeDevice::with(|state| {
+        state.module = true;
+        state.stopped = true;
+        state.files.insert(DAEMON_PATH.to_string(), old);
+    });
+    assert_eq!(upgrade(&fake, &path, false).unwrap(), "upgraded 0.1.0 (abc123) to 0.2.0 (def456)");
+    assert!(!fake.0.lock().unwrap().running);
+
+    fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn cancelled_install_is_still_rolled_back() {
+    let _exclusive = exclusive();
+    let path = binary("cancelled");
+    let old = b"\x7fELF old daemon".to_vec();
+    let fake = FakeDevice::with(|state| {
+        state.module = true;
+        state.supervised = true;
+        state.running = true;
+        state.files.insert(DAEMON_PATH.to_string(), old.clone());
+        state.cancel_after_push = Some(CONFIG_PATH);
+    });
+
+    let error = install(&fake, &path);
+    cancel::reset();
+    let error = error.unwrap_err();
+    assert_eq!(error.exit_code(), 130);
+    assert_eq!(error.to_string(), "install the service module failed (rolled back): Install cancelled");
+
+    // No step starts after Ctrl-C, but the undo commands still run.
+    let undo = fake.ran_after(&format!("push {}", CONFIG_PATH));
+    assert_eq!(undo.len(), 2);
+    assert!(undo[0].starts_with("for f in") && undo[0].contains("mv $f.bak $f"));
+    assert_eq!(undo[1], service::start_command());
+    let state = fake.0.lock().unwrap();
+    assert_eq!(state.files[DAEMON_PATH], old);
+    assert!(!state.files.contains_key(CONFIG_PATH));
+    assert!(state.supervised && state.running);
+    drop(state);
+
+    fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn command_cancelled_mid_step_exits_130() {
+    let _exclusive = exclusive();
+    let path = binary("cancelled-mid-step");
+    let fake = FakeDevice::with(|state| {
+        state.module = true;
+        state.supervised = true;
+        state.running = true;
+        state.cancel_during = Some("sha256sum ");
+    });
+
+    let error = install(&fake, &path);
+    cancel::reset();
+    let error = error.unwrap_err();
+    assert_eq!(error.exit_code(), 130);
+    assert!(error.to_string().contains("(rolled back): 'sha256sum "), "{}", error);
+    assert!(error.to_string().ends_with("' on fake cancelled"), "{}", error);
+    assert!(!fake.0.lock().unwrap().files.contains_key(DAEMON_PATH));
+
+    fs::remove_file(&path).unwrap();
+}
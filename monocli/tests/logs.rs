@@ -0,0 +1,127 @@
+//! `monocli logs` against replayed logcat streams.
+
+use monocli::device::Device;
+use monocli::logs::{stream_logs, LogsOptions, OutputFormat};
+use monocli::transport::{Fixture, RecordedCommand, ReplayTransport};
+use monocore::logcat::{self, Level};
+
+fn device(serial: &str, commands: &[(&str, &str)]) -> Device {
+    let mut fixture = Fixture {
+        serial: serial.to_string(),
+        ..Fixture::default()
+    };
+    for (cmd, stdout) in commands {
+        let recorded = RecordedCommand {
+            stdout: stdout.to_string(),
+            ..RecordedCommand::default()
+        };
+        fixture.commands.insert(cmd.to_string(), recorded);
+    }
+    Device::from_transport(ReplayTransport::new(fixture))
+}
+
+fn options(args: &[&str]) -> LogsOptions {
+    let mut args: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+    let options = LogsOptions::from_args(&mut args).unwrap();
+    assert!(args.is_empty());
+    options
+}
+
+fn stream(devices: &[Device], options: &LogsOptions) -> String {
+    let mut out = Vec::new();
+    stream_logs(devices, options, &mut out).unwrap();
+    String::from_utf8(out).unwrap()
+}
+
+const PIXEL: &str = "\
+--------- beginning of main
+10-17 09:12:01.204  1342  1342 I ActivityManager: Start proc 8812:com.android.chrome/u0a142 for activity
+10-17 09:12:01.377  8812  8812 D ChromiumNet: network changed: wlan0
+10-17 09:12:02.019   871   903 W BatteryStats: Timeout reading wifi controller activity
+10-17 09:12:03.100  7001  7001 W Maps: location request timed out
+10-17 09:12:04.550  8812  8840 E AndroidRuntime: FATAL EXCEPTION: NetworkThread
+10-17 09:12:05.118  1342  1410 I ActivityManager: Start proc 9120:com.android.chrome:sandboxed_process0/u0i3 for service
+10-17 09:12:05.200  9120  9120 W cr_ChildProcess: sandbox ready
+";
+
+#[test]
+fn filters_by_level_tag_and_pattern() {
+    let pixel = [device("pixel", &[("logcat -v threadtime", PIXEL)])];
+
+    let out = stream(&pixel, &options(&["--level", "W", "--color", "never"]));
+    let tags: Vec<String> = out.lines().map(|line| logcat::parse_line(line).unwrap().tag).collect();
+    assert_eq!(tags, ["BatteryStats", "Maps", "AndroidRuntime", "cr_ChildProcess"]);
+
+    let out = stream(&pixel, &options(&["--tag", "Maps,AndroidRuntime", "--exclude-tag", "Maps", "--color", "never"]));
+    assert_eq!(out, "10-17 09:12:04.550  8812  8840 E AndroidRuntime: FATAL EXCEPTION: NetworkThread\n");
+
+    let out = stream(&pixel, &options(&["--grep", "(?i)^battery|timed out", "--color", "never"]));
+    assert_eq!(out.lines().count(), 2);
+
+    let out = stream(&pixel, &options(&["--level", "E", "--color", "always"]));
+    assert_eq!(out, "\x1b[31m10-17 09:12:04.550  8812  8840 E AndroidRuntime: FATAL EXCEPTION: NetworkThread\x1b[0m\n");
+}
+
+#[test]
+fn packages_follow_their_processes() {
+    // Not running when the stream starts: picked up from ActivityManager.
+    let pixel = device("pixel", &[("logcat -v threadtime", PIXEL), ("pidof com.android.chrome", "")]);
+    let out = stream(&[pixel], &options(&["--package", "com.android.chrome", "--json"]));
+    let pids: Vec<u64> = out
+        .lines()
+        .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap()["pid"].as_u64().unwrap())
+        .collect();
+    assert_eq!(pids, [8812, 8812, 9120]);
+
+    let pixel = device("pixel", &[("logcat -v threadtime", PIXEL), ("pidof com.google.maps", "7001\n")]);
+    let out = stream(&[pixel], &options(&["--package", "com.google.maps", "--color", "never"]));
+    assert_eq!(out, "10-17 09:12:03.100  7001  7001 W Maps: location request timed out\n");
+}
+
+#[test]
+fn devices_interleave_by_timestamp() {
+    let command = "logcat -v threadtime -v year -v UTC";
+    let pixel = device(
+        "pixel",
+        &[(
+            command,
+            "2026-10-17 09:12:01.000  100  100 I A: one\n2026-10-17 09:12:03.000  100  100 I A: three\n",
+        )],
+    );
+    let tablet = device(
+        "tablet",
+        &[(
+            command,
+            "2026-10-17 09:12:02.000  200  200 I B: two\n2026-10-17 09:12:04.000  200  200 E B: four\n",
+        )],
+    );
+
+    let out = stream(&[pixel.clone(), tablet.clone()], &options(&["--color", "never"]));
+    assert_eq!(
+        out,
+        "[pixel] 2026-10-17 09:12:01.000   100   100 I A: one\n\
+         [tablet] 2026-10-17 09:12:02.000   200   200 I B: two\n\
+         [pixel] 2026-10-17 09:12:03.000   100   100 I A: three\n\
+         [tablet] 2026-10-17 09:12:04.000   200   200 E B: four\n"
+    );
+
+    let out = stream(&[pixel, tablet], &options(&["--json", "--level", "E"]));
+    let line: serde_json::Value = serde_json::from_str(out.trim()).unwrap();
+    assert_eq!(line["serial"], "tablet");
+    assert_eq!(line["time"], "2026-10-17 09:12:04.000");
+    assert_eq!(line["level"], "E");
+    assert_eq!(line["message"], "four");
+}
+
+#[test]
+fn bad_options_are_usage_errors() {
+    let parsed = options(&["--level", "warn", "--json"]);
+    assert_eq!(parsed.filter.min_level, Level::Warn);
+    assert_eq!(parsed.format, OutputFormat::Json);
+
+    for args in [&["--level", "loud"][..], &["--grep", "("], &["--color", "rainbow"], &["--tag"]] {
+        let mut args: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+        let error = LogsOptions::from_args(&mut args).unwrap_err();
+        assert_eq!(error.exit_code(), 2);
+    }
+}
@@ -0,0 +1,146 @@
+//! `monocli monitor` against a fake device whose counters move between samples.
+
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+use monocli::adb::ShellOutput;
+use monocli::device::Device;
+use monocli::monitor::{monitor_device, parse_duration, MonitorOptions, MonitorSample};
+use monocli::parsers::THERMAL_ZONES_COMMAND;
+use monocli::report::ReportTheme;
+use monocli::transport::{DeviceTransport, ReplayTransport};
+use monocore::config::{Config, Source};
+
+/// Replays pixel7.json, with a `/proc/stat` that is busy for a quarter of
+/// each 400-tick step, and three thermal zones. Given a count, the device goes
+/// away after that many samples.
+struct Busy(ReplayTransport, AtomicU64, Option<u64>);
+
+impl Busy {
+    fn new(lost_after: Option<u64>) -> Self {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/replay/pixel7.json");
+        Busy(ReplayTransport::load(&path).unwrap(), AtomicU64::new(0), lost_after)
+    }
+}
+
+impl DeviceTransport for Busy {
+    fn serial(&self) -> &str {
+        self.0.serial()
+    }
+
+    fn shell(&self, cmd: &str) -> io::Result<ShellOutput> {
+        let stdout = match cmd {
+            "cat /proc/stat" => {
+                let step = self.1.fetch_add(1, Ordering::SeqCst);
+                if self.2.is_some_and(|lost_after| step >= lost_after) {
+                    return Err(io::Error::other("device '28151FDH2000QK' not found"));
+                }
+                format!("cpu  {} 0 {} {} 0 0 0 0 0 0\ncpu0 1 0 1 1 0 0 0\nctxt 123\n", step * 50, step * 50, step * 300)
+            }
+            THERMAL_ZONES_COMMAND => "battery 31200\nskin-therm 35800\npa,therm 40000\n".to_string(),
+            _ => return self.0.shell(cmd),
+        };
+        Ok(ShellOutput {
+            stdout: stdout.into_bytes(),
+            stderr: Vec::new(),
+            exit_code: 0,
+        })
+    }
+
+    fn push(&self, local: &Path, remote: &str, mode: u32) -> io::Result<()> {
+        self.0.push(local, remote, mode)
+    }
+
+    fn pull(&self, remote: &str, local: &Path) -> io::Result<u64> {
+        self.0.pull(remote, local)
+    }
+
+    fn getprop(&self, name: &str) -> io::Result<String> {
+        self.0.getprop(name)
+    }
+}
+
+#[test]
+fn durations_take_units() {
+    assert_eq!(parse_duration("500ms"), Some(Duration::from_millis(500)));
+    assert_eq!(parse_duration("2s"), Some(Duration::from_secs(2)));
+    assert_eq!(parse_duration("10m"), Some(Duration::from_secs(600)));
+    assert_eq!(parse_duration("1.5h"), Some(Duration::from_secs(5400)));
+    assert_eq!(parse_duration("30"), Some(Duration::from_secs(30)));
+    assert_eq!(parse_duration("10 minutes"), None);
+    assert_eq!(parse_duration("-1s"), None);
+
+    let mut config = Config::default();
+    config.set("monitor.interval", "0s", Source::Cli("--interval".to_string())).unwrap();
+    let error = MonitorOptions::from_config(&config).unwrap_err();
+    assert_eq!(error.exit_code(), 2);
+    assert!(error.to_string().contains("monitor.interval"));
+}
+
+#[test]
+fn monitor_writes_samples_and_time_series_report() {
+    let output_root = std::env::temp_dir().join(format!("monocli-monitor-{}", std::process::id()));
+    let device = Device::from_transport(Busy::new(None));
+    let options = MonitorOptions {
+        interval: Duration::from_millis(30),
+        duration: Duration::from_millis(90),
+    };
+
+    monitor_device(&device, &output_root, &options, &ReportTheme::default()).unwrap();
+    let dir = fs::read_dir(&output_root).unwrap().next().unwrap().unwrap().path();
+    assert!(dir.file_name().unwrap().to_string_lossy().starts_with("Pixel_7_28151FDH2000QK_"));
+
+    // Samples at 0, 30, 60 and 90ms.
+    let samples: Vec<MonitorSample> =
+        serde_json::from_str(&fs::read_to_string(dir.join("samples.json")).unwrap()).unwrap();
+    assert_eq!(samples.len(), 4);
+    assert_eq!(samples[0].cpu_percent, None);
+    for sample in &samples[1..] {
+        assert_eq!(sample.cpu_percent, Some(25.0));
+    }
+    assert!(samples.windows(2).all(|pair| pair[1].time > pair[0].time));
+    assert_eq!(samples[0].battery_percent, Some(81));
+    assert_eq!(samples[0].mem_total_kb, Some(7824300));
+    assert_eq!(samples[0].thermal["skin-therm"], 35.8);
+
+    let csv = fs::read_to_string(dir.join("samples.csv")).unwrap();
+    let mut lines = csv.lines();
+    assert_eq!(lines.next(), Some("timestamp,metric,value"));
+    assert_eq!(csv.matches(",cpu_percent,25").count(), 3);
+    assert_eq!(csv.matches(",thermal.battery,31.2").count(), 4);
+    assert_eq!(csv.matches(",\"thermal.pa,therm\",40\n").count(), 4);
+
+    let html = fs::read_to_string(dir.join("dashboard.html")).unwrap();
+    assert!(html.contains("<li>Samples: 4 over"));
+    assert!(html.contains("<li>CPU: avg 25.0%, peak 25.0%</li>"));
+    for chart in ["cpuChart", "memoryChart", "batteryChart", "thermalChart"] {
+        assert!(html.contains(&format!("id=\"{}\">\n<svg", chart)), "{}", chart);
+    }
+    assert!(html.contains("<td>skin-therm</td><td>min 35.8 / avg 35.8 / max 35.8 °C</td>"));
+
+    fs::remove_dir_all(&output_root).unwrap();
+}
+
+#[test]
+fn lost_device_keeps_samples_so_far() {
+    let output_root = std::env::temp_dir().join(format!("monocli-monitor-lost-{}", std::process::id()));
+    let device = Device::from_transport(Busy::new(Some(2)));
+    let options = MonitorOptions {
+        interval: Duration::from_millis(10),
+        duration: Duration::from_secs(60),
+    };
+
+    let error = monitor_device(&device, &output_root, &options, &ReportTheme::default()).unwrap_err();
+    assert_eq!(error.exit_code(), 4);
+    let dir = fs::read_dir(&output_root).unwrap().next().unwrap().unwrap().path();
+    let samples: Vec<MonitorSample> =
+        serde_json::from_str(&fs::read_to_string(dir.join("samples.json")).unwrap()).unwrap();
+    assert_eq!(samples.len(), 2);
+    assert_eq!(fs::read_to_string(dir.join("samples.csv")).unwrap().matches(",thermal.battery,").count(), 2);
+    assert!(fs::read_to_string(dir.join("dashboard.html")).unwrap().contains("<li>Samples: 2 over"));
+
+    fs::remove_dir_all(&output_root).unwrap();
+}
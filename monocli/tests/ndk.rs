@@ -0,0 +1,156 @@
+//! NDK discovery against fake SDK layouts in a temp folder.
+
+mod common;
+
+use std::collections::HashMap;
+use std::ffi::OsString;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use common::scratch;
+use monocli::ndk::{candidates, find_ndk, installed, Candidate, HostOs, Ndk, NdkRequirement, NdkVersion};
+use monocore::config::{Config, Source};
+
+fn env(vars: &[(&str, &Path)]) -> impl Fn(&str) -> Option<OsString> {
+    let vars: HashMap<String, OsString> = vars
+        .iter()
+        .map(|(name, value)| (name.to_string(), value.as_os_str().to_owned()))
+        .collect();
+    move |name| vars.get(name).cloned()
+}
+
+/// Lays out an NDK with a toolchain for `host` at `root`.
+fn fake_ndk(root: &Path, host: HostOs) {
+    let ndk = Ndk {
+        root: root.to_path_buf(),
+        host,
+    };
+    fs::create_dir_all(ndk.bin_dir()).unwrap();
+    fs::write(root.join("source.properties"), "Pkg.Desc = Android NDK\n").unwrap();
+}
+
+fn any() -> NdkRequirement {
+    NdkRequirement::default()
+}
+
+fn requirement(version: &str, min_version: &str) -> NdkRequirement {
+    let mut config = Config::default();
+    config.set("ndk.version", version, Source::Cli("--set".to_string())).unwrap();
+    config.set("ndk.min_version", min_version, Source::Cli("--set".to_string())).unwrap();
+    NdkRequirement::from_config(&config).unwrap()
+}
+
+#[test]
+fn environment_comes_before_default_locations() {
+    let dir = scratch("ndk-env");
+    let home = dir.join("home");
+    fake_ndk(&home.join("Android/Sdk/ndk/26.1.10909125"), HostOs::Linux);
+    fake_ndk(&dir.join("pinned"), HostOs::Linux);
+
+    let ndk = find_ndk(HostOs::Linux, env(&[("HOME", &home)]), &any()).unwrap();
+    assert_eq!(ndk.root, home.join("Android/Sdk/ndk/26.1.10909125"));
+    assert_eq!(
+        ndk.linker("aarch64-linux-android", 21),
+        ndk.root.join("toolchains/llvm/prebuilt/linux-x86_64/bin/aarch64-linux-android21-clang")
+    );
+    assert_eq!(ndk.ar(), ndk.root.join("toolchains/llvm/prebuilt/linux-x86_64/bin/llvm-ar"));
+
+    let ndk = find_ndk(HostOs::Linux, env(&[("HOME", &home), ("ANDROID_NDK_ROOT", &dir.join("pinned"))]), &any()).unwrap();
+    assert_eq!(ndk.root, dir.join("pinned"));
+
+    // An SDK named explicitly wins over the home folder.
+    let sdk = dir.join("sdk");
+    fake_ndk(&sdk.join("ndk-bundle"), HostOs::Linux);
+    let ndk = find_ndk(HostOs::Linux, env(&[("HOME", &home), ("ANDROID_SDK_ROOT", &sdk)]), &any()).unwrap();
+    assert_eq!(ndk.root, sdk.join("ndk-bundle"));
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn hosts_have_their_own_locations_and_toolchains() {
+    let home = Path::new("/Users/me");
+    let found = candidates(HostOs::MacOs, env(&[("HOME", home)]));
+    assert_eq!(found[0], Candidate::Versions(home.join("Library/Android/sdk/ndk")));
+
+    let local = Path::new(r"C:\Users\me\AppData\Local");
+    let found = candidates(HostOs::Windows, env(&[("LOCALAPPDATA", local)]));
+    assert_eq!(found[0], Candidate::Versions(local.join("Android/Sdk").join("ndk")));
+
+    let ndk = Ndk {
+        root: PathBuf::from("ndk"),
+        host: HostOs::Windows,
+    };
+    assert!(ndk.linker("x86_64-linux-android", 21).ends_with("windows-x86_64/bin/x86_64-linux-android21-clang.cmd"));
+    assert!(ndk.ar().ends_with("llvm-ar.exe"));
+}
+
+#[test]
+fn missing_ndk_or_toolchain_is_a_build_error() {
+    let dir = scratch("ndk-missing");
+    let error = find_ndk(HostOs::Linux, env(&[("HOME", &dir)]), &any()).unwrap_err();
+    assert_eq!(error.exit_code(), 6);
+    assert!(error.to_string().contains(&dir.join("Android/Sdk/ndk").display().to_string()));
+
+    // Installed, but only with the macOS toolchain.
+    fake_ndk(&dir.join("mac-only"), HostOs::MacOs);
+    let error = find_ndk(HostOs::Linux, env(&[("ANDROID_NDK_HOME", &dir.join("mac-only"))]), &any()).unwrap_err();
+    assert!(error.to_string().contains("no toolchain for this host"));
+
+    // An NDK further down the search order that has one is used instead.
+    let home = dir.join("home");
+    fake_ndk(&home.join("Android/Sdk/ndk/26.1.10909125"), HostOs::Linux);
+    let ndk = find_ndk(HostOs::Linux, env(&[("HOME", &home), ("ANDROID_NDK_HOME", &dir.join("mac-only"))]), &any()).unwrap();
+    assert_eq!(ndk.root, home.join("Android/Sdk/ndk/26.1.10909125"));
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn versions_compare_numerically() {
+    let dir = scratch("ndk-versions");
+    let ndks = dir.join("sdk/ndk");
+    for version in ["9.0.1234", "25.2.9519653", "26.1.10909125", "26.0.10792818", "100"] {
+        fake_ndk(&ndks.join(version), HostOs::Linux);
+    }
+    // The folder name says 100, but source.properties knows better.
+    fs::write(ndks.join("100/source.properties"), "Pkg.Desc = Android NDK\nPkg.Revision = 21.4.7075529\n").unwrap();
+    let sdk = dir.join("sdk");
+    let var = env(&[("ANDROID_SDK_ROOT", &sdk)]);
+
+    let versions: Vec<String> = installed(HostOs::Linux, &var)
+        .iter()
+        .map(|ndk| ndk.version.unwrap().to_string())
+        .collect();
+    assert_eq!(versions, ["26.1.10909125", "26.0.10792818", "25.2.9519653", "21.4.7075529", "9.0.1234"]);
+
+    assert_eq!(find_ndk(HostOs::Linux, &var, &any()).unwrap().root, ndks.join("26.1.10909125"));
+    assert_eq!(find_ndk(HostOs::Linux, &var, &requirement("r26", "")).unwrap().root, ndks.join("26.1.10909125"));
+    assert_eq!(find_ndk(HostOs::Linux, &var, &requirement("r26a", "")).unwrap().root, ndks.join("26.0.10792818"));
+    assert_eq!(find_ndk(HostOs::Linux, &var, &requirement("21", "")).unwrap().root, ndks.join("100"));
+    assert_eq!(find_ndk(HostOs::Linux, &var, &requirement("", "25")).unwrap().root, ndks.join("26.1.10909125"));
+
+    let error = find_ndk(HostOs::Linux, &var, &requirement("27.0", "")).unwrap_err();
+    assert_eq!(error.exit_code(), 6);
+    let message = error.to_string();
+    assert!(message.starts_with("No installed NDK meets ndk.version = 27.0. Installed:\n  26.1.10909125  "));
+    assert!(message.contains(&format!("  9.0.1234  {}", ndks.join("9.0.1234").display())));
+
+    let error = find_ndk(HostOs::Linux, &var, &requirement("25", "26")).unwrap_err();
+    assert!(error.to_string().starts_with("No installed NDK meets ndk.version = 25, ndk.min_version = 26.0.0."));
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn version_settings_are_validated() {
+    assert_eq!("r21e".parse::<NdkVersion>().unwrap().to_string(), "21.4.0");
+    assert_eq!("26.0.10404224-beta1".parse::<NdkVersion>().unwrap().to_string(), "26.0.10404224");
+    assert!("latest".parse::<NdkVersion>().is_err());
+
+    let mut config = Config::default();
+    config.set("ndk.version", "newest", Source::Cli("--set".to_string())).unwrap();
+    let error = NdkRequirement::from_config(&config).unwrap_err();
+    assert_eq!(error.exit_code(), 2);
+    assert_eq!(error.to_string(), "Invalid value 'newest' for ndk.version (from flag --set)");
+}
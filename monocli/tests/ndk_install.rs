@@ -0,0 +1,234 @@
+//! `monocli ndk install` and its downloads against a local HTTP server.
+
+mod common;
+
+use std::collections::HashMap;
+use std::ffi::OsString;
+use std::fs;
+use std::io::{Cursor, Read, Write};
+use std::net::TcpListener;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use common::scratch;
+use monocli::download::{self, part_path, Checksum, Download, Fetched};
+use monocli::ndk::{find_ndk, install_ndk, parse_repository, HostOs, NdkRequirement};
+use sha1::{Digest, Sha1};
+use zip::write::SimpleFileOptions;
+use zip::ZipWriter;
+
+/// Serves `files` by path, honouring `Range: bytes=N-`. The first request
+/// for `cut` only gets that many bytes of its body before the connection
+/// drops. Returns the base URL and the requests seen, with their ranges.
+fn serve(files: HashMap<String, Vec<u8>>, cut: Option<(&str, usize)>) -> (String, Arc<Mutex<Vec<String>>>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let base = format!("http://{}", listener.local_addr().unwrap());
+    let seen = Arc::new(Mutex::new(Vec::new()));
+    let log = Arc::clone(&seen);
+    let mut cut = cut.map(|(path, bytes)| (path.to_string(), bytes));
+
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let mut stream = stream.unwrap();
+            let mut request = Vec::new();
+            let mut byte = [0u8];
+            while !request.ends_with(b"\r\n\r\n") && stream.read(&mut byte).unwrap() == 1 {
+                request.push(byte[0]);
+            }
+            let request = String::from_utf8(request).unwrap();
+            let path = request.split(' ').nth(1).unwrap_or("").to_string();
+            let range = request.lines().find_map(|line| {
+                let (name, value) = line.split_once(':')?;
+                let start = value.trim().strip_prefix("bytes=")?.strip_suffix('-')?;
+                name.eq_ignore_ascii_case("range").then(|| start.parse::<usize>().unwrap())
+            });
+            log.lock().unwrap().push(match range {
+                Some(start) => format!("{} from {}", path, start),
+                None => path.clone(),
+            });
+
+            let Some(body) = files.get(&path) else {
+                let _ = stream.write_all(b"HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n");
+                continue;
+            };
+            let start = range.unwrap_or(0);
+            let status = if range.is_some() { "206 Partial Content" } else { "200 OK" };
+            let body = &body[start..];
+            let head = format!("HTTP/1.1 {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n", status, body.len());
+            let _ = stream.write_all(head.as_bytes());
+            let sent = match cut.take_if(|(cut_path, _)| *cut_path == path) {
+                Some((_, bytes)) => &body[..bytes],
+                None => body,
+            };
+            let _ = stream.write_all(sent);
+        }
+    });
+    (base, seen)
+}
+
+fn sha1_hex(bytes: &[u8]) -> String {
+    Sha1::digest(bytes).iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// A zip laid out like an NDK release: everything under one top folder.
+fn fake_ndk_zip(top: &str, revision: &str) -> Vec<u8> {
+    let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+    let options = SimpleFileOptions::default();
+    zip.start_file(format!("{}/source.properties", top), options).unwrap();
+    write!(zip, "Pkg.Desc = Android NDK\nPkg.Revision = {}\n", revision).unwrap();
+    zip.add_directory(format!("{}/toolchains/llvm/prebuilt/linux-x86_64/bin", top), options).unwrap();
+    zip.start_file(format!("{}/toolchains/llvm/prebuilt/linux-x86_64/bin/llvm-ar", top), options).unwrap();
+    zip.write_all(b"#!/bin/sh\n").unwrap();
+    zip.finish().unwrap().into_inner()
+}
+
+fn archive(os: &str, url: &str, bytes: &[u8]) -> String {
+    format!(
+        "<archive><complete><size>{}</size><checksum type=\"sha1\">{}</checksum><url>{}</url></complete><host-os>{}</host-os></archive>",
+        bytes.len(),
+        sha1_hex(bytes),
+        url,
+        os
+    )
+}
+
+fn package(path: &str, channel: &str, archives: &[String]) -> String {
+    format!(
+        "<remotePackage path=\"{}\"><channelRef ref=\"{}\"/><archives>{}</archives></remotePackage>",
+        path,
+        channel,
+        archives.concat()
+    )
+}
+
+fn manifest(packages: &[String]) -> String {
+    format!(
+        "<?xml version=\"1.0\"?><sdk:sdk-repository xmlns:sdk=\"http://schemas.android.com/sdk/android/repo/repository2/01\">\
+         <channel id=\"channel-0\">stable</channel><channel id=\"channel-1\">beta</channel>{}</sdk:sdk-repository>",
+        packages.concat()
+    )
+}
+
+fn cache_env(cache: &Path) -> impl Fn(&str) -> Option<OsString> + '_ {
+    move |name| (name == "XDG_CACHE_HOME").then(|| cache.as_os_str().to_owned())
+}
+
+#[test]
+fn picks_the_newest_stable_archive_for_the_host() {
+    let linux = fake_ndk_zip("android-ndk-r26b", "26.1.10909125");
+    let xml = manifest(&[
+        package(
+            "ndk;26.1.10909125",
+            "channel-0",
+            &[archive("macosx", "android-ndk-r26b-darwin.zip", b"mac"), archive("linux", "android-ndk-r26b-linux.zip", &linux)],
+        ),
+        package("ndk;27.0.11718014", "channel-1", &[archive("linux", "android-ndk-r27-beta1-linux.zip", b"beta")]),
+        package("ndk;25.2.9519653", "channel-0", &[archive("windows", "https://mirror.example/r25c-windows.zip", b"win")]),
+        package("cmake;3.22.1", "channel-0", &[archive("linux", "cmake-linux.zip", b"cmake")]),
+    ]);
+
+    let found = parse_repository(&xml, "https://dl.example/repository/", HostOs::Linux).unwrap();
+    assert_eq!(found.len(), 1);
+    assert_eq!(found[0].version.to_string(), "26.1.10909125");
+    assert_eq!(found[0].url, "https://dl.example/repository/android-ndk-r26b-linux.zip");
+    assert_eq!(found[0].size, Some(linux.len() as u64));
+    assert_eq!(found[0].checksum, Checksum::Sha1(sha1_hex(&linux)));
+
+    let found = parse_repository(&xml, "https://dl.example/repository", HostOs::MacOs).unwrap();
+    assert_eq!(found[0].url, "https://dl.example/repository/android-ndk-r26b-darwin.zip");
+
+    let found = parse_repository(&xml, "https://dl.example/repository", HostOs::Windows).unwrap();
+    assert_eq!(found[0].url, "https://mirror.example/r25c-windows.zip");
+
+    assert!(parse_repository("<sdk", "https://dl.example", HostOs::Linux).is_err());
+}
+
+#[test]
+fn installs_into_the_cache_where_builds_find_it() {
+    let dir = scratch("ndk-install-install");
+    let cache = dir.join("cache/mono");
+    let linux = fake_ndk_zip("android-ndk-r26b", "26.1.10909125");
+    let xml = manifest(&[
+        package("ndk;26.1.10909125", "channel-0", &[archive("linux", "android-ndk-r26b-linux.zip", &linux)]),
+        package("ndk;25.2.9519653", "channel-0", &[archive("linux", "android-ndk-r25c-linux.zip", b"old")]),
+    ]);
+    let files = HashMap::from([
+        ("/repository2-1.xml".to_string(), xml.into_bytes()),
+        ("/android-ndk-r26b-linux.zip".to_string(), linux),
+    ]);
+    let (base, seen) = serve(files, None);
+    let client = download::client().unwrap();
+
+    let installed = install_ndk(&client, &base, HostOs::Linux, &cache, &NdkRequirement::default()).unwrap();
+    assert_eq!(installed.root, cache.join("ndk/26.1.10909125"));
+    assert_eq!(installed.version.unwrap().to_string(), "26.1.10909125");
+    assert!(cache.join("downloads/android-ndk-r26b-linux.zip").is_file());
+
+    let ndk = find_ndk(HostOs::Linux, cache_env(&dir.join("cache")), &NdkRequirement::default()).unwrap();
+    assert_eq!(ndk.root, installed.root);
+
+    // Already there: only the manifest is read again.
+    install_ndk(&client, &base, HostOs::Linux, &cache, &NdkRequirement::default()).unwrap();
+    assert_eq!(
+        *seen.lock().unwrap(),
+        ["/repository2-1.xml", "/android-ndk-r26b-linux.zip", "/repository2-1.xml"]
+    );
+
+    let requirement = NdkRequirement {
+        pinned: Some("27".parse().unwrap()),
+        minimum: None,
+    };
+    let error = install_ndk(&client, &base, HostOs::Linux, &cache, &requirement).unwrap_err();
+    assert_eq!(error.exit_code(), 2);
+    assert!(error.to_string().contains("Newest available: 26.1.10909125, 25.2.9519653"));
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn interrupted_downloads_resume_and_corrupt_ones_are_discarded() {
+    let dir = scratch("ndk-install-resume");
+    let payload: Vec<u8> = (0..200_000u32).map(|i| (i % 251) as u8).collect();
+    let files = HashMap::from([("/big.zip".to_string(), payload.clone())]);
+    let (base, seen) = serve(files, Some(("/big.zip", 70_000)));
+    let client = download::client().unwrap();
+    let url = format!("{}/big.zip", base);
+    let checksum = Checksum::Sha1(sha1_hex(&payload));
+    let download = Download {
+        url: &url,
+        checksum: &checksum,
+        size: Some(payload.len() as u64),
+    };
+    let dest = dir.join("big.zip");
+
+    let error = download::fetch(&client, &download, &dest, |_, _| {}).unwrap_err();
+    assert!(error.to_string().contains("Failed to download"));
+    assert!(!dest.exists());
+    let kept = fs::metadata(part_path(&dest)).unwrap().len();
+    assert!(kept > 0 && kept <= 70_000);
+
+    let mut progress = Vec::new();
+    let fetched = download::fetch(&client, &download, &dest, |done, total| progress.push((done, total))).unwrap();
+    assert_eq!(fetched, Fetched::Downloaded { resumed_from: kept });
+    assert_eq!(progress.last(), Some(&(200_000, Some(200_000))));
+    assert_eq!(fs::read(&dest).unwrap(), payload);
+    assert!(!part_path(&dest).exists());
+    assert_eq!(*seen.lock().unwrap(), ["/big.zip".to_string(), format!("/big.zip from {}", kept)]);
+
+    assert_eq!(download::fetch(&client, &download, &dest, |_, _| {}).unwrap(), Fetched::Cached);
+
+    let wrong = Checksum::Sha1("0".repeat(40));
+    let other = dir.join("other.zip");
+    let download = Download {
+        url: &url,
+        checksum: &wrong,
+        size: None,
+    };
+    let error = download::fetch(&client, &download, &other, |_, _| {}).unwrap_err();
+    assert!(error.to_string().contains("is corrupt: expected sha1:0000"));
+    assert!(!other.exists());
+    assert!(!part_path(&other).exists());
+
+    fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,262 @@
+//! Parser tests against real-world output captured from several Android releases:
+//! Android 5 (toolbox), Android 9 (early toybox) and Android 14.
+
+use std::fs;
+use std::path::Path;
+
+use monocli::parsers::{
+    parse_battery, parse_df, parse_getprop, parse_meminfo, parse_net_dev, parse_proc_stat, parse_ps,
+    parse_thermal_zones, parse_top, BatteryStatus,
+};
+
+fn fixture(version: &str, name: &str) -> String {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/fixtures/parsers")
+        .join(version)
+        .join(name);
+    fs::read_to_string(&path).unwrap_or_else(|e| panic!("{}: {}", path.display(), e))
+}
+
+const GIB: u64 = 1 << 30;
+
+#[test]
+fn getprop_across_versions() {
+    let props = parse_getprop(&fixture("android-5", "getprop.txt")).unwrap();
+    assert_eq!(props.get("ro.product.model"), Some("Nexus 5"));
+    assert_eq!(props.get("ro.build.version.sdk"), Some("22"));
+
+    let props = parse_getprop(&fixture("android-9", "getprop.txt")).unwrap();
+    assert_eq!(props.get("ro.product.model"), Some("Pixel 2"));
+    assert_eq!(props.len(), 9);
+
+    let props = parse_getprop(&fixture("android-14", "getprop.txt")).unwrap();
+    assert_eq!(props.get("ro.product.model"), Some("SM-S911B"));
+    assert_eq!(props.get("ro.boot.hardware.revision"), Some("MP 0.200\nrev 2"));
+    assert_eq!(props.get("ro.boot.verifiedbootstate"), Some("green"));
+    assert_eq!(props.get("missing"), None);
+}
+
+#[test]
+fn getprop_errors_name_the_line() {
+    let error = parse_getprop("[a]: [1]\nnot a property\n").unwrap_err();
+    assert_eq!(error.format, "getprop");
+    assert_eq!(error.line, Some(2));
+
+    let error = parse_getprop("").unwrap_err();
+    assert_eq!(error.to_string(), "getprop: no properties found");
+
+    assert!(parse_getprop("[a]: [unterminated\n").is_err());
+}
+
+#[test]
+fn battery_across_versions() {
+    let battery = parse_battery(&fixture("android-5", "dumpsys_battery.txt")).unwrap();
+    assert_eq!(battery.level, 64);
+    assert_eq!(battery.status, BatteryStatus::Charging);
+    assert!(battery.usb_powered);
+    assert_eq!(battery.technology.as_deref(), Some("Li-poly"));
+
+    let battery = parse_battery(&fixture("android-9", "dumpsys_battery.txt")).unwrap();
+    assert_eq!(battery.percent(), 100);
+    assert_eq!(battery.status, BatteryStatus::Full);
+    assert!(battery.ac_powered);
+    assert_eq!(battery.voltage_mv, Some(4382));
+
+    let battery = parse_battery(&fixture("android-14", "dumpsys_battery.txt")).unwrap();
+    assert_eq!(battery.level, 47);
+    assert_eq!(battery.status, BatteryStatus::Discharging);
+    assert_eq!(battery.temperature_c, Some(31.2));
+    assert_eq!(battery.present, Some(true));
+
+    // A bogus level doesn't overflow.
+    let battery = parse_battery(&format!("  level: {}\n  scale: {}\n", u32::MAX, u32::MAX)).unwrap();
+    assert_eq!(battery.percent(), 100);
+}
+
+#[test]
+fn battery_errors() {
+    let error = parse_battery("Current Battery Service state:\n  status: 2\n").unwrap_err();
+    assert_eq!(error.to_string(), "dumpsys battery: missing 'level'");
+
+    let error = parse_battery("  level: high\n").unwrap_err();
+    assert_eq!(error.format, "dumpsys battery");
+}
+
+#[test]
+fn meminfo_across_versions() {
+    // Android 5 kernels predate MemAvailable.
+    let mem = parse_meminfo(&fixture("android-5", "meminfo.txt")).unwrap();
+    assert_eq!(mem.total_kb, 1_899_988);
+    assert_eq!(mem.available_kb, None);
+    assert_eq!(mem.used_kb(), 1_899_988 - (123_516 + 21_184 + 511_292));
+
+    let mem = parse_meminfo(&fixture("android-9", "meminfo.txt")).unwrap();
+    assert_eq!(mem.available_kb, Some(1_612_440));
+    assert_eq!(mem.used_kb(), 3_756_068 - 1_612_440);
+    assert_eq!(mem.fields["CmaTotal"], 200_704);
+
+    let mem = parse_meminfo(&fixture("android-14", "meminfo.txt")).unwrap();
+    assert_eq!(mem.total_kb, 7_486_156);
+    assert_eq!(mem.free_kb, 238_636);
+    assert_eq!(mem.swap_total_kb - mem.swap_free_kb, 8_388_604 - 6_071_204);
+}
+
+#[test]
+fn meminfo_errors() {
+    let error = parse_meminfo("MemFree: 10 kB\n").unwrap_err();
+    assert_eq!(error.to_string(), "/proc/meminfo: missing 'MemTotal'");
+
+    let error = parse_meminfo("MemTotal: 10 kB\nbogus\n").unwrap_err();
+    assert_eq!(error.line, Some(2));
+}
+
+#[test]
+fn df_across_versions() {
+    // Toolbox: the first column is the mount point, sizes are human readable.
+    let mounts = parse_df(&fixture("android-5", "df.txt")).unwrap();
+    let data = mounts.iter().find(|m| m.mounted_on == "/data").unwrap();
+    assert_eq!(data.size_bytes, (12.9 * GIB as f64).round() as u64);
+    assert_eq!(data.available_bytes, (8.6 * GIB as f64).round() as u64);
+
+    // Toybox `df`: 1K blocks, with a wrapped device name.
+    let mounts = parse_df(&fixture("android-9", "df.txt")).unwrap();
+    assert_eq!(mounts.len(), 5);
+    let data = mounts.iter().find(|m| m.mounted_on == "/data").unwrap();
+    assert_eq!(data.filesystem, "/dev/block/bootdevice/by-name/userdata");
+    assert_eq!(data.size_bytes, 53_311_340 * 1024);
+    assert_eq!(data.used_bytes, 8_735_288 * 1024);
+
+    // Toybox `df -h`.
+    let mounts = parse_df(&fixture("android-14", "df.txt")).unwrap();
+    let data = mounts.iter().find(|m| m.mounted_on == "/data").unwrap();
+    assert_eq!(data.size_bytes, 225 * GIB);
+    assert_eq!(data.used_bytes, 61 * GIB);
+    assert!((data.use_percent() - 27.1).abs() < 0.1);
+    let root = mounts.iter().find(|m| m.mounted_on == "/").unwrap();
+    assert_eq!(root.available_bytes, 16 << 20);
+}
+
+#[test]
+fn df_errors() {
+    let error = parse_df("Mounted on\n").unwrap_err();
+    assert_eq!(error.format, "df");
+
+    let error = parse_df("Filesystem Size Used Avail Use% Mounted on\n/dev/root 1G lots 1G 1% /\n").unwrap_err();
+    assert_eq!(error.line, Some(2));
+    assert!(error.message.contains("lots"));
+}
+
+#[test]
+fn ps_across_versions() {
+    // Toolbox puts an unlabelled state column before NAME.
+    let processes = parse_ps(&fixture("android-5", "ps.txt")).unwrap();
+    assert_eq!(processes.len(), 5);
+    let server = processes.iter().find(|p| p.name == "system_server").unwrap();
+    assert_eq!(server.pid, 782);
+    assert_eq!(server.ppid, Some(199));
+    assert_eq!(server.rss_kb, Some(112_340));
+    assert_eq!(server.state, Some('S'));
+    assert_eq!(processes.last().unwrap().state, Some('R'));
+
+    let processes = parse_ps(&fixture("android-9", "ps.txt")).unwrap();
+    let launcher = processes.iter().find(|p| p.pid == 2254).unwrap();
+    assert_eq!(launcher.user, "u0_a58");
+    assert_eq!(launcher.name, "com.google.android.apps.nexuslauncher");
+    assert_eq!(launcher.state, Some('S'));
+
+    // `ps -ef` keeps arguments in CMD.
+    let processes = parse_ps(&fixture("android-14", "ps.txt")).unwrap();
+    assert_eq!(processes[0].name, "init second_stage");
+    assert_eq!(processes[0].user, "root");
+    assert_eq!(processes[0].rss_kb, None);
+    assert_eq!(processes.last().unwrap().name, "ps -ef");
+}
+
+#[test]
+fn ps_errors() {
+    let error = parse_ps("USER PID NAME\nroot one init\n").unwrap_err();
+    assert_eq!(error.format, "ps");
+    assert_eq!(error.line, Some(2));
+
+    let error = parse_ps("NAME CMD\ninit init\n").unwrap_err();
+    assert_eq!(error.to_string(), "ps: missing USER/UID column");
+}
+
+#[test]
+fn top_across_versions() {
+    let top = parse_top(&fixture("android-5", "top.txt")).unwrap();
+    assert_eq!(top.cpu.user, 5.0);
+    assert_eq!(top.cpu.system, 3.0);
+    assert_eq!(top.cpu.idle, 92.0);
+    assert_eq!(top.processes.len(), 3);
+    assert_eq!(top.processes[0].name, "top");
+    assert_eq!(top.processes[0].cpu_percent, 3.0);
+    // Kernel threads have no scheduling policy.
+    assert_eq!(top.processes[2].user, "root");
+    assert_eq!(top.processes[2].name, "ksoftirqd/0");
+
+    let top = parse_top(&fixture("android-9", "top.txt")).unwrap();
+    assert!((top.cpu.user - 2.25).abs() < 1e-9);
+    assert!((top.cpu.idle - 94.375).abs() < 1e-9);
+    assert_eq!(top.processes[1].name, "system_server");
+    assert_eq!(top.processes[1].mem_percent, Some(5.2));
+
+    let top = parse_top(&fixture("android-14", "top.txt")).unwrap();
+    assert!((top.cpu.user - 33.0 / 8.0).abs() < 1e-9);
+    assert!((top.cpu.irq - 5.0 / 8.0).abs() < 1e-9);
+    assert_eq!(top.processes[0].pid, 28116);
+    assert_eq!(top.processes[0].name, "top -n 1");
+    assert_eq!(top.processes[2].user, "u0_a233");
+}
+
+#[test]
+fn top_errors() {
+    let error = parse_top("Tasks: 1 total\n").unwrap_err();
+    assert_eq!(error.to_string(), "top: no CPU summary line found");
+}
+
+#[test]
+fn net_dev_across_versions() {
+    let interfaces = parse_net_dev(&fixture("android-14", "net_dev.txt")).unwrap();
+    assert_eq!(interfaces.len(), 4);
+    let wlan = interfaces.iter().find(|i| i.interface == "wlan0").unwrap();
+    assert_eq!(wlan.rx_bytes, 1_843_301_921);
+    assert_eq!(wlan.rx_packets, 1_421_019);
+    assert_eq!(wlan.tx_bytes, 91_203_344);
+    assert_eq!(wlan.tx_packets, 630_121);
+
+    // Old kernels don't separate the name from the first counter.
+    let interfaces = parse_net_dev(&fixture("android-5", "net_dev.txt")).unwrap();
+    assert_eq!(interfaces[1].interface, "wlan0");
+    assert_eq!(interfaces[1].rx_bytes, 90_211_340);
+
+    let error = parse_net_dev("Inter-|\n face |\n wlan0: 1 2 3\n").unwrap_err();
+    assert_eq!(error.to_string(), "/proc/net/dev: line 3: expected 16 counters, got 3");
+}
+
+#[test]
+fn proc_stat_cpu_usage() {
+    let before = parse_proc_stat("cpu  100 0 100 700 100 0 0 0 0 0\ncpu0 50 0 50 350 50 0 0 0\n").unwrap();
+    let after = parse_proc_stat("cpu  300 0 200 900 100 0 0 0 0 0\n").unwrap();
+    assert_eq!(after.total(), 1500);
+    assert_eq!(after.usage_since(&before), Some(60.0));
+    assert_eq!(after.usage_since(&after), None);
+
+    // Android 5 era kernels list fewer fields.
+    let old = parse_proc_stat("cpu  10 2 5 80\n").unwrap();
+    assert_eq!(old.total(), 97);
+
+    assert_eq!(
+        parse_proc_stat("intr 1 2\n").unwrap_err().to_string(),
+        "/proc/stat: missing 'cpu' line"
+    );
+}
+
+#[test]
+fn thermal_zones_in_milli_or_whole_degrees() {
+    let zones = parse_thermal_zones("battery 31200\nskin-therm 36\nsdm-therm \nbad zone x\n").unwrap();
+    assert_eq!(zones.len(), 2);
+    assert_eq!((zones[0].name.as_str(), zones[0].celsius), ("battery", 31.2));
+    assert_eq!((zones[1].name.as_str(), zones[1].celsius), ("skin-therm", 36.0));
+    assert!(parse_thermal_zones("").is_err());
+}
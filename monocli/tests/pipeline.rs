@@ -0,0 +1,396 @@
+//! Runs the dump and report pipeline against a recorded fixture, no device needed.
+
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use monocli::device::Device;
+use monocli::dump::{dump_device_data, regenerate_report, run_dir_name, CaptureOptions};
+use monocli::adb::ShellOutput;
+use monocli::manifest::{Manifest, Outcome};
+use monocli::netstats;
+use monocli::report::ReportTheme;
+use monocli::transport::{DeviceTransport, Fixture, ReplayTransport};
+use monocore::config::{Config, Source};
+
+fn replay(name: &str) -> ReplayTransport {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/replay").join(name);
+    ReplayTransport::load(&path).unwrap()
+}
+
+fn dump(device: &Device, output_root: &Path) -> String {
+    dump_device_data(device, output_root, &CaptureOptions::default(), &ReportTheme::default()).unwrap()
+}
+
+#[test]
+fn replay_serves_recorded_commands() {
+    let transport = replay("pixel7.json");
+
+    assert_eq!(transport.serial(), "28151FDH2000QK");
+    assert_eq!(transport.getprop("ro.product.model").unwrap(), "Pixel 7");
+    assert_eq!(transport.getprop("ro.boot.verifiedbootstate").unwrap(), "green");
+    assert_eq!(transport.getprop("ro.does.not.exist").unwrap(), "");
+
+    let battery = transport.shell("dumpsys battery").unwrap();
+    assert!(battery.success());
+    assert!(battery.stdout_lossy().contains("level: 81"));
+
+    let root = transport.shell("su -c id").unwrap();
+    assert_eq!(root.exit_code, 127);
+
+    let missing = transport.shell("dumpsys nonexistent").unwrap();
+    assert_eq!(missing.exit_code, 127);
+}
+
+#[test]
+fn dump_runs_end_to_end_from_fixture() {
+    let output_root = std::env::temp_dir().join(format!("monocli-pipeline-{}", std::process::id()));
+    let device = Device::from_transport(replay("pixel7.json"));
+
+    let dump_dir = dump(&device, &output_root);
+    let dump_dir = Path::new(&dump_dir);
+    assert!(dump_dir.file_name().unwrap().to_string_lossy().starts_with("Pixel_7_28151FDH2000QK_"));
+    // Network serials and odd model names still make one folder.
+    assert_eq!(
+        run_dir_name("Galaxy A/B 5G", "10.0.0.5:5555", "20240812_101500"),
+        "Galaxy_A_B_5G_10.0.0.5_5555_20240812_101500"
+    );
+
+    let html = fs::read_to_string(dump_dir.join("dashboard.html")).unwrap();
+    assert!(html.contains("Device Dashboard - Pixel_7"));
+    assert!(html.contains("Battery Status"));
+    assert!(html.contains("<td>level</td><td>81</td>"));
+    assert!(html.contains("<li>Model: Pixel 7</li>"));
+    assert!(html.contains("<li>Android Version: 14</li>"));
+    assert!(html.contains("<li>Total RAM: 7824300 KB</li>"));
+    assert!(!html.contains("Parser Warnings"));
+
+    // Charts are inline SVG; nothing is fetched when the dashboard is opened.
+    assert!(html.contains("<div class=\"chart\" id=\"batteryChart\">\n<svg"));
+    assert!(!html.contains("<script src="));
+    assert!(!html.contains("https://"));
+
+    let manifest = Manifest::load(dump_dir).unwrap();
+    assert_eq!(manifest.serial, "28151FDH2000QK");
+    assert_eq!(manifest.device_name, "Pixel_7");
+    assert_eq!(manifest.profile, "full");
+
+    let battery = manifest.entries.iter().find(|e| e.command == "dumpsys battery").unwrap();
+    assert_eq!(battery.section, "device");
+    assert_eq!(battery.file, "device/dumpsys_battery.txt");
+    assert_eq!(battery.exit_code, Some(0));
+    let raw = fs::read_to_string(dump_dir.join(&battery.file)).unwrap();
+    assert!(raw.contains("level: 81"));
+    assert_eq!(battery.bytes, raw.len() as u64);
+
+    let unrecorded = manifest.entries.iter().find(|e| e.command == "dumpsys wifi").unwrap();
+    assert_eq!(unrecorded.section, "network");
+    assert_eq!(unrecorded.exit_code, Some(127));
+    assert!(unrecorded.stderr.contains("not recorded"));
+    assert_eq!(unrecorded.bytes, 0);
+
+    fs::remove_dir_all(&output_root).unwrap();
+}
+
+#[test]
+fn report_regenerates_identically_without_device() {
+    let output_root = std::env::temp_dir().join(format!("monocli-report-{}", std::process::id()));
+    let device = Device::from_transport(replay("pixel7.json"));
+
+    let dump_dir = dump(&device, &output_root);
+    let dump_dir = Path::new(&dump_dir);
+    let dashboard = dump_dir.join("dashboard.html");
+    let original = fs::read_to_string(&dashboard).unwrap();
+    fs::remove_file(&dashboard).unwrap();
+
+    regenerate_report(dump_dir, &ReportTheme::default()).unwrap();
+    assert_eq!(fs::read_to_string(&dashboard).unwrap(), original);
+
+    assert!(regenerate_report(&output_root, &ReportTheme::default()).is_err());
+
+    fs::remove_dir_all(&output_root).unwrap();
+}
+
+#[test]
+fn device_output_is_escaped() {
+    let output_root = std::env::temp_dir().join(format!("monocli-escape-{}", std::process::id()));
+    let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/replay/pixel7.json");
+    let mut fixture = Fixture::load(&path).unwrap();
+    let getprop = fixture.commands.get_mut("getprop").unwrap();
+    getprop.stdout.push_str("[persist.wifi.ssid]: [<script>alert(1)</script> & \"friends\"]\n");
+    let device = Device::from_transport(ReplayTransport::new(fixture));
+
+    let dump_dir = dump(&device, &output_root);
+    let html = fs::read_to_string(Path::new(&dump_dir).join("dashboard.html")).unwrap();
+    assert!(!html.contains("<script>alert(1)"));
+    assert!(html.contains("[&lt;script&gt;alert(1)&lt;/script&gt; &amp; &quot;friends&quot;]"));
+
+    fs::remove_dir_all(&output_root).unwrap();
+}
+
+#[test]
+fn logcat_card_counts_levels_and_lists_errors() {
+    let output_root = std::env::temp_dir().join(format!("monocli-logcat-{}", std::process::id()));
+    let device = Device::from_transport(replay("pixel7.json"));
+    let dump_dir = dump(&device, &output_root);
+    let html = fs::read_to_string(Path::new(&dump_dir).join("dashboard.html")).unwrap();
+
+    assert!(html.contains("<h2>Logcat</h2>"));
+    assert!(html.contains("<li>Errors: 4</li>"));
+    assert!(html.contains("<li>Warnings: 1</li>"));
+    assert!(html.contains("<tr><td>10-17 09:12:06.002</td><td>WifiVendorHal</td><td>getWifiLinkLayerStats failed</td></tr>"));
+    assert!(html.contains("java.lang.IllegalStateException: &lt;closed&gt;"));
+
+    fs::remove_dir_all(&output_root).unwrap();
+}
+
+#[test]
+fn custom_template_and_theme() {
+    let output_root = std::env::temp_dir().join(format!("monocli-template-{}", std::process::id()));
+    let device = Device::from_transport(replay("pixel7.json"));
+    let dump_dir = dump(&device, &output_root);
+    let dump_dir = Path::new(&dump_dir);
+
+    let template = output_root.join("branded.html");
+    let theme = output_root.join("branded.css");
+    fs::write(&template, "<style>{{ stylesheet }}</style><h1>ACME {{device_name}}</h1>{{cards}}{{sections}}").unwrap();
+    fs::write(&theme, "h1 { color: #c00; }").unwrap();
+
+    let branded = ReportTheme::new(Some(&template), Some(&theme)).unwrap();
+    regenerate_report(dump_dir, &branded).unwrap();
+    let html = fs::read_to_string(dump_dir.join("dashboard.html")).unwrap();
+    assert!(html.starts_with("<style>h1 { color: #c00; }</style><h1>ACME Pixel_7</h1>"));
+    assert!(html.contains("<li>Model: Pixel 7</li>"));
+
+    fs::write(&template, "<h1>{{device_nmae}}</h1>").unwrap();
+    let error = ReportTheme::new(Some(&template), None).err().unwrap();
+    assert_eq!(error.exit_code(), 2);
+    assert!(error.to_string().contains("unknown placeholder '{{device_nmae}}'"));
+
+    fs::write(&template, "<h1>{{device_name</h1>").unwrap();
+    let error = ReportTheme::new(Some(&template), None).err().unwrap();
+    assert!(error.to_string().contains("line 1: unterminated"));
+
+    fs::remove_dir_all(&output_root).unwrap();
+}
+
+/// Replays a fixture, but `ls -lR /sdcard` hangs until its timeout.
+struct SlowSdcard(ReplayTransport);
+
+impl DeviceTransport for SlowSdcard {
+    fn serial(&self) -> &str {
+        self.0.serial()
+    }
+
+    fn shell(&self, cmd: &str) -> io::Result<ShellOutput> {
+        self.0.shell(cmd)
+    }
+
+    fn shell_timeout(&self, cmd: &str, timeout: Duration) -> io::Result<ShellOutput> {
+        if cmd == "ls -lR /sdcard" {
+            thread::sleep(timeout);
+            return Err(io::Error::new(io::ErrorKind::TimedOut, "command timed out"));
+        }
+        self.0.shell(cmd)
+    }
+
+    fn push(&self, local: &Path, remote: &str, mode: u32) -> io::Result<()> {
+        self.0.push(local, remote, mode)
+    }
+
+    fn pull(&self, remote: &str, local: &Path) -> io::Result<u64> {
+        self.0.pull(remote, local)
+    }
+
+    fn getprop(&self, name: &str) -> io::Result<String> {
+        self.0.getprop(name)
+    }
+}
+
+#[test]
+fn timeouts_are_recorded_and_do_not_block_the_dump() {
+    let output_root = std::env::temp_dir().join(format!("monocli-timeout-{}", std::process::id()));
+    let device = Device::from_transport(SlowSdcard(replay("pixel7.json")));
+    let mut options = CaptureOptions {
+        jobs: 8,
+        timeout: Duration::from_secs(5),
+        ..CaptureOptions::default()
+    };
+    options.timeouts.insert("ls -lR /sdcard".to_string(), Duration::from_millis(300));
+
+    let started = Instant::now();
+    let dump_dir = dump_device_data(&device, &output_root, &options, &ReportTheme::default()).unwrap();
+    assert!(started.elapsed() < Duration::from_secs(5));
+
+    let manifest = Manifest::load(Path::new(&dump_dir)).unwrap();
+    assert!(!manifest.cancelled);
+    let slow = manifest.entries.iter().find(|e| e.command == "ls -lR /sdcard").unwrap();
+    assert_eq!(slow.outcome, Outcome::TimedOut);
+    assert_eq!(slow.exit_code, None);
+    assert_eq!(slow.timeout_ms, Some(300));
+    assert!(Path::new(&dump_dir).join(&slow.file).exists());
+
+    // Entries stay in plan order even though commands ran concurrently.
+    assert_eq!(manifest.entries[0].command, "getprop");
+    assert_eq!(manifest.entries.last().unwrap().command, "logcat -d -v threadtime");
+    let battery = manifest.entries.iter().find(|e| e.command == "dumpsys battery").unwrap();
+    assert_eq!(battery.outcome, Outcome::Completed);
+    assert_eq!(battery.timeout_ms, Some(5000));
+
+    fs::remove_dir_all(&output_root).unwrap();
+}
+
+#[test]
+fn profiles_from_config_choose_commands_and_sections() {
+    let output_root = std::env::temp_dir().join(format!("monocli-profile-{}", std::process::id()));
+    let device = Device::from_transport(replay("pixel7.json"));
+    let mut config = Config::default();
+    let text = r#"
+[profiles.radio]
+description = "Radio state"
+commands = [
+    { section = "device", command = "getprop" },
+    { section = "wifi-debug", command = "dumpsys wifi", timeout = 2.5 },
+    { section = "wifi-debug", command = "iptables -L -n", root = true },
+]
+"#;
+    config.apply_toml(text, Path::new("mono.toml")).unwrap();
+    config.set("dump.profile", "radio", Source::Cli("--profile".to_string())).unwrap();
+    let options = CaptureOptions::from_config(&config).unwrap();
+
+    let dump_dir = dump_device_data(&device, &output_root, &options, &ReportTheme::default()).unwrap();
+    let dump_dir = Path::new(&dump_dir);
+
+    let manifest = Manifest::load(dump_dir).unwrap();
+    assert_eq!(manifest.profile, "radio");
+    let commands: Vec<&str> = manifest.entries.iter().map(|e| e.command.as_str()).collect();
+    assert_eq!(commands, ["getprop", "dumpsys wifi", "iptables -L -n"]);
+    assert_eq!(manifest.entries[1].file, "wifi-debug/dumpsys_wifi.txt");
+    assert_eq!(manifest.entries[1].timeout_ms, Some(2500));
+
+    // The fixture device isn't rooted, so the root command is skipped, not failed.
+    let iptables = &manifest.entries[2];
+    assert!(iptables.root);
+    assert_eq!(iptables.outcome, Outcome::Skipped);
+    assert!(dump_dir.join(&iptables.file).exists());
+
+    let html = fs::read_to_string(dump_dir.join("dashboard.html")).unwrap();
+    assert!(html.contains("Device Information"));
+    assert!(html.contains("Wifi Debug"));
+    assert!(!html.contains("System Settings"));
+
+    fs::remove_dir_all(&output_root).unwrap();
+}
+
+#[test]
+fn unknown_or_invalid_profiles_are_usage_errors() {
+    let mut config = Config::default();
+    config.set("dump.profile", "nope", Source::Cli("--profile".to_string())).unwrap();
+    let error = CaptureOptions::from_config(&config).unwrap_err();
+    assert_eq!(error.exit_code(), 2);
+    assert!(error.to_string().contains("quick"));
+
+    let mut config = Config::default();
+    let text = "[profiles.empty]\ncommands = []\n";
+    config.apply_toml(text, Path::new("mono.toml")).unwrap();
+    let error = CaptureOptions::from_config(&config).unwrap_err();
+    assert_eq!(error.exit_code(), 2);
+    assert!(error.to_string().contains("profile 'empty' has no commands"));
+}
+
+/// Serves a `/proc/net/dev` whose wlan0 counters grow by 1 MB per read.
+struct BusyWlan(ReplayTransport, AtomicU64);
+
+impl DeviceTransport for BusyWlan {
+    fn serial(&self) -> &str {
+        self.0.serial()
+    }
+
+    fn shell(&self, cmd: &str) -> io::Result<ShellOutput> {
+        if cmd != netstats::NET_DEV_COMMAND {
+            return self.0.shell(cmd);
+        }
+        let rx = self.1.fetch_add(1_000_000, Ordering::SeqCst);
+        let stdout = format!(
+            "Inter-|   Receive |  Transmit\n face |bytes packets|bytes packets\n    lo: 100 1 0 0 0 0 0 0 100 1 0 0 0 0 0 0\n wlan0: {} 10 0 0 0 0 0 0 {} 5 0 0 0 0 0 0\n",
+            rx,
+            rx / 10
+        );
+        Ok(ShellOutput {
+            stdout: stdout.into_bytes(),
+            stderr: Vec::new(),
+            exit_code: 0,
+        })
+    }
+
+    fn push(&self, local: &Path, remote: &str, mode: u32) -> io::Result<()> {
+        self.0.push(local, remote, mode)
+    }
+
+    fn pull(&self, remote: &str, local: &Path) -> io::Result<u64> {
+        self.0.pull(remote, local)
+    }
+
+    fn getprop(&self, name: &str) -> io::Result<String> {
+        self.0.getprop(name)
+    }
+}
+
+#[test]
+fn network_rates_are_sampled_during_the_dump() {
+    let output_root = std::env::temp_dir().join(format!("monocli-netdev-{}", std::process::id()));
+    let device = Device::from_transport(BusyWlan(replay("pixel7.json"), AtomicU64::new(0)));
+    let options = CaptureOptions {
+        net_interval: Some(Duration::from_millis(20)),
+        ..CaptureOptions::default()
+    };
+
+    let dump_dir = dump_device_data(&device, &output_root, &options, &ReportTheme::default()).unwrap();
+    let dump_dir = Path::new(&dump_dir);
+
+    let manifest = Manifest::load(dump_dir).unwrap();
+    assert_eq!(manifest.network_samples.as_deref(), Some("network/net_dev.csv"));
+    let rows = netstats::read_csv(&dump_dir.join("network/net_dev.csv")).unwrap();
+    let wlan: Vec<_> = rows.iter().filter(|row| row.interface == "wlan0").collect();
+    assert!(wlan.len() >= 2);
+    assert_eq!(wlan[0].rx_rate, None);
+    for pair in wlan.windows(2) {
+        assert!(pair[1].time > pair[0].time);
+        assert_eq!(pair[1].rx_bytes - pair[0].rx_bytes, 1_000_000);
+        assert!(pair[1].rx_rate.unwrap() > 0.0);
+        assert!(pair[1].tx_rate.unwrap() > 0.0);
+    }
+
+    // The chart plots the busiest interface, and is redrawn identically from the CSV.
+    let dashboard = dump_dir.join("dashboard.html");
+    let original = fs::read_to_string(&dashboard).unwrap();
+    assert!(original.contains("wlan0 rx"));
+    assert!(!original.contains("lo rx"));
+    fs::remove_file(&dashboard).unwrap();
+    regenerate_report(dump_dir, &ReportTheme::default()).unwrap();
+    assert_eq!(fs::read_to_string(&dashboard).unwrap(), original);
+
+    fs::remove_dir_all(&output_root).unwrap();
+}
+
+#[test]
+fn network_csv_quotes_interface_names() {
+    let path = std::env::temp_dir().join(format!("monocli-netdev-csv-{}.csv", std::process::id()));
+    let row = netstats::RateRow {
+        time: chrono::DateTime::parse_from_rfc3339("2026-01-02T03:04:05.678+01:00").unwrap(),
+        interface: "odd,\"name\"".to_string(),
+        rx_bytes: 10,
+        tx_bytes: 20,
+        rx_rate: Some(1.5),
+        tx_rate: None,
+    };
+
+    netstats::write_csv(&path, std::slice::from_ref(&row)).unwrap();
+    assert!(fs::read_to_string(&path).unwrap().contains(",\"odd,\"\"name\"\"\",10,20,1.5,\n"));
+    assert_eq!(netstats::read_csv(&path).unwrap(), [row]);
+
+    fs::remove_file(&path).unwrap();
+}
@@ -0,0 +1,269 @@
+//! `monocli upgrade` options, canary selection and the staged rollout.
+
+mod common;
+
+use std::fs;
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::TcpListener;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use common::{exclusive, shared};
+use monocli::adb::ShellOutput;
+use monocli::cancel;
+use monocli::device::Device;
+use monocli::error::MonoError;
+use monocli::install::{Install, CONFIG_PATH, DAEMON_PATH};
+use monocli::transport::DeviceTransport;
+use monocli::upgrade::{canary_indices, rollout, upgrade_device, UpgradeOptions};
+use monocore::control::reply;
+use monocore::version::BuildInfo;
+
+/// A rooted device whose daemon is running, connected or not, unless its
+/// service is `stopped`. Pushes are only recorded, and press Ctrl-C if
+/// `cancel_on_push` is set.
+#[derive(Default)]
+struct Fleet {
+    serial: String,
+    connected: bool,
+    stopped: bool,
+    cancel_on_push: bool,
+    ran: Arc<Mutex<Vec<String>>>,
+}
+
+impl DeviceTransport for Fleet {
+    fn serial(&self) -> &str {
+        &self.serial
+    }
+
+    /// Fails once Ctrl-C is pressed, as reads from adb do.
+    fn shell(&self, cmd: &str) -> io::Result<ShellOutput> {
+        if cancel::requested() {
+            return Err(cancel::error());
+        }
+        self.shell_uncancellable(cmd, Duration::ZERO)
+    }
+
+    fn shell_uncancellable(&self, cmd: &str, _timeout: Duration) -> io::Result<ShellOutput> {
+        self.ran.lock().unwrap().push(cmd.to_string());
+        let stdout = if cmd == "su -c id" {
+            "uid=0(root)\n"
+        } else if cmd.contains("echo daemon=") && self.stopped {
+            "installed\nstopped\ndaemon=\n"
+        } else if cmd.contains("echo daemon=") {
+            "installed\nsupervisor=100\ndaemon=4242\n"
+        } else {
+            ""
+        };
+        Ok(ShellOutput {
+            stdout: stdout.as_bytes().to_vec(),
+            stderr: Vec::new(),
+            exit_code: 0,
+        })
+    }
+
+    fn push(&self, _local: &Path, remote: &str, _mode: u32) -> io::Result<()> {
+        self.ran.lock().unwrap().push(format!("push {}", remote));
+        if self.cancel_on_push {
+            cancel::request();
+        }
+        Ok(())
+    }
+
+    fn pull(&self, remote: &str, _local: &Path) -> io::Result<u64> {
+        Err(io::Error::new(io::ErrorKind::Unsupported, remote.to_string()))
+    }
+
+    /// Answers a status request from the daemon.
+    fn forward(&self, _remote: &str) -> io::Result<u16> {
+        let listener = TcpListener::bind("127.0.0.1:0")?;
+        let port = listener.local_addr()?.port();
+        let status = format!(
+            "pid=4242\nbuild=0.2.0 (def456)\nserver=10.0.0.5:12345\nstarted=0\nupdated=0\nconnected={}\n",
+            u8::from(self.connected)
+        );
+        thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            BufReader::new(&stream).read_line(&mut String::new()).unwrap();
+            stream.write_all(reply(Ok(status)).as_bytes()).unwrap();
+        });
+        Ok(port)
+    }
+}
+
+fn fleet(count: usize, unhealthy: &[usize]) -> Vec<Device> {
+    (0..count)
+        .map(|index| {
+            Device::from_transport(Fleet {
+                serial: format!("device{:02}", index),
+                connected: !unhealthy.contains(&index),
+                ..Fleet::default()
+            })
+        })
+        .collect()
+}
+
+fn args(line: &str) -> Vec<String> {
+    line.split_whitespace().map(str::to_string).collect()
+}
+
+fn parse(line: &str) -> monocli::error::Result<UpgradeOptions> {
+    UpgradeOptions::from_args(&mut args(line))
+}
+
+#[test]
+fn options_take_their_flags_and_leave_the_rest() {
+    let _shared = shared();
+    let mut rest = args("--canary 10% -s emulator-5554 --soak 10m --force");
+    let options = UpgradeOptions::from_args(&mut rest).unwrap();
+    assert_eq!(
+        options,
+        UpgradeOptions {
+            canary: Some(10),
+            soak: Some(Duration::from_secs(600)),
+            force: true,
+        }
+    );
+    assert_eq!(rest, args("-s emulator-5554"));
+    assert_eq!(parse("").unwrap(), UpgradeOptions::default());
+
+    for (line, message) in [
+        ("--canary 0", "Invalid --canary '0'"),
+        ("--canary 101", "Invalid --canary '101'"),
+        ("--canary", "--canary needs a percentage"),
+        ("--canary 5 --soak soon", "Invalid --soak 'soon'"),
+        ("--soak 5m", "--soak only applies with --canary"),
+    ] {
+        let error = parse(line).unwrap_err();
+        assert!(matches!(error, MonoError::Usage(_)), "{}", line);
+        assert!(error.to_string().contains(message), "{}: {}", line, error);
+    }
+}
+
+#[test]
+fn canaries_are_stable_and_grow_with_the_share() {
+    let _shared = shared();
+    let serials: Vec<String> = (0..20).map(|index| format!("device{:02}", index)).collect();
+    let serials: Vec<&str> = serials.iter().map(String::as_str).collect();
+
+    let ten = canary_indices(&serials, 10);
+    assert_eq!(ten.len(), 2);
+    assert_eq!(canary_indices(&serials, 10), ten);
+    let fifty = canary_indices(&serials, 50);
+    assert_eq!(fifty.len(), 10);
+    assert!(ten.iter().all(|index| fifty.contains(index)));
+    assert!(fifty.windows(2).all(|pair| pair[0] < pair[1]));
+    assert_eq!(canary_indices(&serials, 100), (0..20).collect::<Vec<_>>());
+
+    // The pick doesn't depend on the order devices were listed in.
+    let reversed: Vec<&str> = serials.iter().rev().copied().collect();
+    let picked: Vec<&str> = ten.iter().map(|&index| serials[index]).collect();
+    let mut picked_reversed: Vec<&str> = canary_indices(&reversed, 10).iter().map(|&index| reversed[index]).collect();
+    picked_reversed.sort();
+    assert_eq!(picked_reversed, picked);
+
+    // At least one device, however small the share.
+    assert_eq!(canary_indices(&serials[..3], 1).len(), 1);
+}
+
+#[test]
+fn rollout_holds_the_rest_until_the_canaries_prove_healthy() {
+    let _shared = shared();
+    let devices = fleet(10, &[]);
+    let canaries = canary_indices(&devices.iter().map(|device| device.serial.as_str()).collect::<Vec<_>>(), 20);
+    let upgraded = Mutex::new(Vec::new());
+    let upgrade = |device: &Device| {
+        upgraded.lock().unwrap().push(device.serial.clone());
+        Ok("upgraded".to_string())
+    };
+    let serials = |indices: &[usize]| indices.iter().map(|&index| format!("device{:02}", index)).collect::<Vec<_>>();
+
+    // Without a soak time only the canaries are upgraded.
+    let options = parse("--canary 20").unwrap();
+    let reports = rollout(&devices, &options, "10.0.0.5:12345", upgrade);
+    assert_eq!(reports.iter().map(|report| report.serial.clone()).collect::<Vec<_>>(), serials(&canaries));
+    assert_eq!(upgraded.lock().unwrap().len(), 2);
+
+    // With one, the rest follow once the canaries are still connected, and
+    // the reports come back in selection order.
+    upgraded.lock().unwrap().clear();
+    let options = parse("--canary 20 --soak 0s").unwrap();
+    let reports = rollout(&devices, &options, "10.0.0.5:12345", upgrade);
+    assert_eq!(reports.len(), 10);
+    assert_eq!(reports.iter().map(|report| report.serial.clone()).collect::<Vec<_>>(), serials(&(0..10).collect::<Vec<_>>()));
+    let mut first = upgraded.lock().unwrap()[..2].to_vec();
+    first.sort();
+    assert_eq!(first, serials(&canaries));
+
+    // A canary that loses its connection stops the rollout.
+    let devices = fleet(10, &canaries[..1]);
+    upgraded.lock().unwrap().clear();
+    let reports = rollout(&devices, &options, "10.0.0.5:12345", upgrade);
+    assert_eq!(reports.len(), 2);
+    let failed = reports.iter().find(|report| report.result.is_err()).unwrap();
+    assert_eq!(failed.serial, format!("device{:02}", canaries[0]));
+    assert!(failed.result.as_ref().unwrap_err().to_string().contains("not connected to 10.0.0.5:12345"));
+    assert_eq!(upgraded.lock().unwrap().len(), 2);
+
+    // A canary stopped with monocli service stop stays stopped, and isn't
+    // held against the rollout.
+    let mut devices = fleet(10, &[]);
+    devices[canaries[0]] = Device::from_transport(Fleet {
+        serial: format!("device{:02}", canaries[0]),
+        stopped: true,
+        ..Fleet::default()
+    });
+    let reports = rollout(&devices, &options, "10.0.0.5:12345", upgrade);
+    assert_eq!(reports.len(), 10);
+    assert!(reports.iter().all(|report| report.result.is_ok()));
+
+    // As does a canary whose upgrade fails.
+    let devices = fleet(10, &[]);
+    let reports = rollout(&devices, &options, "10.0.0.5:12345", |device| {
+        Err(MonoError::Other(format!("cannot upgrade {}", device.serial)))
+    });
+    assert_eq!(reports.len(), 2);
+}
+
+#[test]
+fn cancelled_upgrade_is_still_rolled_back() {
+    let _exclusive = exclusive();
+    let binary = std::env::temp_dir().join(format!("monocli-upgrade-cancelled-{}", std::process::id()));
+    let info = BuildInfo {
+        version: "0.3.0".to_string(),
+        git: "fed987".to_string(),
+    };
+    fs::write(&binary, [&b"\x7fELF\0"[..], info.line().as_bytes()].concat()).unwrap();
+    let ran = Arc::new(Mutex::new(Vec::new()));
+    let device = Device::from_transport(Fleet {
+        serial: "device00".to_string(),
+        connected: true,
+        stopped: false,
+        cancel_on_push: true,
+        ran: ran.clone(),
+    });
+    let install = Install {
+        binary: &binary,
+        config: "[daemon]\nserver = \"10.0.0.5:12345\"\n",
+        server: "10.0.0.5:12345",
+        timeout: Duration::ZERO,
+    };
+
+    // The running daemon reports 0.2.0, so the binary isn't read.
+    let result = upgrade_device(&device, &install, false);
+    cancel::reset();
+    let error = result.unwrap_err();
+    assert_eq!(error.exit_code(), 130);
+    assert_eq!(error.to_string(), "verify the pushed daemon failed (rolled back): Install cancelled");
+
+    // The staged files are removed even though Ctrl-C was pressed.
+    let ran = ran.lock().unwrap();
+    assert!(!ran.iter().any(|cmd| cmd.contains("grep")));
+    let push = ran.iter().position(|cmd| *cmd == format!("push {}.new", DAEMON_PATH)).unwrap();
+    assert_eq!(ran[push + 1..], [format!("rm -f {}.new {}.new", DAEMON_PATH, CONFIG_PATH)]);
+    drop(ran);
+
+    fs::remove_file(&binary).unwrap();
+}
@@ -0,0 +1,428 @@
+//! `mono.toml`: settings shared by monocli, monodeamon and monoserve.
+//!
+//! Every setting has a built-in default, which can be overridden, in order of
+//! increasing precedence, by the config file, a `MONO_*` environment
+//! variable, and the command line. Each value remembers which layer it came
+//! from so `monocli config show` can explain it.
+//!
+//! ```toml
+//! [daemon]
+//! server = "10.0.0.5:12345"
+//!
+//! [dump]
+//! jobs = 8
+//!
+//! [dump.timeouts]
+//! "ls -lR /sdcard" = 300
+//! ```
+//!
+//! A few sections, such as `[profiles]`, are structured data rather than
+//! settings; they are kept as parsed TOML for the tool that owns them.
+
+use std::collections::BTreeMap;
+use std::env;
+use std::ffi::OsString;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+pub const CONFIG_FILE: &str = "mono.toml";
+
+/// Environment variable naming the config file explicitly.
+pub const CONFIG_ENV: &str = "MONO_CONFIG";
+
+/// A known setting and its built-in default.
+pub struct Setting {
+    pub key: &'static str,
+    pub default: &'static str,
+    pub help: &'static str,
+}
+
+pub const SETTINGS: &[Setting] = &[
+    Setting {
+        key: "adb.path",
+        default: "adb",
+        help: "adb binary used to start the adb server when it isn't running",
+    },
+    Setting {
+        key: "daemon.project",
+        default: "../monodeamon",
+        help: "monodeamon cargo project that monocli builds and installs",
+    },
+    Setting {
+        key: "daemon.server",
+        default: "192.168.1.100:12345",
+        help: "monoserve address the daemon streams logs to",
+    },
+    Setting {
+        key: "daemon.min_level",
+        default: "V",
+        help: "least severe logcat level the daemon forwards (V, D, I, W, E or F)",
+    },
+    Setting {
+        key: "daemon.spool",
+        default: "10000",
+        help: "logcat lines the daemon holds while monoserve is unreachable; the oldest are dropped beyond this",
+    },
+    Setting {
+        key: "server.bind",
+        default: "0.0.0.0:12345",
+        help: "address monoserve listens on",
+    },
+    Setting {
+        key: "server.log_dir",
+        default: ".",
+        help: "directory monoserve writes logs_<client>.txt files into",
+    },
+    Setting {
+        key: "dump.dir",
+        default: "./dump",
+        help: "where monocli dump creates dump folders",
+    },
+    Setting {
+        key: "dump.jobs",
+        default: "4",
+        help: "capture commands run at once per device",
+    },
+    Setting {
+        key: "dump.net_interval",
+        default: "1",
+        help: "seconds between /proc/net/dev samples during a dump, 0 to disable",
+    },
+    Setting {
+        key: "dump.profile",
+        default: "full",
+        help: "capture profile monocli dump runs",
+    },
+    Setting {
+        key: "dump.timeout",
+        default: "60",
+        help: "seconds a capture command may run before it is abandoned",
+    },
+    Setting {
+        key: "monitor.dir",
+        default: "./monitor",
+        help: "where monocli monitor creates its sample folders",
+    },
+    Setting {
+        key: "monitor.interval",
+        default: "2s",
+        help: "time between monitor samples, e.g. 500ms, 2s or 1m",
+    },
+    Setting {
+        key: "monitor.duration",
+        default: "10m",
+        help: "how long monocli monitor samples for, e.g. 30s, 10m or 1h",
+    },
+    Setting {
+        key: "ndk.version",
+        default: "",
+        help: "NDK version to build with, e.g. 26, 26.1 or r26b; empty for the highest installed",
+    },
+    Setting {
+        key: "ndk.min_version",
+        default: "",
+        help: "oldest NDK version to build with; empty for no minimum",
+    },
+    Setting {
+        key: "ndk.repository",
+        default: "https://dl.google.com/android/repository/",
+        help: "SDK repository monocli ndk install downloads NDKs from",
+    },
+];
+
+/// Tables whose keys are free-form, e.g. `[dump.timeouts]` keyed by command line.
+pub const SETTING_TABLES: &[&str] = &["dump.timeouts"];
+
+/// Top-level sections passed through as TOML instead of being flattened
+/// into settings, e.g. `[profiles.<name>]` capture profiles.
+pub const STRUCTURED_SECTIONS: &[&str] = &["profiles"];
+
+/// Where a value came from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Source {
+    Default,
+    File(PathBuf),
+    Env(String),
+    Cli(String),
+}
+
+impl fmt::Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Source::Default => f.write_str("default"),
+            Source::File(path) => write!(f, "{}", path.display()),
+            Source::Env(var) => write!(f, "env {}", var),
+            Source::Cli(flag) => write!(f, "flag {}", flag),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Value {
+    pub value: String,
+    pub source: Source,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConfigError(pub String);
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+/// The effective configuration after all layers have been applied.
+#[derive(Debug, Clone)]
+pub struct Config {
+    values: BTreeMap<String, Value>,
+    sections: BTreeMap<String, toml::Table>,
+    file: Option<PathBuf>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        let values = SETTINGS
+            .iter()
+            .map(|setting| {
+                let value = Value {
+                    value: setting.default.to_string(),
+                    source: Source::Default,
+                };
+                (setting.key.to_string(), value)
+            })
+            .collect();
+        Config {
+            values,
+            sections: BTreeMap::new(),
+            file: None,
+        }
+    }
+}
+
+impl Config {
+    /// Loads the configuration for a tool, consuming `--config <file>`,
+    /// `--set <key=value>` and the tool's own shorthand flags (`aliases`,
+    /// pairs of flag and setting key) from `args`.
+    ///
+    /// The file is `--config`, else `$MONO_CONFIG`, else the first `mono.toml`
+    /// found in the current directory, its parents, or next to the executable.
+    pub fn load(args: &mut Vec<String>, aliases: &[(&str, &str)]) -> Result<Self, ConfigError> {
+        let mut file = None;
+        let mut overrides = Vec::new();
+        let mut rest = Vec::new();
+        let mut iter = args.drain(..);
+
+        while let Some(arg) = iter.next() {
+            if arg == "--config" {
+                let path = iter.next().ok_or_else(|| ConfigError("--config requires a file".to_string()))?;
+                file = Some(PathBuf::from(path));
+            } else if arg == "--set" {
+                let setting = iter.next().ok_or_else(|| ConfigError("--set requires <key=value>".to_string()))?;
+                let (key, value) = setting
+                    .split_once('=')
+                    .ok_or_else(|| ConfigError(format!("Invalid --set '{}', expected <key=value>", setting)))?;
+                overrides.push((key.trim().to_string(), value.trim().to_string(), "--set".to_string()));
+            } else if let Some(&(flag, key)) = aliases.iter().find(|(flag, _)| *flag == arg) {
+                let value = iter.next().ok_or_else(|| ConfigError(format!("{} requires a value", flag)))?;
+                overrides.push((key.to_string(), value, flag.to_string()));
+            } else {
+                rest.push(arg);
+            }
+        }
+
+        drop(iter);
+        *args = rest;
+
+        let file = file.or_else(|| env::var_os(CONFIG_ENV).map(PathBuf::from)).or_else(find_config_file);
+        let mut config = Config::default();
+        if let Some(path) = &file {
+            config.apply_file(path)?;
+        }
+        config.apply_env(env::vars_os())?;
+        for (key, value, flag) in overrides {
+            config.set(&key, &value, Source::Cli(flag))?;
+        }
+        Ok(config)
+    }
+
+    /// Applies a TOML config file over the current values.
+    pub fn apply_file(&mut self, path: &Path) -> Result<(), ConfigError> {
+        let text = fs::read_to_string(path).map_err(|e| ConfigError(format!("Failed to read {}: {}", path.display(), e)))?;
+        self.apply_toml(&text, path)
+    }
+
+    /// Applies TOML text as if it had been read from `path`.
+    pub fn apply_toml(&mut self, text: &str, path: &Path) -> Result<(), ConfigError> {
+        let mut table: toml::Table = text
+            .parse()
+            .map_err(|e: toml::de::Error| ConfigError(format!("{}: {}", path.display(), e.message())))?;
+
+        for name in STRUCTURED_SECTIONS {
+            match table.remove(*name) {
+                Some(toml::Value::Table(section)) => {
+                    self.sections.insert(name.to_string(), section);
+                }
+                Some(_) => return Err(ConfigError(format!("{}: '{}' must be a table", path.display(), name))),
+                None => {}
+            }
+        }
+
+        let mut flat = Vec::new();
+        flatten("", &table, &mut flat).map_err(|e| ConfigError(format!("{}: {}", path.display(), e)))?;
+        for (key, value) in flat {
+            self.set(&key, &value, Source::File(path.to_path_buf()))
+                .map_err(|e| ConfigError(format!("{}: {}", path.display(), e)))?;
+        }
+        self.file = Some(path.to_path_buf());
+        Ok(())
+    }
+
+    /// Applies `MONO_<SECTION>_<KEY>` variables, e.g. `MONO_DAEMON_SERVER`.
+    /// Free-form tables can't be set from the environment. Other variables
+    /// are skipped without being read, so they may hold anything, but a
+    /// `MONO_` value has to be UTF-8.
+    pub fn apply_env<I, K, V>(&mut self, vars: I) -> Result<(), ConfigError>
+    where
+        I: IntoIterator<Item = (K, V)>,
+        K: Into<OsString>,
+        V: Into<OsString>,
+    {
+        for (var, value) in vars {
+            let Ok(var) = var.into().into_string() else { continue };
+            if !var.starts_with("MONO_") {
+                continue;
+            }
+            let value = value
+                .into()
+                .into_string()
+                .map_err(|_| ConfigError(format!("{} is not valid UTF-8", var)))?;
+            if let Some(setting) = SETTINGS.iter().find(|setting| env_var(setting.key) == var) {
+                self.set(setting.key, &value, Source::Env(var))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Sets `key`, which must be a known setting or belong to a known table.
+    pub fn set(&mut self, key: &str, value: &str, source: Source) -> Result<(), ConfigError> {
+        let known = SETTINGS.iter().any(|setting| setting.key == key)
+            || SETTING_TABLES
+                .iter()
+                .any(|table| key.strip_prefix(table).is_some_and(|rest| rest.starts_with('.') && rest.len() > 1));
+        if !known {
+            return Err(ConfigError(format!("unknown setting '{}'", key)));
+        }
+        self.values.insert(
+            key.to_string(),
+            Value {
+                value: value.to_string(),
+                source,
+            },
+        );
+        Ok(())
+    }
+
+    pub fn get(&self, key: &str) -> &str {
+        self.value(key).map_or("", |value| value.value.as_str())
+    }
+
+    pub fn value(&self, key: &str) -> Option<&Value> {
+        self.values.get(key)
+    }
+
+    /// Parses a setting, naming it and its source in the error.
+    pub fn parse<T: FromStr>(&self, key: &str) -> Result<T, ConfigError> {
+        let value = self
+            .value(key)
+            .ok_or_else(|| ConfigError(format!("setting '{}' is not set", key)))?;
+        parse_value(key, value)
+    }
+
+    /// A path setting. Relative paths from the config file are relative to
+    /// the file; anything else is relative to the current directory.
+    pub fn path(&self, key: &str) -> PathBuf {
+        let path = PathBuf::from(self.get(key));
+        match self.value(key).map(|value| &value.source) {
+            Some(Source::File(file)) if path.is_relative() => {
+                file.parent().unwrap_or(Path::new(".")).join(path)
+            }
+            _ => path,
+        }
+    }
+
+    /// Entries of a free-form table, e.g. `dump.timeouts`, keyed without the table prefix.
+    pub fn table(&self, table: &str) -> impl Iterator<Item = (&str, &Value)> {
+        let prefix = format!("{}.", table);
+        self.values
+            .iter()
+            .filter_map(move |(key, value)| Some((key.strip_prefix(&prefix)?, value)))
+    }
+
+    pub fn entries(&self) -> impl Iterator<Item = (&str, &Value)> {
+        self.values.iter().map(|(key, value)| (key.as_str(), value))
+    }
+
+    /// A structured section from the config file, e.g. `profiles`.
+    pub fn section(&self, name: &str) -> Option<&toml::Table> {
+        self.sections.get(name)
+    }
+
+    /// The config file that was applied, if any.
+    pub fn file(&self) -> Option<&Path> {
+        self.file.as_deref()
+    }
+
+    /// Renders one section of the effective configuration as TOML, e.g. to
+    /// hand the daemon its settings.
+    pub fn section_toml(&self, section: &str) -> String {
+        let prefix = format!("{}.", section);
+        let mut toml = format!("[{}]\n", section);
+        for (key, value) in &self.values {
+            if let Some(name) = key.strip_prefix(&prefix).filter(|name| !name.contains('.')) {
+                toml.push_str(&format!("{} = {}\n", name, toml::Value::String(value.value.clone())));
+            }
+        }
+        toml
+    }
+}
+
+pub fn parse_value<T: FromStr>(key: &str, value: &Value) -> Result<T, ConfigError> {
+    value.value.parse().map_err(|_| {
+        ConfigError(format!("Invalid value '{}' for {} (from {})", value.value, key, value.source))
+    })
+}
+
+/// `daemon.server` -> `MONO_DAEMON_SERVER`.
+pub fn env_var(key: &str) -> String {
+    format!("MONO_{}", key.replace('.', "_").to_uppercase())
+}
+
+fn flatten(prefix: &str, table: &toml::Table, out: &mut Vec<(String, String)>) -> Result<(), String> {
+    for (name, value) in table {
+        let key = if prefix.is_empty() { name.clone() } else { format!("{}.{}", prefix, name) };
+        match value {
+            toml::Value::Table(table) => flatten(&key, table, out)?,
+            toml::Value::String(s) => out.push((key, s.clone())),
+            toml::Value::Integer(i) => out.push((key, i.to_string())),
+            toml::Value::Float(f) => out.push((key, f.to_string())),
+            toml::Value::Boolean(b) => out.push((key, b.to_string())),
+            _ => return Err(format!("unsupported value for '{}'", key)),
+        }
+    }
+    Ok(())
+}
+
+fn find_config_file() -> Option<PathBuf> {
+    let cwd = env::current_dir().ok();
+    let exe_dir = env::current_exe().ok().and_then(|exe| exe.parent().map(Path::to_path_buf));
+    cwd.iter()
+        .flat_map(|dir| dir.ancestors())
+        .chain(exe_dir.as_deref())
+        .map(|dir| dir.join(CONFIG_FILE))
+        .find(|path| path.is_file())
+}
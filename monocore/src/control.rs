@@ -0,0 +1,74 @@
+//! monodeamon's control socket: an abstract Unix socket on the device that
+//! monocli reaches through `adb forward tcp:0 localabstract:monodeamon`.
+//!
+//! A client sends one request line and reads the reply until the daemon
+//! closes the connection. A reply is `ok` or `error`, a newline, then the
+//! body: the daemon's status for `status`, log lines for `recent`, or a short
+//! note for the rest.
+
+use std::fmt;
+
+/// Name of the abstract socket, `@monodeamon` in `/proc/net/unix`.
+pub const SOCKET_NAME: &str = "monodeamon";
+
+/// Most lines a `recent` request returns.
+pub const RECENT_LINES: usize = 1000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Request {
+    /// The daemon's [`DaemonStatus`](crate::status::DaemonStatus).
+    Status,
+    /// Sends what it can of the spool, then exits.
+    Stop,
+    /// Re-reads the config, as when starting.
+    Reload,
+    /// Waits for the spool to be sent.
+    Flush,
+    /// The last lines read from logcat, oldest first.
+    Recent(usize),
+}
+
+impl Request {
+    pub fn parse(line: &str) -> Option<Self> {
+        let mut words = line.split_whitespace();
+        let request = match words.next()? {
+            "status" => Request::Status,
+            "stop" => Request::Stop,
+            "reload" => Request::Reload,
+            "flush" => Request::Flush,
+            "recent" => Request::Recent(words.next()?.parse().ok()?),
+            _ => return None,
+        };
+        words.next().is_none().then_some(request)
+    }
+}
+
+impl fmt::Display for Request {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Request::Status => f.write_str("status"),
+            Request::Stop => f.write_str("stop"),
+            Request::Reload => f.write_str("reload"),
+            Request::Flush => f.write_str("flush"),
+            Request::Recent(count) => write!(f, "recent {}", count),
+        }
+    }
+}
+
+/// The reply to send for the outcome of a request.
+pub fn reply(result: Result<String, String>) -> String {
+    match result {
+        Ok(body) => format!("ok\n{}", body),
+        Err(message) => format!("error\n{}", message),
+    }
+}
+
+/// Reads a reply back into the outcome of the request.
+pub fn parse_reply(text: &str) -> Result<String, String> {
+    match text.split_once('\n').unwrap_or((text, "")) {
+        ("ok", body) => Ok(body.to_string()),
+        ("error", message) => Err(message.trim().to_string()),
+        _ if text.is_empty() => Err("no reply; is monodeamon running?".to_string()),
+        _ => Err(format!("unexpected reply: {}", text.lines().next().unwrap_or_default())),
+    }
+}
@@ -0,0 +1,7 @@
+//! Code shared by monocli, monodeamon and monoserve.
+
+pub mod config;
+pub mod control;
+pub mod logcat;
+pub mod status;
+pub mod version;
@@ -0,0 +1,467 @@
+//! Logcat output as structured records, shared by monocli, monodeamon and
+//! monoserve so logs can be filtered and indexed instead of passed around as
+//! opaque text.
+//!
+//! Understands the `threadtime` (the default), `time`, `brief`, `tag` and
+//! `long` formats, with the `year`, `epoch`, `monotonic`, `usec`/`nsec` and
+//! `uid` modifiers. Lines without a header of their own, such as the rest of
+//! a stack trace, are continuations of the record before them.
+
+use std::fmt;
+use std::str::FromStr;
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+/// Log priority, ordered from least to most severe.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum Level {
+    /// Text that wasn't preceded by any header.
+    #[serde(rename = "?")]
+    Unknown,
+    #[serde(rename = "V")]
+    Verbose,
+    #[serde(rename = "D")]
+    Debug,
+    #[serde(rename = "I")]
+    Info,
+    #[serde(rename = "W")]
+    Warn,
+    #[serde(rename = "E")]
+    Error,
+    #[serde(rename = "F")]
+    Fatal,
+    #[serde(rename = "S")]
+    Silent,
+}
+
+impl Level {
+    pub fn from_char(c: char) -> Option<Self> {
+        match c {
+            'V' => Some(Level::Verbose),
+            'D' => Some(Level::Debug),
+            'I' => Some(Level::Info),
+            'W' => Some(Level::Warn),
+            'E' => Some(Level::Error),
+            'F' | 'A' => Some(Level::Fatal),
+            'S' => Some(Level::Silent),
+            _ => None,
+        }
+    }
+
+    pub fn as_char(&self) -> char {
+        match self {
+            Level::Unknown => '?',
+            Level::Verbose => 'V',
+            Level::Debug => 'D',
+            Level::Info => 'I',
+            Level::Warn => 'W',
+            Level::Error => 'E',
+            Level::Fatal => 'F',
+            Level::Silent => 'S',
+        }
+    }
+}
+
+impl fmt::Display for Level {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.as_char())
+    }
+}
+
+/// Accepts the logcat letter (`W`) or the name (`warn`, `warning`), in any case.
+impl FromStr for Level {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let level = match s.to_ascii_lowercase().as_str() {
+            "v" | "verbose" => Level::Verbose,
+            "d" | "debug" => Level::Debug,
+            "i" | "info" => Level::Info,
+            "w" | "warn" | "warning" => Level::Warn,
+            "e" | "error" => Level::Error,
+            "f" | "a" | "fatal" | "assert" => Level::Fatal,
+            "s" | "silent" => Level::Silent,
+            _ => return Err(format!("unknown log level '{}', expected one of V, D, I, W, E, F", s)),
+        };
+        Ok(level)
+    }
+}
+
+/// When a record was logged, as printed by logcat.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum LogTime {
+    /// Device-local wall-clock time; the year is only there with `-v year`.
+    Calendar {
+        year: Option<u32>,
+        month: u32,
+        day: u32,
+        hour: u32,
+        minute: u32,
+        second: u32,
+        nanos: u32,
+    },
+    /// Seconds since the epoch (`-v epoch`) or since boot (`-v monotonic`).
+    Seconds { seconds: u64, nanos: u32 },
+}
+
+impl fmt::Display for LogTime {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let nanos = match self {
+            LogTime::Calendar {
+                year,
+                month,
+                day,
+                hour,
+                minute,
+                second,
+                nanos,
+            } => {
+                if let Some(year) = year {
+                    write!(f, "{:04}-", year)?;
+                }
+                write!(f, "{:02}-{:02} {:02}:{:02}:{:02}", month, day, hour, minute, second)?;
+                *nanos
+            }
+            LogTime::Seconds { seconds, nanos } => {
+                write!(f, "{}", seconds)?;
+                *nanos
+            }
+        };
+        // Milliseconds, unless the source had finer resolution.
+        if nanos % 1_000_000 == 0 {
+            write!(f, ".{:03}", nanos / 1_000_000)
+        } else if nanos % 1_000 == 0 {
+            write!(f, ".{:06}", nanos / 1_000)
+        } else {
+            write!(f, ".{:09}", nanos)
+        }
+    }
+}
+
+impl FromStr for LogTime {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match parse_time_prefix(s) {
+            Some((time, rest)) if rest.trim().is_empty() => Ok(time),
+            _ => Err(format!("invalid logcat timestamp '{}'", s)),
+        }
+    }
+}
+
+impl Serialize for LogTime {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for LogTime {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let text = String::deserialize(deserializer)?;
+        text.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+/// One log entry. Multi-line messages keep their lines joined with `\n`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LogRecord {
+    /// Missing in the `brief` and `tag` formats.
+    pub time: Option<LogTime>,
+    /// Only with `-v uid`: a uid number or name such as `u0_a123`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub uid: Option<String>,
+    pub pid: Option<u32>,
+    /// Only in the `threadtime` and `long` formats.
+    pub tid: Option<u32>,
+    pub level: Level,
+    pub tag: String,
+    pub message: String,
+}
+
+impl LogRecord {
+    /// A headerless line with nothing before it to continue.
+    fn orphan(line: &str) -> Self {
+        LogRecord {
+            time: None,
+            uid: None,
+            pid: None,
+            tid: None,
+            level: Level::Unknown,
+            tag: String::new(),
+            message: line.to_string(),
+        }
+    }
+}
+
+/// Renders the record in `threadtime` layout, one line per message line.
+impl fmt::Display for LogRecord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut header = String::new();
+        if let Some(time) = &self.time {
+            header.push_str(&format!("{} ", time));
+        }
+        if let Some(uid) = &self.uid {
+            header.push_str(&format!("{:>5} ", uid));
+        }
+        if let Some(pid) = self.pid {
+            header.push_str(&format!("{:>5} ", pid));
+        }
+        if let Some(tid) = self.tid {
+            header.push_str(&format!("{:>5} ", tid));
+        }
+        header.push_str(&format!("{} {}: ", self.level, self.tag));
+
+        for (index, line) in self.message.split('\n').enumerate() {
+            if index > 0 {
+                writeln!(f)?;
+            }
+            write!(f, "{}{}", header, line)?;
+        }
+        Ok(())
+    }
+}
+
+/// Parses one single-line-format log line, or `None` if it has no header.
+pub fn parse_line(line: &str) -> Option<LogRecord> {
+    let line = line.trim_end_matches(['\r', '\n']);
+    if let Some(record) = parse_brief(line, None) {
+        return Some(record);
+    }
+    let (time, rest) = parse_time_prefix(line)?;
+    let rest = rest.trim_start();
+    parse_brief(rest, Some(time)).or_else(|| parse_threadtime(rest, time))
+}
+
+/// Turns a stream of logcat lines into records.
+///
+/// A record is only known to be complete once the next header (or the end
+/// of input) arrives, so [`push`](Self::push) returns the *previous* record
+/// and [`finish`](Self::finish) flushes the last one.
+#[derive(Debug, Default)]
+pub struct LogParser {
+    pending: Option<LogRecord>,
+    /// Inside a `long` record, whose message runs until a blank line.
+    long_lines: Option<usize>,
+}
+
+impl LogParser {
+    pub fn new() -> Self {
+        LogParser::default()
+    }
+
+    pub fn push(&mut self, line: &str) -> Option<LogRecord> {
+        let line = line.trim_end_matches(['\r', '\n']);
+
+        // `--------- beginning of main` and friends separate buffers.
+        if line.starts_with("--------- ") {
+            self.long_lines = None;
+            return self.pending.take();
+        }
+
+        if let Some(record) = parse_long_header(line) {
+            self.long_lines = Some(0);
+            return self.pending.replace(record);
+        }
+
+        if let Some(lines) = self.long_lines.as_mut() {
+            if line.is_empty() {
+                self.long_lines = None;
+                return self.pending.take();
+            }
+            if let Some(record) = self.pending.as_mut() {
+                if *lines > 0 {
+                    record.message.push('\n');
+                }
+                record.message.push_str(line);
+            }
+            *lines += 1;
+            return None;
+        }
+
+        if let Some(record) = parse_line(line) {
+            return self.pending.replace(record);
+        }
+        if line.trim().is_empty() {
+            return None;
+        }
+        match self.pending.as_mut() {
+            Some(record) => {
+                record.message.push('\n');
+                record.message.push_str(line);
+                None
+            }
+            None => {
+                self.pending = Some(LogRecord::orphan(line));
+                None
+            }
+        }
+    }
+
+    pub fn finish(&mut self) -> Option<LogRecord> {
+        self.long_lines = None;
+        self.pending.take()
+    }
+}
+
+/// Parses a whole log, e.g. the output of `logcat -d`.
+pub fn parse_log(text: &str) -> Vec<LogRecord> {
+    let mut parser = LogParser::new();
+    let mut records: Vec<LogRecord> = text.lines().filter_map(|line| parser.push(line)).collect();
+    records.extend(parser.finish());
+    records
+}
+
+/// `I/Tag( 1234): message` (brief), `I/Tag: message` (tag).
+fn parse_brief(line: &str, time: Option<LogTime>) -> Option<LogRecord> {
+    let level = Level::from_char(line.chars().next()?)?;
+    let body = line.get(1..)?.strip_prefix('/')?;
+
+    let (head, message) = if let Some(index) = body.find("): ") {
+        (&body[..=index], &body[index + 3..])
+    } else if let Some(head) = body.strip_suffix(':').filter(|head| head.ends_with(')')) {
+        (head, "")
+    } else {
+        body.split_once(": ").or_else(|| Some((body.strip_suffix(':')?, "")))?
+    };
+
+    let (tag, pid) = match head.strip_suffix(')').and_then(|head| head.rsplit_once('(')) {
+        Some((tag, pid)) => (tag, Some(pid.trim().parse().ok()?)),
+        None => (head, None),
+    };
+
+    Some(LogRecord {
+        time,
+        uid: None,
+        pid,
+        tid: None,
+        level,
+        tag: tag.trim_end().to_string(),
+        message: message.to_string(),
+    })
+}
+
+/// `[uid] pid tid L Tag: message`, after the timestamp.
+fn parse_threadtime(rest: &str, time: LogTime) -> Option<LogRecord> {
+    let mut ids = Vec::new();
+    let mut rest = rest;
+    let level = loop {
+        let (token, after) = next_token(rest)?;
+        rest = after;
+        if ids.len() >= 2 {
+            let mut chars = token.chars();
+            if let (Some(level), None) = (chars.next().and_then(Level::from_char), chars.next()) {
+                break level;
+            }
+        }
+        ids.push(token);
+        if ids.len() > 3 {
+            return None;
+        }
+    };
+
+    let uid = (ids.len() == 3).then(|| ids[0].to_string());
+    let pid = ids[ids.len() - 2].parse().ok()?;
+    let tid = ids[ids.len() - 1].parse().ok()?;
+
+    let rest = rest.strip_prefix(' ').unwrap_or(rest);
+    let (tag, message) = rest.split_once(": ").or_else(|| Some((rest.strip_suffix(':')?, "")))?;
+
+    Some(LogRecord {
+        time: Some(time),
+        uid,
+        pid: Some(pid),
+        tid: Some(tid),
+        level,
+        tag: tag.trim().to_string(),
+        message: message.to_string(),
+    })
+}
+
+/// `[ 08-12 10:15:00.123  1234: 5678 I/Tag ]`, optionally with a uid before the pid.
+fn parse_long_header(line: &str) -> Option<LogRecord> {
+    let inner = line.strip_prefix('[')?.strip_suffix(']')?.trim();
+    let (time, rest) = parse_time_prefix(inner)?;
+    let (ids, rest) = rest.split_once(':')?;
+    let mut ids: Vec<&str> = ids.split_whitespace().collect();
+    let pid = ids.pop()?.parse().ok()?;
+    let uid = match ids.as_slice() {
+        [] => None,
+        [uid] => Some(uid.to_string()),
+        _ => return None,
+    };
+    let (tid, rest) = next_token(rest)?;
+    let rest = rest.trim();
+    let level = Level::from_char(rest.chars().next()?)?;
+    let tag = rest.get(1..)?.strip_prefix('/')?;
+
+    Some(LogRecord {
+        time: Some(time),
+        uid,
+        pid: Some(pid),
+        tid: Some(tid.parse().ok()?),
+        level,
+        tag: tag.trim().to_string(),
+        message: String::new(),
+    })
+}
+
+/// `MM-DD HH:MM:SS.fff`, `YYYY-MM-DD HH:MM:SS.fff` or `SECONDS.fff`, and what follows.
+fn parse_time_prefix(line: &str) -> Option<(LogTime, &str)> {
+    let (first, rest) = next_token(line)?;
+
+    if first.contains('-') {
+        let date: Vec<&str> = first.split('-').collect();
+        let (year, month, day) = match date[..] {
+            [month, day] => (None, month, day),
+            [year, month, day] if year.len() == 4 => (Some(number(year)?), month, day),
+            _ => return None,
+        };
+        let (clock, rest) = next_token(rest)?;
+        let (clock, fraction) = clock.split_once('.')?;
+        let [hour, minute, second] = clock.split(':').collect::<Vec<_>>()[..] else {
+            return None;
+        };
+        let time = LogTime::Calendar {
+            year,
+            month: number(month)?,
+            day: number(day)?,
+            hour: number(hour)?,
+            minute: number(minute)?,
+            second: number(second)?,
+            nanos: fraction_nanos(fraction)?,
+        };
+        return Some((time, rest));
+    }
+
+    let (seconds, fraction) = first.split_once('.')?;
+    let time = LogTime::Seconds {
+        seconds: seconds.parse().ok().filter(|_| seconds.bytes().all(|b| b.is_ascii_digit()))?,
+        nanos: fraction_nanos(fraction)?,
+    };
+    Some((time, rest))
+}
+
+fn number(digits: &str) -> Option<u32> {
+    if digits.is_empty() || !digits.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    digits.parse().ok()
+}
+
+/// `123` (ms), `123456` (us) or `123456789` (ns) as nanoseconds.
+fn fraction_nanos(fraction: &str) -> Option<u32> {
+    if fraction.is_empty() || fraction.len() > 9 {
+        return None;
+    }
+    Some(number(fraction)? * 10u32.pow(9 - fraction.len() as u32))
+}
+
+/// The next whitespace-separated token and everything after it.
+fn next_token(text: &str) -> Option<(&str, &str)> {
+    let text = text.trim_start();
+    if text.is_empty() {
+        return None;
+    }
+    let end = text.find(char::is_whitespace).unwrap_or(text.len());
+    Some((&text[..end], &text[end..]))
+}
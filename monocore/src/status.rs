@@ -0,0 +1,89 @@
+//! The status file monodeamon keeps next to its binary, which `monocli check`
+//! reads to report on a daemon without talking to it.
+//!
+//! One `key=value` per line, rewritten whole every [`STATUS_INTERVAL`]:
+//!
+//! ```text
+//! pid=4242
+//! build=0.2.0 (1a2b3c4d5e6f)
+//! server=10.0.0.5:12345
+//! started=1723456789
+//! updated=1723460389
+//! connected=1
+//! last_send=1723460388
+//! spool=0
+//! dropped=0
+//! ```
+//!
+//! Times are Unix seconds on the device's clock.
+
+use std::fmt;
+use std::time::Duration;
+
+pub const STATUS_FILE: &str = "monodeamon.status";
+
+/// How often the daemon rewrites its status file. A file much older than
+/// this belongs to a daemon that is gone or stuck.
+pub const STATUS_INTERVAL: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DaemonStatus {
+    pub pid: u32,
+    /// The daemon's [`BuildInfo`](crate::version::BuildInfo), as displayed.
+    pub build: String,
+    /// Where the daemon sends logs, from its own config.
+    pub server: String,
+    pub started: u64,
+    pub updated: u64,
+    /// Whether the daemon holds an open connection to `server`.
+    pub connected: bool,
+    /// When lines last reached the server; `None` if none have yet.
+    pub last_send: Option<u64>,
+    /// Lines read from logcat and waiting to be sent.
+    pub spool: usize,
+    /// Lines dropped since the start because the spool was full.
+    pub dropped: u64,
+}
+
+impl DaemonStatus {
+    /// Reads a status file; `None` unless it has at least a pid and the
+    /// times, as a half-written or foreign file wouldn't.
+    pub fn parse(text: &str) -> Option<Self> {
+        let mut status = DaemonStatus::default();
+        let (mut pid, mut started, mut updated) = (None, None, None);
+        for (key, value) in text.lines().filter_map(|line| line.trim().split_once('=')) {
+            match key {
+                "pid" => pid = value.parse().ok(),
+                "build" => status.build = value.to_string(),
+                "server" => status.server = value.to_string(),
+                "started" => started = value.parse().ok(),
+                "updated" => updated = value.parse().ok(),
+                "connected" => status.connected = value == "1",
+                "last_send" => status.last_send = value.parse().ok(),
+                "spool" => status.spool = value.parse().unwrap_or_default(),
+                "dropped" => status.dropped = value.parse().unwrap_or_default(),
+                _ => {}
+            }
+        }
+        status.pid = pid?;
+        status.started = started?;
+        status.updated = updated?;
+        Some(status)
+    }
+}
+
+impl fmt::Display for DaemonStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "pid={}", self.pid)?;
+        writeln!(f, "build={}", self.build)?;
+        writeln!(f, "server={}", self.server)?;
+        writeln!(f, "started={}", self.started)?;
+        writeln!(f, "updated={}", self.updated)?;
+        writeln!(f, "connected={}", u8::from(self.connected))?;
+        if let Some(last_send) = self.last_send {
+            writeln!(f, "last_send={}", last_send)?;
+        }
+        writeln!(f, "spool={}", self.spool)?;
+        writeln!(f, "dropped={}", self.dropped)
+    }
+}
@@ -0,0 +1,85 @@
+//! Which monodeamon build is which. The daemon embeds a [`BuildInfo`] line
+//! in its binary, so monocli can read the version of a local build or of the
+//! one installed on a device without running either.
+
+use std::fmt;
+use std::str::FromStr;
+
+/// Starts the embedded line, which goes on `version=<semver> git=<hash>;`.
+pub const MARKER: &str = "MONODEAMON_BUILD ";
+
+/// A daemon build: its crate version and the commit it was built from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BuildInfo {
+    pub version: String,
+    /// Short commit hash, with `-dirty` when built from uncommitted changes,
+    /// or `unknown` when built outside a git checkout.
+    pub git: String,
+}
+
+impl BuildInfo {
+    /// The line the daemon embeds, as written by its build.
+    pub fn line(&self) -> String {
+        format!("{}version={} git={};", MARKER, self.version, self.git)
+    }
+
+    /// Reads the first embedded line in `text`.
+    pub fn parse(text: &str) -> Option<Self> {
+        text.match_indices(MARKER).find_map(|(start, _)| parse_fields(&text[start + MARKER.len()..]))
+    }
+
+    /// Finds the embedded line in a binary.
+    pub fn find_in(bytes: &[u8]) -> Option<Self> {
+        let marker = MARKER.as_bytes();
+        bytes
+            .windows(marker.len())
+            .enumerate()
+            .filter(|(_, window)| *window == marker)
+            .find_map(|(start, _)| {
+                let rest = &bytes[start + marker.len()..];
+                let end = rest.iter().take(200).position(|&byte| byte == b';')?;
+                parse_fields(std::str::from_utf8(&rest[..=end]).ok()?)
+            })
+    }
+
+    /// `major.minor.patch` as numbers, for telling an upgrade from a downgrade.
+    pub fn semver(&self) -> Option<(u64, u64, u64)> {
+        let core = self.version.split(['-', '+']).next()?;
+        let mut parts = core.split('.').map(|part| part.parse::<u64>().ok());
+        let version = (parts.next()??, parts.next()??, parts.next()??);
+        parts.next().is_none().then_some(version)
+    }
+}
+
+/// Parses `version=<semver> git=<hash>;`.
+fn parse_fields(text: &str) -> Option<BuildInfo> {
+    let fields = &text[..text.find(';')?];
+    let (version, git) = fields.split_once(' ')?;
+    let version = version.strip_prefix("version=")?;
+    let git = git.strip_prefix("git=")?;
+    let valid = |value: &str| !value.is_empty() && value.chars().all(|c| c.is_ascii_graphic());
+    (valid(version) && valid(git)).then(|| BuildInfo {
+        version: version.to_string(),
+        git: git.to_string(),
+    })
+}
+
+impl fmt::Display for BuildInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} ({})", self.version, self.git)
+    }
+}
+
+/// Reads back the `<version> (<git>)` form that `Display` writes, as the
+/// daemon reports itself in its status.
+impl FromStr for BuildInfo {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        s.trim()
+            .strip_suffix(')')
+            .and_then(|rest| rest.split_once(" ("))
+            .and_then(|(version, git)| parse_fields(&format!("version={} git={};", version, git)))
+            .ok_or_else(|| format!("malformed build '{}', expected <version> (<git>)", s))
+    }
+}
@@ -0,0 +1,139 @@
+//! Layering and provenance of `mono.toml` settings.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use monocore::config::{env_var, Config, Source};
+
+const FILE: &str = r#"
+[daemon]
+server = "10.0.0.5:12345"
+project = "monodeamon"
+
+[dump]
+jobs = 8
+
+[dump.timeouts]
+"ls -lR /sdcard" = 300
+"#;
+
+fn with_file() -> Config {
+    let mut config = Config::default();
+    config.apply_toml(FILE, Path::new("/etc/mono/mono.toml")).unwrap();
+    config
+}
+
+#[test]
+fn defaults_apply_without_a_file() {
+    let config = Config::default();
+    assert_eq!(config.get("daemon.server"), "192.168.1.100:12345");
+    assert_eq!(config.get("server.bind"), "0.0.0.0:12345");
+    assert_eq!(config.parse::<usize>("dump.jobs").unwrap(), 4);
+    assert_eq!(config.value("adb.path").unwrap().source, Source::Default);
+    assert!(config.file().is_none());
+}
+
+#[test]
+fn layers_override_in_order_and_remember_their_source() {
+    let mut config = with_file();
+    let file = PathBuf::from("/etc/mono/mono.toml");
+    assert_eq!(config.get("daemon.server"), "10.0.0.5:12345");
+    assert_eq!(config.value("daemon.server").unwrap().source, Source::File(file.clone()));
+    assert_eq!(config.parse::<usize>("dump.jobs").unwrap(), 8);
+    assert_eq!(config.value("server.bind").unwrap().source, Source::Default);
+
+    config
+        .apply_env([
+            ("MONO_DAEMON_SERVER".to_string(), "10.0.0.9:1".to_string()),
+            ("HOME".to_string(), "/root".to_string()),
+        ])
+        .unwrap();
+    assert_eq!(config.get("daemon.server"), "10.0.0.9:1");
+    assert_eq!(config.value("daemon.server").unwrap().source, Source::Env("MONO_DAEMON_SERVER".to_string()));
+
+    config.set("daemon.server", "127.0.0.1:9", Source::Cli("--set".to_string())).unwrap();
+    assert_eq!(config.get("daemon.server"), "127.0.0.1:9");
+    assert_eq!(config.value("daemon.server").unwrap().source.to_string(), "flag --set");
+    assert_eq!(config.value("dump.jobs").unwrap().source.to_string(), "/etc/mono/mono.toml");
+}
+
+#[test]
+fn tables_and_paths() {
+    let config = with_file();
+    let timeouts: Vec<_> = config.table("dump.timeouts").map(|(cmd, v)| (cmd, v.value.as_str())).collect();
+    assert_eq!(timeouts, [("ls -lR /sdcard", "300")]);
+
+    // Relative paths from the file resolve against the file's directory.
+    assert_eq!(config.path("daemon.project"), Path::new("/etc/mono/monodeamon"));
+    assert_eq!(config.path("dump.dir"), Path::new("./dump"));
+
+    assert_eq!(
+        config.section_toml("daemon"),
+        "[daemon]\nmin_level = \"V\"\nproject = \"monodeamon\"\nserver = \"10.0.0.5:12345\"\nspool = \"10000\"\n"
+    );
+}
+
+#[test]
+fn mistakes_are_reported() {
+    let mut config = Config::default();
+    let error = config.apply_toml("[daemon]\nsever = \"x\"\n", Path::new("mono.toml")).unwrap_err();
+    assert_eq!(error.to_string(), "mono.toml: unknown setting 'daemon.sever'");
+
+    assert!(config.apply_toml("[daemon\n", Path::new("mono.toml")).is_err());
+
+    config.set("dump.jobs", "lots", Source::Env(env_var("dump.jobs"))).unwrap();
+    let error = config.parse::<usize>("dump.jobs").unwrap_err();
+    assert_eq!(error.to_string(), "Invalid value 'lots' for dump.jobs (from env MONO_DUMP_JOBS)");
+}
+
+#[test]
+fn load_consumes_its_flags() {
+    let path = std::env::temp_dir().join(format!("mono-config-{}.toml", std::process::id()));
+    fs::write(&path, FILE).unwrap();
+
+    let config_arg = path.to_str().unwrap();
+    let mut args: Vec<String> = ["dump", "--config", config_arg, "-j", "2", "--set", "server.bind=0.0.0.0:1", "--all"]
+        .iter()
+        .map(|s| s.to_string())
+        .collect();
+    let config = Config::load(&mut args, &[("-j", "dump.jobs")]).unwrap();
+    assert_eq!(args, ["dump", "--all"]);
+    assert_eq!(config.file(), Some(path.as_path()));
+    assert_eq!(config.get("dump.jobs"), "2");
+    assert_eq!(config.value("dump.jobs").unwrap().source, Source::Cli("-j".to_string()));
+    assert_eq!(config.get("server.bind"), "0.0.0.0:1");
+    assert_eq!(config.get("daemon.server"), "10.0.0.5:12345");
+
+    fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn structured_sections_are_kept_as_toml() {
+    let mut config = Config::default();
+    let text = r#"
+[dump]
+profile = "mine"
+
+[profiles.mine]
+commands = [{ section = "device", command = "getprop" }]
+"#;
+    config.apply_toml(text, Path::new("mono.toml")).unwrap();
+
+    assert_eq!(config.get("dump.profile"), "mine");
+    let profiles = config.section("profiles").unwrap();
+    assert!(profiles["mine"]["commands"].is_array());
+    assert!(config.value("profiles.mine.commands").is_none());
+}
+
+#[cfg(unix)]
+#[test]
+fn env_values_must_be_utf8_only_for_mono() {
+    use std::ffi::OsString;
+    use std::os::unix::ffi::OsStringExt;
+
+    let invalid = || OsString::from_vec(b"\xff\xfe".to_vec());
+    let mut config = Config::default();
+    config.apply_env([(OsString::from("LESSOPEN"), invalid())]).unwrap();
+    let error = config.apply_env([(OsString::from("MONO_DUMP_JOBS"), invalid())]).unwrap_err();
+    assert_eq!(error.to_string(), "MONO_DUMP_JOBS is not valid UTF-8");
+}
@@ -0,0 +1,23 @@
+//! Requests and replies on the daemon's control socket.
+
+use monocore::control::{parse_reply, reply, Request};
+
+#[test]
+fn requests_round_trip() {
+    for request in [Request::Status, Request::Stop, Request::Reload, Request::Flush, Request::Recent(50)] {
+        assert_eq!(Request::parse(&request.to_string()), Some(request));
+    }
+    assert_eq!(Request::parse("  recent 20\n"), Some(Request::Recent(20)));
+    for line in ["", "recent", "recent many", "status now", "restart"] {
+        assert_eq!(Request::parse(line), None, "{:?}", line);
+    }
+}
+
+#[test]
+fn replies_carry_the_outcome() {
+    assert_eq!(parse_reply(&reply(Ok("pid=4242\nspool=0\n".to_string()))), Ok("pid=4242\nspool=0\n".to_string()));
+    assert_eq!(parse_reply(&reply(Ok(String::new()))), Ok(String::new()));
+    assert_eq!(parse_reply(&reply(Err("bad config\n".to_string()))), Err("bad config".to_string()));
+    assert_eq!(parse_reply(""), Err("no reply; is monodeamon running?".to_string()));
+    assert_eq!(parse_reply("HTTP/1.1 400\r\n"), Err("unexpected reply: HTTP/1.1 400".to_string()));
+}
@@ -0,0 +1,134 @@
+//! Logcat formats as printed by `logcat -v <format>` on real devices.
+
+use monocore::logcat::{parse_line, parse_log, Level, LogParser, LogTime};
+
+#[test]
+fn threadtime() {
+    let record = parse_line("08-12 10:15:00.123  1234  5678 I ActivityManager: Start proc 4321:com.example/u0a123").unwrap();
+    assert_eq!(record.time.unwrap().to_string(), "08-12 10:15:00.123");
+    assert_eq!(record.pid, Some(1234));
+    assert_eq!(record.tid, Some(5678));
+    assert_eq!(record.level, Level::Info);
+    assert_eq!(record.tag, "ActivityManager");
+    assert_eq!(record.message, "Start proc 4321:com.example/u0a123");
+
+    // Padded tags, empty messages, pid 1.
+    let record = parse_line("08-12 10:15:00.124     1     1 W chatty  : ").unwrap();
+    assert_eq!(record.tag, "chatty");
+    assert_eq!(record.message, "");
+    assert_eq!(record.pid, Some(1));
+}
+
+#[test]
+fn year_epoch_usec_and_uid_modifiers() {
+    let record = parse_line("2024-08-12 10:15:00.123456  1234  5678 E AndroidRuntime: FATAL EXCEPTION: main").unwrap();
+    assert_eq!(
+        record.time,
+        Some(LogTime::Calendar {
+            year: Some(2024),
+            month: 8,
+            day: 12,
+            hour: 10,
+            minute: 15,
+            second: 0,
+            nanos: 123_456_000,
+        })
+    );
+    assert_eq!(record.time.unwrap().to_string(), "2024-08-12 10:15:00.123456");
+    assert_eq!(record.message, "FATAL EXCEPTION: main");
+
+    let record = parse_line("1723457700.123  1234  5678 D wpa_supplicant: wlan0: CTRL-EVENT-SCAN-STARTED").unwrap();
+    assert_eq!(record.time, Some(LogTime::Seconds { seconds: 1_723_457_700, nanos: 123_000_000 }));
+    assert_eq!(record.tag, "wpa_supplicant");
+    assert_eq!(record.message, "wlan0: CTRL-EVENT-SCAN-STARTED");
+
+    let record = parse_line("08-12 10:15:00.123 u0_a123  4321  4330 V MyApp   : tick").unwrap();
+    assert_eq!(record.uid.as_deref(), Some("u0_a123"));
+    assert_eq!(record.pid, Some(4321));
+    assert_eq!(record.level, Level::Verbose);
+}
+
+#[test]
+fn brief_time_and_tag() {
+    let record = parse_line("W/PackageManager(  912): Unknown permission android.permission.FOO").unwrap();
+    assert_eq!(record.time, None);
+    assert_eq!(record.pid, Some(912));
+    assert_eq!(record.tid, None);
+    assert_eq!(record.tag, "PackageManager");
+    assert_eq!(record.level, Level::Warn);
+
+    let record = parse_line("08-12 10:15:00.123 E/audio_hw_primary(  611): out_write: error -32").unwrap();
+    assert_eq!(record.time.unwrap().to_string(), "08-12 10:15:00.123");
+    assert_eq!(record.tag, "audio_hw_primary");
+    assert_eq!(record.message, "out_write: error -32");
+
+    let record = parse_line("I/Zygote: Process 4321 exited").unwrap();
+    assert_eq!(record.pid, None);
+    assert_eq!(record.tag, "Zygote");
+
+    assert_eq!(parse_line("\tat com.example.Main.run(Main.java:42)"), None);
+}
+
+#[test]
+fn long_format_spans_lines() {
+    let text = "\
+--------- beginning of main
+[ 08-12 10:15:00.123  1234: 5678 E/AndroidRuntime ]
+FATAL EXCEPTION: main
+java.lang.IllegalStateException: boom
+
+[ 08-12 10:15:00.200  1234: 5678 I/Process ]
+Sending signal. PID: 1234 SIG: 9
+
+";
+    let records = parse_log(text);
+    assert_eq!(records.len(), 2);
+    assert_eq!(records[0].tag, "AndroidRuntime");
+    assert_eq!(records[0].level, Level::Error);
+    assert_eq!(records[0].tid, Some(5678));
+    assert_eq!(records[0].message, "FATAL EXCEPTION: main\njava.lang.IllegalStateException: boom");
+    assert_eq!(records[1].message, "Sending signal. PID: 1234 SIG: 9");
+}
+
+#[test]
+fn headerless_lines_continue_the_previous_record() {
+    let text = "\
+leftover from before the capture
+08-12 10:15:00.123  1234  5678 E AndroidRuntime: java.lang.NullPointerException
+\tat com.example.Main.run(Main.java:42)
+\tat android.os.Handler.dispatchMessage(Handler.java:106)
+08-12 10:15:00.130  1234  1234 I Process : Sending signal
+";
+    let records = parse_log(text);
+    assert_eq!(records.len(), 3);
+    assert_eq!(records[0].level, Level::Unknown);
+    assert_eq!(records[0].message, "leftover from before the capture");
+    assert_eq!(records[1].message.lines().count(), 3);
+    assert!(records[1].message.ends_with("dispatchMessage(Handler.java:106)"));
+
+    // Streaming: a record is handed out once the next header shows it ended.
+    let mut parser = LogParser::new();
+    let mut lines = text.lines().skip(1);
+    assert_eq!(parser.push(lines.next().unwrap()), None);
+    assert_eq!(parser.push(lines.next().unwrap()), None);
+    assert_eq!(parser.push(lines.next().unwrap()), None);
+    assert_eq!(parser.push(lines.next().unwrap()).unwrap().tag, "AndroidRuntime");
+    assert_eq!(parser.finish().unwrap().tag, "Process");
+    assert_eq!(parser.finish(), None);
+}
+
+#[test]
+fn records_round_trip_through_display_and_json() {
+    let line = "08-12 10:15:00.123  1234  5678 I ActivityManager: Start proc";
+    let record = parse_line(line).unwrap();
+    assert_eq!(parse_line(&record.to_string()), Some(record.clone()));
+
+    let json = serde_json::to_string(&record).unwrap();
+    assert!(json.contains("\"time\":\"08-12 10:15:00.123\""));
+    assert!(json.contains("\"level\":\"I\""));
+    assert_eq!(serde_json::from_str::<monocore::logcat::LogRecord>(&json).unwrap(), record);
+
+    assert_eq!("warning".parse::<Level>(), Ok(Level::Warn));
+    assert!(Level::Error > Level::Warn);
+    assert!("loud".parse::<Level>().is_err());
+}
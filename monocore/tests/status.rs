@@ -0,0 +1,34 @@
+//! The daemon's status file, as written and read back.
+
+use monocore::status::DaemonStatus;
+
+#[test]
+fn status_round_trips_and_rejects_partial_files() {
+    let status = DaemonStatus {
+        pid: 4242,
+        build: "0.2.0 (1a2b3c4d5e6f)".to_string(),
+        server: "10.0.0.5:12345".to_string(),
+        started: 1_723_456_789,
+        updated: 1_723_460_389,
+        connected: true,
+        last_send: Some(1_723_460_388),
+        spool: 12,
+        dropped: 3,
+    };
+    let text = status.to_string();
+    assert!(text.starts_with("pid=4242\nbuild=0.2.0 (1a2b3c4d5e6f)\n"));
+    assert_eq!(DaemonStatus::parse(&text), Some(status.clone()));
+
+    // Nothing sent yet, and keys from a newer daemon are skipped.
+    let fresh = DaemonStatus {
+        connected: false,
+        last_send: None,
+        ..status
+    };
+    let text = format!("{}future=1\n", fresh);
+    assert!(!text.contains("last_send"));
+    assert_eq!(DaemonStatus::parse(&text), Some(fresh));
+
+    assert_eq!(DaemonStatus::parse("pid=4242\nstarted=1\n"), None);
+    assert_eq!(DaemonStatus::parse(""), None);
+}
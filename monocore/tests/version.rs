@@ -0,0 +1,40 @@
+//! Build info lines as embedded in daemon binaries.
+
+use monocore::version::BuildInfo;
+
+fn build(version: &str, git: &str) -> BuildInfo {
+    BuildInfo {
+        version: version.to_string(),
+        git: git.to_string(),
+    }
+}
+
+#[test]
+fn found_in_binaries_and_text() {
+    let info = build("0.2.0", "1a2b3c4d5e6f-dirty");
+    assert_eq!(info.line(), "MONODEAMON_BUILD version=0.2.0 git=1a2b3c4d5e6f-dirty;");
+    assert_eq!(info.to_string(), "0.2.0 (1a2b3c4d5e6f-dirty)");
+    assert_eq!("0.2.0 (1a2b3c4d5e6f-dirty)".parse(), Ok(info.clone()));
+    assert!("0.2.0".parse::<BuildInfo>().is_err());
+    assert!("0.2.0 (two words)".parse::<BuildInfo>().is_err());
+
+    // The marker on its own, as it would be if another string used it, is skipped.
+    let mut binary = b"\x7fELF\0\0MONODEAMON_BUILD \0garbage\0".to_vec();
+    binary.extend_from_slice(info.line().as_bytes());
+    binary.extend_from_slice(b"\0\x01\x02more");
+    assert_eq!(BuildInfo::find_in(&binary), Some(info.clone()));
+    assert_eq!(BuildInfo::parse(&format!("monodeamon {}\n", info.line())), Some(info));
+
+    assert_eq!(BuildInfo::find_in(b"\x7fELF no marker here"), None);
+    assert_eq!(BuildInfo::parse("MONODEAMON_BUILD version=0.1.0"), None);
+    assert_eq!(BuildInfo::parse("MONODEAMON_BUILD version= git=abc;"), None);
+}
+
+#[test]
+fn semver_orders_versions() {
+    assert_eq!(build("0.10.2", "x").semver(), Some((0, 10, 2)));
+    assert_eq!(build("1.0.0-rc.1", "x").semver(), Some((1, 0, 0)));
+    assert!(build("0.10.0", "x").semver() > build("0.9.9", "x").semver());
+    assert_eq!(build("1.0", "x").semver(), None);
+    assert_eq!(build("1.0.0.0", "x").semver(), None);
+}
@@ -0,0 +1,28 @@
+//! Embeds the commit the daemon is built from, for `monodeamon --version`
+//! and for `monocli upgrade` to read back out of the binary.
+
+use std::process::Command;
+
+fn git(args: &[&str]) -> Option<String> {
+    let output = Command::new("git").args(args).output().ok()?;
+    output
+        .status
+        .success()
+        .then(|| String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+fn main() {
+    let hash = git(&["rev-parse", "--short=12", "HEAD"]).unwrap_or_else(|| "unknown".to_string());
+    let dirty = git(&["status", "--porcelain", "--untracked-files=no"]).is_some_and(|status| !status.is_empty());
+    println!("cargo:rustc-env=MONO_GIT_HASH={}{}", hash, if dirty { "-dirty" } else { "" });
+
+    // Rebuilt on commits, checkouts and edits, which change the hash or the dirty flag.
+    println!("cargo:rerun-if-changed=src");
+    if let Some(git_dir) = git(&["rev-parse", "--absolute-git-dir"]) {
+        println!("cargo:rerun-if-changed={}/HEAD", git_dir);
+        println!("cargo:rerun-if-changed={}/index", git_dir);
+        if let Some(branch) = git(&["symbolic-ref", "-q", "HEAD"]) {
+            println!("cargo:rerun-if-changed={}/{}", git_dir, branch);
+        }
+    }
+}
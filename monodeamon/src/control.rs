@@ -0,0 +1,107 @@
+//! The control socket described in `monocore::control`, which monocli reaches
+//! through `adb forward`. Abstract sockets have no file permissions, so only
+//! root and adb's shell user are served: anyone else could read logs through
+//! `recent` or stop the daemon.
+
+use std::fs;
+use std::io::{self, BufRead, BufReader, Write};
+#[cfg(target_os = "android")]
+use std::os::android::net::SocketAddrExt;
+use std::os::fd::AsRawFd;
+#[cfg(target_os = "linux")]
+use std::os::linux::net::SocketAddrExt;
+use std::os::unix::net::{SocketAddr, UnixListener, UnixStream};
+use std::process;
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use monocore::control::{reply, Request, SOCKET_NAME};
+
+use crate::Daemon;
+
+/// uid of adbd, which connects on behalf of `adb forward`, unless it runs as root.
+const SHELL_UID: u32 = 2000;
+
+/// How long `stop` waits for the spool to be sent.
+const STOP_FLUSH: Duration = Duration::from_secs(5);
+
+/// How long `flush` waits for the spool to be sent.
+const FLUSH_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Listens on the control socket, serving each client on its own thread.
+pub fn serve(daemon: Arc<Daemon>) -> io::Result<()> {
+    let address = SocketAddr::from_abstract_name(SOCKET_NAME)?;
+    // Fails if another daemon holds the name
+    let listener = UnixListener::bind_addr(&address)?;
+    thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            let daemon = daemon.clone();
+            thread::spawn(move || {
+                if let Err(e) = handle(&daemon, stream) {
+                    eprintln!("Control request failed: {}", e);
+                }
+            });
+        }
+    });
+    Ok(())
+}
+
+fn handle(daemon: &Daemon, mut stream: UnixStream) -> io::Result<()> {
+    let uid = peer_uid(&stream)?;
+    if uid != 0 && uid != SHELL_UID {
+        return stream.write_all(reply(Err(format!("uid {} may not use this socket", uid))).as_bytes());
+    }
+
+    stream.set_read_timeout(Some(Duration::from_secs(5)))?;
+    let mut line = String::new();
+    BufReader::new(&stream).read_line(&mut line)?;
+    let Some(request) = Request::parse(&line) else {
+        return stream.write_all(reply(Err(format!("unknown request: {}", line.trim()))).as_bytes());
+    };
+
+    let result = match request {
+        Request::Status => Ok(daemon.status().to_string()),
+        Request::Reload => daemon.reload(),
+        Request::Flush => daemon.flush(FLUSH_TIMEOUT),
+        Request::Recent(count) => {
+            let state = daemon.spool.state();
+            let skip = state.recent.len().saturating_sub(count);
+            Ok(state.recent.iter().skip(skip).map(|line| format!("{}\n", line)).collect())
+        }
+        Request::Stop => {
+            let note = match daemon.flush(STOP_FLUSH) {
+                Ok(_) => "stopped".to_string(),
+                Err(e) => format!("stopped, {}", e),
+            };
+            stream.write_all(reply(Ok(note)).as_bytes())?;
+            eprintln!("monodeamon stopped through the control socket");
+            // A status file left behind would describe a daemon that is gone
+            if let Ok(path) = crate::status_path() {
+                let _ = fs::remove_file(path);
+            }
+            process::exit(0);
+        }
+    };
+    stream.write_all(reply(result).as_bytes())
+}
+
+/// uid of the process on the other end of `stream`.
+fn peer_uid(stream: &UnixStream) -> io::Result<u32> {
+    let mut credentials = libc::ucred { pid: 0, uid: 0, gid: 0 };
+    let mut length = std::mem::size_of::<libc::ucred>() as libc::socklen_t;
+    // SAFETY: the buffer and its length describe a valid ucred, as SO_PEERCRED expects.
+    let result = unsafe {
+        libc::getsockopt(
+            stream.as_raw_fd(),
+            libc::SOL_SOCKET,
+            libc::SO_PEERCRED,
+            &mut credentials as *mut libc::ucred as *mut libc::c_void,
+            &mut length,
+        )
+    };
+    if result != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(credentials.uid)
+}
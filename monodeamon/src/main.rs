@@ -1,47 +1,341 @@
-use std::process::{Command, Stdio};
+use std::collections::VecDeque;
+use std::env;
+use std::fs;
+use std::io::Write;
 use std::io::{BufRead, BufReader};
 use std::net::TcpStream;
-use std::io::Write;
-use std::time::Duration;
+use std::path::PathBuf;
+use std::process::{self, Command, Stdio};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
 use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use monocore::config::Config;
+use monocore::control::RECENT_LINES;
+use monocore::logcat::{self, Level};
+use monocore::status::{DaemonStatus, STATUS_FILE, STATUS_INTERVAL};
+use monocore::version::BuildInfo;
+
+// Abstract sockets are Linux-only; host builds for other systems go without.
+#[cfg(any(target_os = "android", target_os = "linux"))]
+mod control;
+
+/// This build, as a `monocore::version` line that `monocli upgrade` reads
+/// back out of the binary. `#[used]` keeps it in even if unreferenced.
+#[used]
+static BUILD: &str = concat!(
+    "MONODEAMON_BUILD version=",
+    env!("CARGO_PKG_VERSION"),
+    " git=",
+    env!("MONO_GIT_HASH"),
+    ";"
+);
 
 fn main() {
-    let server_address = "192.168.1.100:12345"; // Replace with your server IP and port
+    let build = BuildInfo::parse(BUILD).expect("BUILD is a build info line");
+    if env::args().skip(1).any(|arg| arg == "--version") {
+        println!("monodeamon {}", build);
+        return;
+    }
+    eprintln!("monodeamon {} starting", build);
 
-    // Start a thread to capture and send logs
-    thread::spawn(move || {
-        loop {
-            if let Err(e) = capture_and_send_logs(server_address) {
-                eprintln!("Error capturing logs: {}", e);
-                thread::sleep(Duration::from_secs(5));
-            }
+    let args: Vec<String> = env::args().skip(1).collect();
+    let settings = Settings::load(&args).unwrap_or_else(|e| {
+        eprintln!("Invalid configuration: {}", e);
+        process::exit(2);
+    });
+    let daemon = Arc::new(Daemon {
+        args,
+        build: build.to_string(),
+        started: now(),
+        spool: Spool::new(settings.spool),
+        settings: Mutex::new(settings),
+    });
+
+    // Start a thread to capture logs into the spool
+    let capturing = daemon.clone();
+    thread::spawn(move || loop {
+        if let Err(e) = capture_logs(&capturing) {
+            eprintln!("Error capturing logs: {}", e);
         }
+        thread::sleep(Duration::from_secs(5));
     });
 
-    // Keep the main thread alive
+    // One to report on the daemon for `monocli check`
+    let reporting = daemon.clone();
+    thread::spawn(move || loop {
+        if let Err(e) = write_status(&reporting.status()) {
+            eprintln!("Error writing status: {}", e);
+        }
+        thread::sleep(STATUS_INTERVAL);
+    });
+
+    // And the control socket monocli talks to through adb forward
+    #[cfg(any(target_os = "android", target_os = "linux"))]
+    if let Err(e) = control::serve(daemon.clone()) {
+        eprintln!("Control socket unavailable: {}", e);
+    }
+
+    // Send from the spool, reconnecting whenever the server goes away or a
+    // reload changes it; lines read in the meantime wait in the spool
     loop {
-        thread::sleep(Duration::from_secs(60));
+        let server_address = daemon.settings().server;
+        if let Err(e) = send_logs(&daemon, &server_address) {
+            eprintln!("Error sending logs: {}", e);
+            daemon.spool.state().connected = false;
+            thread::sleep(Duration::from_secs(5));
+        }
+    }
+}
+
+/// What the daemon runs with; `reload` reads them again.
+#[derive(Debug, Clone)]
+struct Settings {
+    server: String,
+    min_level: Level,
+    spool: usize,
+}
+
+impl Settings {
+    /// Settings come from mono.toml next to the binary (pushed by `monocli install`),
+    /// MONO_* env vars, or --server / --set on the command line
+    fn load(args: &[String]) -> Result<Self, String> {
+        let mut args = args.to_vec();
+        let config = Config::load(&mut args, &[("--server", "daemon.server")]).map_err(|e| e.to_string())?;
+        Ok(Settings {
+            server: config.get("daemon.server").to_string(),
+            min_level: config.parse("daemon.min_level").map_err(|e| e.to_string())?,
+            spool: config.parse("daemon.spool").map_err(|e| e.to_string())?,
+        })
     }
 }
 
-fn capture_and_send_logs(server_address: &str) -> Result<(), Box<dyn std::error::Error>> {
-    // Start adb logcat command to capture logs
-    let adb_process = Command::new("logcat")
+/// State shared by the daemon's threads.
+struct Daemon {
+    /// Command line, kept for `reload`.
+    args: Vec<String>,
+    build: String,
+    started: u64,
+    settings: Mutex<Settings>,
+    spool: Spool,
+}
+
+impl Daemon {
+    fn settings(&self) -> Settings {
+        lock(&self.settings).clone()
+    }
+
+    fn status(&self) -> DaemonStatus {
+        let server = self.settings().server;
+        let state = self.spool.state();
+        DaemonStatus {
+            pid: process::id(),
+            build: self.build.clone(),
+            server,
+            started: self.started,
+            updated: now(),
+            connected: state.connected,
+            last_send: state.last_send,
+            spool: state.waiting(),
+            dropped: state.dropped,
+        }
+    }
+
+    /// Reads the config again. A new server is connected to once the lines
+    /// already taken for the old one are sent.
+    fn reload(&self) -> Result<String, String> {
+        let settings = Settings::load(&self.args)?;
+        let note = format!("reloaded, sending to {} with a spool of {} lines", settings.server, settings.spool);
+        let mut state = self.spool.state();
+        state.capacity = settings.spool;
+        state.trim();
+        drop(state);
+        *lock(&self.settings) = settings;
+        Ok(note)
+    }
+
+    /// Waits up to `timeout` for the spool to be sent.
+    fn flush(&self, timeout: Duration) -> Result<String, String> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            let (connected, waiting) = {
+                let state = self.spool.state();
+                (state.connected, state.waiting())
+            };
+            if waiting == 0 {
+                return Ok("flushed".to_string());
+            }
+            if !connected {
+                return Err(format!("not connected to {}; {} lines waiting", self.settings().server, waiting));
+            }
+            if Instant::now() >= deadline {
+                return Err(format!("{} lines still waiting after {:?}", waiting, timeout));
+            }
+            thread::sleep(Duration::from_millis(100));
+        }
+    }
+}
+
+/// Logcat lines waiting to be sent, oldest first.
+struct Spool {
+    state: Mutex<SpoolState>,
+    ready: Condvar,
+}
+
+#[derive(Default)]
+struct SpoolState {
+    lines: VecDeque<String>,
+    capacity: usize,
+    /// Lines taken by the sender and not yet written.
+    sending: usize,
+    dropped: u64,
+    connected: bool,
+    last_send: Option<u64>,
+    /// The last lines read, sent or not, for `recent`.
+    recent: VecDeque<String>,
+}
+
+impl Spool {
+    fn new(capacity: usize) -> Self {
+        Spool {
+            state: Mutex::new(SpoolState {
+                capacity,
+                ..SpoolState::default()
+            }),
+            ready: Condvar::new(),
+        }
+    }
+
+    fn state(&self) -> MutexGuard<'_, SpoolState> {
+        lock(&self.state)
+    }
+
+    fn push(&self, line: String) {
+        let mut state = self.state();
+        if state.recent.len() == RECENT_LINES {
+            state.recent.pop_front();
+        }
+        state.recent.push_back(line.clone());
+        state.lines.push_back(line);
+        state.trim();
+        self.ready.notify_one();
+    }
+
+    /// Waits up to `timeout` for lines, then takes up to `max` of them.
+    fn take(&self, max: usize, timeout: Duration) -> Vec<String> {
+        let state = self.state();
+        let (mut state, _) = self
+            .ready
+            .wait_timeout_while(state, timeout, |state| state.lines.is_empty())
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        let count = state.lines.len().min(max);
+        state.sending = count;
+        state.lines.drain(..count).collect()
+    }
+
+    fn sent(&self) {
+        let mut state = self.state();
+        state.sending = 0;
+        state.last_send = Some(now());
+    }
+
+    /// Puts back lines that couldn't be sent, ahead of the newer ones.
+    fn put_back(&self, lines: Vec<String>) {
+        let mut state = self.state();
+        state.sending = 0;
+        for line in lines.into_iter().rev() {
+            state.lines.push_front(line);
+        }
+        state.trim();
+    }
+}
+
+impl SpoolState {
+    fn waiting(&self) -> usize {
+        self.lines.len() + self.sending
+    }
+
+    /// Drops the oldest lines beyond the capacity.
+    fn trim(&mut self) {
+        while self.lines.len() > self.capacity {
+            self.lines.pop_front();
+            self.dropped += 1;
+        }
+    }
+}
+
+/// Locks `mutex`, carrying on past a thread that panicked holding it.
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+fn capture_logs(daemon: &Daemon) -> Result<(), Box<dyn std::error::Error>> {
+    // Start logcat to capture logs, in the format monoserve indexes
+    let mut logcat_process = Command::new("logcat")
+        .args(["-v", "threadtime"])
         .stdout(Stdio::piped())
         .spawn()?;
 
-    let stdout = adb_process.stdout.ok_or("Failed to capture stdout")?;
+    let stdout = logcat_process.stdout.take().ok_or("Failed to capture stdout")?;
     let reader = BufReader::new(stdout);
 
+    // Spool the logs at or above min_level; headerless lines belong to the
+    // record before them
+    let mut forward = true;
+    for line in reader.lines() {
+        let line = line?;
+        if let Some(record) = logcat::parse_line(&line) {
+            forward = record.level >= lock(&daemon.settings).min_level;
+        }
+        if forward {
+            daemon.spool.push(line);
+        }
+    }
+
+    logcat_process.wait()?;
+    Err("logcat exited".into())
+}
+
+/// Sends from the spool until the connection fails, or until a reload
+/// points the daemon at another server.
+fn send_logs(daemon: &Daemon, server_address: &str) -> Result<(), Box<dyn std::error::Error>> {
     // Connect to the remote server
     let mut stream = TcpStream::connect(server_address)?;
+    stream.set_write_timeout(Some(Duration::from_secs(30)))?;
+    daemon.spool.state().connected = true;
 
-    // Read logs and send to the server
-    for line in reader.lines() {
-        let line = line?;
-        stream.write_all(line.as_bytes())?;
-        stream.write_all(b"\n")?;
+    while daemon.settings().server == server_address {
+        let lines = daemon.spool.take(1000, Duration::from_secs(1));
+        if lines.is_empty() {
+            continue;
+        }
+        let mut batch = lines.join("\n");
+        batch.push('\n');
+        if let Err(e) = stream.write_all(batch.as_bytes()) {
+            // Sent again after reconnecting; the server may see some twice
+            daemon.spool.put_back(lines);
+            return Err(e.into());
+        }
+        daemon.spool.sent();
     }
 
+    daemon.spool.state().connected = false;
     Ok(())
 }
+
+/// Replaces the status file next to the binary, via a temporary file so
+/// readers never see half of it.
+fn write_status(status: &DaemonStatus) -> std::io::Result<()> {
+    let path = status_path()?;
+    let temp = path.with_extension("status.tmp");
+    fs::write(&temp, status.to_string())?;
+    fs::rename(&temp, &path)
+}
+
+fn status_path() -> std::io::Result<PathBuf> {
+    Ok(env::current_exe()?.with_file_name(STATUS_FILE))
+}
+
+fn now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |elapsed| elapsed.as_secs())
+}
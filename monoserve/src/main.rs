@@ -1,16 +1,33 @@
 use std::net::{TcpListener, TcpStream};
 use std::io::{BufRead, BufReader, Write};
-use std::fs::OpenOptions;
+use std::fs::{File, OpenOptions};
+use std::path::PathBuf;
+use std::env;
+use std::process;
+
+use monocore::config::Config;
+use monocore::logcat::{LogParser, LogRecord};
 
 fn main() -> std::io::Result<()> {
-    let listener = TcpListener::bind("0.0.0.0:12345")?; // Replace with your desired port
+    let mut args: Vec<String> = env::args().skip(1).collect();
+    let config = Config::load(&mut args, &[("--bind", "server.bind"), ("--log-dir", "server.log_dir")])
+        .unwrap_or_else(|e| {
+            eprintln!("Invalid configuration: {}", e);
+            process::exit(2);
+        });
+    let bind_address = config.get("server.bind");
+    let log_dir = config.path("server.log_dir");
+    std::fs::create_dir_all(&log_dir)?;
+
+    let listener = TcpListener::bind(bind_address)?;
 
-    println!("Server listening on port 12345");
+    println!("Server listening on {}, writing logs to {}", bind_address, log_dir.display());
 
     for stream in listener.incoming() {
         match stream {
             Ok(stream) => {
-                std::thread::spawn(|| handle_client(stream));
+                let log_dir = log_dir.clone();
+                std::thread::spawn(move || handle_client(stream, log_dir));
             }
             Err(e) => {
                 eprintln!("Failed to accept connection: {}", e);
@@ -20,24 +37,27 @@ fn main() -> std::io::Result<()> {
     Ok(())
 }
 
-fn handle_client(stream: TcpStream) {
+fn handle_client(stream: TcpStream, log_dir: PathBuf) {
     let client_addr = stream.peer_addr().unwrap();
     println!("New connection from {}", client_addr);
 
     let reader = BufReader::new(stream);
 
-    // Open the log file for appending
-    let mut log_file = OpenOptions::new()
-        .create(true)
-        .append(true)
-        .open(format!("logs_{}.txt", client_addr))
-        .unwrap();
+    // Open the log files for appending: the raw lines, and one JSON record
+    // per log entry for filtering and indexing
+    let open = |name: String| OpenOptions::new().create(true).append(true).open(log_dir.join(name)).unwrap();
+    let mut log_file = open(format!("logs_{}.txt", client_addr));
+    let mut index_file = open(format!("logs_{}.jsonl", client_addr));
+    let mut parser = LogParser::new();
 
     // Write each line to the log file
     for line in reader.lines() {
         match line {
             Ok(log) => {
                 writeln!(log_file, "{}", log).unwrap();
+                if let Some(record) = parser.push(&log) {
+                    write_record(&mut index_file, &record);
+                }
             }
             Err(e) => {
                 eprintln!("Failed to read line: {}", e);
@@ -45,6 +65,14 @@ fn handle_client(stream: TcpStream) {
             }
         }
     }
+    if let Some(record) = parser.finish() {
+        write_record(&mut index_file, &record);
+    }
 
     println!("Connection from {} closed", client_addr);
 }
+
+fn write_record(index_file: &mut File, record: &LogRecord) {
+    let json = serde_json::to_string(record).unwrap();
+    writeln!(index_file, "{}", json).unwrap();
+}